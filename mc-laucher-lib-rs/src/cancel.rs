@@ -0,0 +1,115 @@
+use crate::expections::{LauncherLibError, LibResult};
+use std::process::Output;
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::watch;
+
+#[derive(Debug)]
+struct CancellationState {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+/// Handle used to stop a running install. Clones share the same state,
+/// so one clone can be kept to cancel while another is passed to the install.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self {
+            state: Arc::new(CancellationState { sender, receiver }),
+        }
+    }
+}
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        // the state holds a receiver, so this can't fail
+        let _ = self.state.sender.send(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.receiver.borrow()
+    }
+
+    /// Returns [`LauncherLibError::Cancelled`] once the token has been cancelled
+    pub fn check(&self) -> LibResult<()> {
+        if self.is_cancelled() {
+            return Err(LauncherLibError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Completes once the token is cancelled
+    pub async fn cancelled(&self) {
+        let mut receiver = self.state.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Runs `command` to completion, killing it if `cancel` fires first
+pub(crate) async fn run_command(command: &mut Command, cancel: &CancellationToken) -> LibResult<Output> {
+    cancel.check()?;
+
+    // dropping the output future when cancelled drops the child, which kills it
+    command.kill_on_drop(true);
+    tokio::select! {
+        output = command.output() => match output {
+            Ok(value) => Ok(value),
+            Err(err) => Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to run command".into(),
+            }),
+        },
+        _ = cancel.cancelled() => Err(LauncherLibError::Cancelled),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiters() {
+        let token = CancellationToken::new();
+        assert!(token.check().is_ok());
+
+        let waiter = token.clone();
+        let handle = tokio::spawn(async move { waiter.cancelled().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        token.cancel();
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(token.check(), Err(LauncherLibError::Cancelled)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_kills_on_cancel() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let result = run_command(Command::new("sleep").arg("30"), &token).await;
+        assert!(matches!(result, Err(LauncherLibError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,477 @@
+use crate::expections::{LauncherLibError, LibResult};
+use crate::gc::get_size;
+use crate::journal::open_journal;
+use crate::json::install::{JavaComponent, VersionManifest};
+use crate::utils::read_manifest;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Why an installed version can't be launched
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionProblem {
+    /// the directory has no `<id>.json`
+    MissingJson,
+    /// the json could not be read as a version manifest
+    InvalidJson(String),
+    /// a version in the parent chain is not installed
+    MissingParent(String),
+    /// the parent chain leads back to this version
+    InheritanceCycle,
+    /// the client jar of the base version is missing
+    MissingJar,
+}
+
+/// The loader an installed version was made by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstalledLoader {
+    Vanilla,
+    Forge,
+    NeoForge,
+    Fabric,
+    Quilt,
+    Optifine,
+    /// inherits from another version but matches no known loader
+    Custom,
+}
+
+/// A version found in the `versions` directory
+#[derive(Debug, Clone)]
+pub struct InstalledVersion {
+    pub id: String,
+    /// `None` when the json can't be read
+    pub loader: Option<InstalledLoader>,
+    pub release_type: Option<String>,
+    /// the versions this one inherits from, nearest first
+    pub parents: Vec<String>,
+    /// java the version asks for, from itself or the nearest parent that says
+    pub java: Option<JavaComponent>,
+    /// bytes taken by the version directory, libraries and assets are shared so not counted
+    pub size: u64,
+    pub problems: Vec<VersionProblem>,
+}
+impl InstalledVersion {
+    pub fn is_broken(&self) -> bool {
+        !self.problems.is_empty()
+    }
+}
+
+/// Works out the loader of a version from its json and the ones it inherits from, nearest first
+pub fn get_loader(manifest: &VersionManifest, parents: &[VersionManifest]) -> InstalledLoader {
+    let chain = || std::iter::once(manifest).chain(parents.iter());
+    let has_main_class = |main_class: &str| chain().any(|json| json.main_class == main_class);
+    let has_library = |prefix: &str| {
+        chain().any(|json| {
+            json.libraries
+                .iter()
+                .any(|library| library.name.starts_with(prefix))
+        })
+    };
+    if has_library("optifine:") {
+        InstalledLoader::Optifine
+    } else if has_main_class("org.quiltmc.loader.impl.launch.knot.KnotClient")
+        || has_library("org.quiltmc:quilt-loader")
+    {
+        InstalledLoader::Quilt
+    } else if has_main_class("net.fabricmc.loader.impl.launch.knot.KnotClient")
+        || has_main_class("net.fabricmc.loader.launch.knot.KnotClient")
+        || has_library("net.fabricmc:fabric-loader")
+    {
+        InstalledLoader::Fabric
+    } else if has_library("net.neoforged:neoforge")
+        || has_library("net.neoforged:forge")
+        || has_library("net.neoforged.fancymodloader:")
+        || has_library("net.neoforged:fmlloader")
+    {
+        InstalledLoader::NeoForge
+    } else if has_main_class("cpw.mods.modlauncher.Launcher")
+        || has_library("net.minecraftforge:forge")
+        || has_library("net.minecraftforge:fmlloader")
+    {
+        InstalledLoader::Forge
+    } else if manifest.inherits_from.is_some() {
+        InstalledLoader::Custom
+    } else {
+        InstalledLoader::Vanilla
+    }
+}
+
+fn get_json_path(mc_dir: &Path, id: &str) -> PathBuf {
+    mc_dir
+        .join("versions")
+        .join(id)
+        .join(format!("{}.json", id))
+}
+
+/// An id is a single directory name, so it can't lead outside the `versions` directory
+fn check_id(id: &str) -> LibResult<()> {
+    let mut components = Path::new(id).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) if name == id => Ok(()),
+        _ => Err(LauncherLibError::InvalidVersionId(id.to_string())),
+    }
+}
+
+/// `inheritsFrom` read leniently, so it is known even for jsons that aren't valid manifests
+fn get_parent(mc_dir: &Path, id: &str) -> Option<String> {
+    let raw = std::fs::read_to_string(get_json_path(mc_dir, id)).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&raw).ok()?;
+    value
+        .get("inheritsFrom")
+        .and_then(|parent| parent.as_str())
+        .map(|parent| parent.to_string())
+}
+
+/// Describes the installed version `id`
+pub async fn get_installed_version(mc_dir: &Path, id: &str) -> LibResult<InstalledVersion> {
+    check_id(id)?;
+    let dir = mc_dir.join("versions").join(id);
+    if !dir.is_dir() {
+        return Err(LauncherLibError::NotFound(id.to_string()));
+    }
+
+    let mut version = InstalledVersion {
+        id: id.to_string(),
+        loader: None,
+        release_type: None,
+        parents: vec![],
+        java: None,
+        size: get_size(&dir),
+        problems: vec![],
+    };
+
+    let json = get_json_path(mc_dir, id);
+    if !json.is_file() {
+        version.problems.push(VersionProblem::MissingJson);
+        return Ok(version);
+    }
+    let own = match read_manifest(json).await {
+        Ok(value) => Some(value),
+        Err(err) => {
+            version
+                .problems
+                .push(VersionProblem::InvalidJson(err.to_string()));
+            None
+        }
+    };
+    if let Some(own) = &own {
+        version.release_type = own.release_type.clone();
+        version.java = own.java_version.clone();
+    }
+    let mut chain = vec![];
+    let mut manifest = own.clone();
+
+    let mut seen = HashSet::from([id.to_string()]);
+    let mut current = id.to_string();
+    let mut is_complete = true;
+    while let Some(parent) = get_parent(mc_dir, &current) {
+        if !seen.insert(parent.clone()) {
+            version.problems.push(VersionProblem::InheritanceCycle);
+            is_complete = false;
+            break;
+        }
+        version.parents.push(parent.clone());
+        if !get_json_path(mc_dir, &parent).is_file() {
+            version.problems.push(VersionProblem::MissingParent(parent));
+            is_complete = false;
+            break;
+        }
+        manifest = read_manifest(get_json_path(mc_dir, &parent)).await.ok();
+        if let Some(parent) = &manifest {
+            if version.java.is_none() {
+                version.java = parent.java_version.clone();
+            }
+            chain.push(parent.clone());
+        }
+        current = parent;
+    }
+    if let Some(own) = &own {
+        version.loader = Some(get_loader(own, &chain));
+    }
+
+    if !is_complete {
+        return Ok(version);
+    }
+
+    // the base of the chain is the one with the client jar
+    if let Some(base) = &manifest {
+        let has_client = base
+            .downloads
+            .as_ref()
+            .map(|downloads| downloads.contains_key("client"))
+            .unwrap_or(false);
+        let jar = mc_dir
+            .join("versions")
+            .join(&current)
+            .join(format!("{}.jar", current));
+        if has_client && !jar.is_file() {
+            version.problems.push(VersionProblem::MissingJar);
+        }
+    }
+
+    Ok(version)
+}
+
+/// Every directory in `versions`, broken ones included, sorted by id
+pub async fn list_installed_versions(mc_dir: &Path) -> LibResult<Vec<InstalledVersion>> {
+    let entries = match std::fs::read_dir(mc_dir.join("versions")) {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to read directory".into(),
+            })
+        }
+    };
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    ids.sort();
+
+    let mut versions = vec![];
+    for id in ids {
+        match get_installed_version(mc_dir, &id).await {
+            Ok(value) => versions.push(value),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(versions)
+}
+
+/// Deletes the version directory and returns the bytes freed. Refuses with
+/// [`LauncherLibError::VersionInUse`] when another version inherits from it.
+/// Libraries and assets stay, `gc::plan_gc` finds the ones no longer used.
+pub async fn remove_version(mc_dir: &Path, id: &str) -> LibResult<u64> {
+    check_id(id)?;
+    let dir = mc_dir.join("versions").join(id);
+    if !dir.is_dir() {
+        return Err(LauncherLibError::NotFound(id.to_string()));
+    }
+
+    let entries = match std::fs::read_dir(mc_dir.join("versions")) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to read directory".into(),
+            })
+        }
+    };
+    let mut dependents: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|other| other != id && get_parent(mc_dir, other).as_deref() == Some(id))
+        .collect();
+    if !dependents.is_empty() {
+        dependents.sort();
+        return Err(LauncherLibError::VersionInUse {
+            id: id.to_string(),
+            dependents,
+        });
+    }
+
+    let size = get_size(&dir);
+    if let Err(err) = std::fs::remove_dir_all(&dir) {
+        return Err(LauncherLibError::OS {
+            source: err,
+            msg: format!("Failed to remove version {}", id),
+        });
+    }
+
+    // installing it again must not skip steps
+    let journal = open_journal(mc_dir);
+    journal.forget_dir(&dir);
+    journal.reset_version(id)?;
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{version_json as version, write_version};
+
+    #[test]
+    fn test_get_loader() {
+        let manifest = |id: &str, main_class: &str, libraries: &[&str], parent: Option<&str>| {
+            serde_json::from_value::<VersionManifest>(version(id, main_class, libraries, parent))
+                .unwrap()
+        };
+        let vanilla = manifest("1.20.1", "net.minecraft.client.main.Main", &[], None);
+        let neoforge = manifest(
+            "neoforge-20.4.80",
+            "cpw.mods.modlauncher.Launcher",
+            &["net.neoforged.fancymodloader:loader:2.0.7"],
+            Some("1.20.1"),
+        );
+        let quilt = manifest(
+            "quilt-loader-0.19.2-1.20.1",
+            "org.quiltmc.loader.impl.launch.knot.KnotClient",
+            &["org.quiltmc:quilt-loader:0.19.2"],
+            Some("1.20.1"),
+        );
+        let forge = manifest(
+            "1.20.1-forge-47.1.0",
+            "cpw.mods.modlauncher.Launcher",
+            &["net.minecraftforge:fmlloader:1.20.1-47.1.0"],
+            Some("1.20.1"),
+        );
+        // a profile made by hand on top of a loader, naming none of its libraries
+        let profile = manifest(
+            "my-pack",
+            "net.minecraft.client.main.Main",
+            &[],
+            Some("1.20.1-forge-47.1.0"),
+        );
+        let custom = manifest(
+            "my-pack",
+            "net.minecraft.client.main.Main",
+            &[],
+            Some("1.20.1"),
+        );
+
+        assert_eq!(get_loader(&vanilla, &[]), InstalledLoader::Vanilla);
+        assert_eq!(
+            get_loader(&neoforge, std::slice::from_ref(&vanilla)),
+            InstalledLoader::NeoForge
+        );
+        assert_eq!(
+            get_loader(&quilt, std::slice::from_ref(&vanilla)),
+            InstalledLoader::Quilt
+        );
+        assert_eq!(
+            get_loader(&profile, &[forge, vanilla.clone()]),
+            InstalledLoader::Forge
+        );
+        assert_eq!(get_loader(&custom, &[vanilla]), InstalledLoader::Custom);
+        // the parent is not installed, yet the json says it is not vanilla
+        assert_eq!(get_loader(&custom, &[]), InstalledLoader::Custom);
+    }
+
+    #[tokio::test]
+    async fn test_catalogue_classifies_and_protects_parents() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-catalogue-{}", uuid::Uuid::new_v4()));
+        let mut vanilla: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("tests/fixtures/install/fixture-1.0.json").unwrap(),
+        )
+        .unwrap();
+        vanilla["javaVersion"] =
+            serde_json::json!({ "component": "java-runtime-gamma", "majorVersion": 17 });
+        write_version(&mc_dir, vanilla);
+        write_version(
+            &mc_dir,
+            version(
+                "fixture-1.0-forge-1",
+                "cpw.mods.modlauncher.Launcher",
+                &["net.minecraftforge:fmlloader:1.0-1"],
+                Some("fixture-1.0"),
+            ),
+        );
+        write_version(
+            &mc_dir,
+            version(
+                "fabric-loader-0.14-fixture-1.0",
+                "net.fabricmc.loader.impl.launch.knot.KnotClient",
+                &["net.fabricmc:fabric-loader:0.14"],
+                Some("fixture-1.0"),
+            ),
+        );
+        write_version(
+            &mc_dir,
+            version(
+                "orphan",
+                "net.minecraft.client.main.Main",
+                &[],
+                Some("1.7.10"),
+            ),
+        );
+        std::fs::create_dir_all(mc_dir.join("versions").join("empty")).unwrap();
+
+        let versions = list_installed_versions(&mc_dir).await.unwrap();
+        let get = |id: &str| versions.iter().find(|version| version.id == id).unwrap();
+        assert_eq!(versions.len(), 5);
+
+        let vanilla = get("fixture-1.0");
+        assert_eq!(vanilla.loader, Some(InstalledLoader::Vanilla));
+        assert_eq!(vanilla.problems, vec![VersionProblem::MissingJar]);
+
+        let forge = get("fixture-1.0-forge-1");
+        assert_eq!(forge.loader, Some(InstalledLoader::Forge));
+        assert_eq!(forge.parents, vec!["fixture-1.0".to_string()]);
+        let java = forge.java.as_ref().unwrap();
+        assert_eq!(java.major_version, 17);
+        assert_eq!(java.component.to_string(), "java-runtime-gamma");
+        assert!(forge.size > 0);
+
+        assert_eq!(
+            get("fabric-loader-0.14-fixture-1.0").loader,
+            Some(InstalledLoader::Fabric)
+        );
+        let orphan = get("orphan");
+        assert_eq!(
+            orphan.problems,
+            vec![VersionProblem::MissingParent("1.7.10".into())]
+        );
+        assert_eq!(orphan.loader, Some(InstalledLoader::Custom));
+        assert_eq!(get("empty").problems, vec![VersionProblem::MissingJson]);
+
+        match remove_version(&mc_dir, "fixture-1.0").await {
+            Err(LauncherLibError::VersionInUse { dependents, .. }) => assert_eq!(
+                dependents,
+                vec![
+                    "fabric-loader-0.14-fixture-1.0".to_string(),
+                    "fixture-1.0-forge-1".to_string()
+                ]
+            ),
+            other => panic!("{:?}", other),
+        }
+
+        assert!(
+            remove_version(&mc_dir, "fixture-1.0-forge-1")
+                .await
+                .unwrap()
+                > 0
+        );
+        assert!(remove_version(&mc_dir, "fabric-loader-0.14-fixture-1.0")
+            .await
+            .is_ok());
+        assert!(remove_version(&mc_dir, "fixture-1.0").await.is_ok());
+        assert!(!mc_dir.join("versions").join("fixture-1.0").exists());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_ids_outside_the_versions_directory_are_rejected() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-catalogue-{}", uuid::Uuid::new_v4()));
+        write_version(
+            &mc_dir,
+            version("a", "net.minecraft.client.main.Main", &[], None),
+        );
+        std::fs::create_dir_all(mc_dir.join("versions").join("a").join("b")).unwrap();
+
+        for id in ["..", ".", "a/b", "", "../versions"] {
+            assert!(
+                matches!(
+                    remove_version(&mc_dir, id).await,
+                    Err(LauncherLibError::InvalidVersionId(_))
+                ),
+                "{}",
+                id
+            );
+            assert!(
+                matches!(
+                    get_installed_version(&mc_dir, id).await,
+                    Err(LauncherLibError::InvalidVersionId(_))
+                ),
+                "{}",
+                id
+            );
+        }
+        assert!(mc_dir.join("versions").join("a").join("b").is_dir());
+        assert!(get_installed_version(&mc_dir, "a").await.is_ok());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
@@ -1,8 +1,10 @@
+use crate::cancel::CancellationToken;
 use crate::command::get_launch_command;
+use crate::crash::{analyze_crash, CrashAnalysis};
 use crate::expections::{LauncherLibError, LibResult};
 use crate::fabric::install_fabric;
 use crate::forge::install_forge;
-use crate::install::install_minecraft_version;
+use crate::install::{install_minecraft_version, is_version_installed};
 use crate::json::{
     authentication_microsoft::Account,
     client::{InstallManifest, Loader},
@@ -11,9 +13,11 @@ use crate::json::{
 };
 use crate::mod_utiles::install_mods;
 use crate::optifine::install_optifine;
+use crate::preflight::{preflight, PreflightReport};
+use crate::process::{GameEvent, GameProcess, DEFAULT_STOP_TIMEOUT};
 use crate::utils::get_minecraft_directory;
 use std::path::PathBuf;
-use std::process::{Child, Command};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Debug, Default)]
@@ -21,7 +25,7 @@ pub struct Client {
     options: GameOptions,
     minecraft: String,
     minecraft_directory: PathBuf,
-    process: Option<Child>,
+    process: Option<GameProcess>,
 }
 impl Client {
     pub fn new(minecraft: String, minecraft_directory: PathBuf, options: GameOptions) -> Self {
@@ -35,19 +39,16 @@ impl Client {
     pub fn is_running(&mut self) -> LibResult<bool> {
         if let Some(p) = &mut self.process {
             match p.try_wait() {
-                Ok(Some(_status)) => {
-                    self.process = None;
-                    Ok(false)
-                }
+                Ok(Some(_code)) => Ok(false),
                 Ok(None) => Ok(true),
-                Err(err) => Err(LauncherLibError::General(err.to_string())),
+                Err(err) => Err(err),
             }
         } else {
             Ok(false)
         }
     }
     pub async fn start(&mut self) -> LibResult<()> {
-        if self.process.is_some() {
+        if let Ok(true) = self.is_running() {
             return Err(LauncherLibError::General(
                 "A instance of minecraft is already running".into(),
             ));
@@ -64,38 +65,80 @@ impl Client {
             Err(err) => return Err(err),
         };
 
-        let handler: Child = match Command::new(java).args(args).spawn() {
-            Err(err) => {
-                return Err(LauncherLibError::OS {
-                    source: err,
-                    msg: "Failed to launch minecraft".into(),
-                })
-            }
-            Ok(handle) => handle,
+        let handler = match GameProcess::spawn(&java, &args, self.options.enable_logging_config) {
+            Ok(value) => value,
+            Err(err) => return Err(err),
         };
 
         self.process = Some(handler);
 
         Ok(())
     }
-    pub fn exit(&mut self) -> LibResult<()> {
-        if let Some(process) = &mut self.process {
-            match process.wait() {
-                Err(err) => {
-                    return Err(LauncherLibError::OS {
-                        source: err,
-                        msg: "Minecraft was not running".into(),
-                    })
-                }
-                Ok(_) => {
-                    self.process = None;
-                }
-            }
+    /// The next line or log record of the game, [`GameEvent::Exited`] with the exit code
+    /// once it has closed and `None` after that or when it was never started
+    pub async fn next_event(&mut self) -> Option<GameEvent> {
+        match &mut self.process {
+            Some(process) => process.next_event().await,
+            None => None,
+        }
+    }
+    /// Checks the game can start without starting it, see [`preflight`]
+    pub async fn preflight(&self, check_hashes: bool) -> LibResult<PreflightReport> {
+        preflight(
+            self.minecraft.clone(),
+            self.minecraft_directory.clone(),
+            &mut self.options.clone(),
+            check_hashes,
+        )
+        .await
+    }
+    /// Reads the newest crash report and log of the game directory, see [`analyze_crash`]
+    pub fn analyze_crash(&self) -> CrashAnalysis {
+        match &self.options.game_directory {
+            Some(dir) => analyze_crash(dir),
+            None => analyze_crash(&self.minecraft_directory),
+        }
+    }
+    /// Asks the game to close and kills it after the timeout, giving the exit code
+    pub async fn stop(&mut self, timeout: Duration) -> LibResult<Option<i32>> {
+        match &mut self.process {
+            Some(process) => process.stop(timeout).await,
+            None => Err(LauncherLibError::General(
+                "Minecraft was not running".into(),
+            )),
+        }
+    }
+    pub async fn kill(&mut self) -> LibResult<Option<i32>> {
+        match &mut self.process {
+            Some(process) => process.kill().await,
+            None => Err(LauncherLibError::General(
+                "Minecraft was not running".into(),
+            )),
+        }
+    }
+    /// Closes the game, killing it if it hasn't closed after [`DEFAULT_STOP_TIMEOUT`]
+    pub async fn exit(&mut self) -> LibResult<()> {
+        if self.process.is_some() {
+            self.stop(DEFAULT_STOP_TIMEOUT).await?;
+            self.process = None;
         }
         Ok(())
     }
 }
 
+/// Where and with what [`ClientBuilder::install`] installs
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    /// defaults to the standard minecraft directory
+    pub minecraft_directory: Option<PathBuf>,
+    /// forge is not installed again when this version is already installed
+    pub desired_forge_version: Option<String>,
+    /// needed by the fabric, forge and optifine installers
+    pub temp_path: Option<PathBuf>,
+    pub cache_path: Option<PathBuf>,
+    pub java: Option<PathBuf>,
+}
+
 #[derive(Debug, Default)]
 pub struct ClientBuilder {
     options: GameOptions,
@@ -106,13 +149,17 @@ pub struct ClientBuilder {
 impl ClientBuilder {
     pub async fn install(
         manifest: InstallManifest,
-        minecraft_directory: Option<PathBuf>,
-        callback: Callback,
-        desired_forge_version: Option<&str>,
-        temp_path: Option<PathBuf>,
-        cache_path: Option<PathBuf>,
-        java: Option<PathBuf>,
+        options: InstallOptions,
+        callback: Callback<'_>,
+        cancel: &CancellationToken,
     ) -> LibResult<()> {
+        let InstallOptions {
+            minecraft_directory,
+            desired_forge_version,
+            temp_path,
+            cache_path,
+            java,
+        } = options;
         let mc_dir = if let Some(dir) = minecraft_directory {
             dir
         } else {
@@ -133,6 +180,7 @@ impl ClientBuilder {
                     mc_dir,
                     manifest.modloader_version,
                     callback,
+                    cancel,
                     java,
                     temp,
                 )
@@ -143,18 +191,21 @@ impl ClientBuilder {
                     Some(value) => value,
                     None => return Err(LauncherLibError::General("Missing temp path".into())),
                 };
-                if !mc_dir
-                    .clone()
-                    .join("versions")
-                    .join(desired_forge_version.unwrap())
-                    .join(format!("{}.json", desired_forge_version.unwrap()))
-                    .is_file()
-                {
+                let forge_id = match desired_forge_version {
+                    Some(value) => value,
+                    None => {
+                        return Err(LauncherLibError::General(
+                            "Missing desired forge version".into(),
+                        ))
+                    }
+                };
+                if !is_version_installed(&forge_id, &mc_dir) {
                     install_forge(
                         manifest.minecraft.clone(),
                         mc_dir.clone(),
                         temp.clone(),
                         callback,
+                        cancel,
                         cache_path.clone(),
                         manifest.modloader_version,
                         java,
@@ -163,7 +214,7 @@ impl ClientBuilder {
                     )
                     .await?;
                 }
-                install_mods(mc_dir, callback).await
+                install_mods(mc_dir, callback, cancel).await
             }
             Loader::Optifine => {
                 let temp = match temp_path {
@@ -175,6 +226,7 @@ impl ClientBuilder {
                     mc_dir,
                     temp,
                     callback,
+                    cancel,
                     cache_path,
                     manifest.modloader_version,
                     java,
@@ -184,32 +236,20 @@ impl ClientBuilder {
                 .await
             }
             Loader::Vanilla => {
-                install_minecraft_version(manifest.minecraft.clone(), mc_dir, callback).await
+                install_minecraft_version(manifest.minecraft.clone(), mc_dir, callback, cancel)
+                    .await
             }
         }
     }
     pub async fn install_str(
         manifest: String,
-        minecraft_directory: Option<PathBuf>,
-        callback: Callback,
-        temp_path: Option<PathBuf>,
-        cache_path: Option<PathBuf>,
-        java: Option<PathBuf>,
+        options: InstallOptions,
+        callback: Callback<'_>,
+        cancel: &CancellationToken,
     ) -> LibResult<()> {
         match serde_json::from_str::<InstallManifest>(&manifest) {
             Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
-            Ok(value) => {
-                ClientBuilder::install(
-                    value,
-                    minecraft_directory,
-                    callback,
-                    None,
-                    temp_path,
-                    cache_path,
-                    java,
-                )
-                .await
-            }
+            Ok(value) => ClientBuilder::install(value, options, callback, cancel).await,
         }
     }
     pub fn new(minecraft_dir: Option<PathBuf>) -> LibResult<Self> {
@@ -239,6 +279,8 @@ impl ClientBuilder {
     pub fn as_dev_user(&mut self) -> &mut Self {
         self.options.uuid = Some(Uuid::new_v4().to_hyphenated().to_string());
         self.options.username = Some("Rusty".into());
+        // offline sessions have no token, the game accepts any value
+        self.options.token = Some("0".into());
         self.options.user_type = UserType::Unkown;
         self
     }
@@ -263,6 +305,36 @@ impl ClientBuilder {
         self.options.client_id = Some(id);
         self
     }
+    fn clear_quick_play(&mut self) {
+        self.options.server = None;
+        self.options.port = None;
+        self.options.quick_play_singleplayer = None;
+        self.options.quick_play_realms = None;
+    }
+    /// joins the server on launch, with Quick Play on versions that have it and `--server` on older ones
+    pub fn join_server(&mut self, host: String, port: Option<u16>) -> &mut Self {
+        self.clear_quick_play();
+        self.options.server = Some(host);
+        self.options.port = port.map(|port| port.to_string());
+        self
+    }
+    /// opens the singleplayer world on launch, starting fails on versions without Quick Play
+    pub fn open_world(&mut self, world: String) -> &mut Self {
+        self.clear_quick_play();
+        self.options.quick_play_singleplayer = Some(world);
+        self
+    }
+    /// joins the realm on launch, starting fails on versions without Quick Play
+    pub fn join_realm(&mut self, realm_id: String) -> &mut Self {
+        self.clear_quick_play();
+        self.options.quick_play_realms = Some(realm_id);
+        self
+    }
+    /// file the game logs the Quick Play session to, relative to the game directory
+    pub fn set_quick_play_log(&mut self, path: PathBuf) -> &mut Self {
+        self.options.quick_play_path = Some(path);
+        self
+    }
     pub fn enable_logging(&mut self) -> &mut Self {
         self.options.enable_logging_config = true;
 
@@ -332,83 +404,185 @@ impl ClientBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::{fixture_context, with_context};
+    use crate::json::install::Event;
+    use std::path::Path;
     use std::thread;
     use std::time;
 
+    /// Stands in for java running a loader installer, leaving the version json the installer writes
+    #[cfg(unix)]
+    const FAKE_INSTALLER: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+    last=$1
+    case "$1" in
+        --installer) id=$(basename "$2" .jar); parent=${id%%-forge-*}; shift ;;
+        --target|-dir) dir=$2; shift ;;
+        -mcversion) parent=$2; shift ;;
+        -loader) loader=$2; shift ;;
+        *-OptiFine_*.jar) id=$(basename "$1" .jar); parent=${id%%-OptiFine_*} ;;
+    esac
+    shift
+done
+[ -n "$loader" ] && id="fabric-loader-$loader-$parent"
+dir=${dir:-$last}
+mkdir -p "$dir/versions/$id"
+printf '{"id":"%s","inheritsFrom":"%s","mainClass":"net.minecraft.client.main.Main","libraries":[],"arguments":{"game":[]},"releaseTime":"2022-01-01T00:00:00+00:00","time":"2022-01-01T00:00:00+00:00","type":"release"}' "$id" "$parent" > "$dir/versions/$id/$id.json"
+"#;
+
+    /// A fresh minecraft directory next to a temp directory and the fake installer java
+    fn setup() -> (PathBuf, InstallOptions) {
+        let root = std::env::temp_dir().join(format!("mc-client-{}", Uuid::new_v4()));
+        let mc_dir = root.join(".minecraft");
+        let temp = root.join("temp");
+        std::fs::create_dir_all(&temp).unwrap();
+
+        #[cfg(unix)]
+        let java = {
+            use std::os::unix::fs::PermissionsExt;
+            let java = root.join("java");
+            std::fs::write(&java, FAKE_INSTALLER).unwrap();
+            std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+            Some(java)
+        };
+        #[cfg(not(unix))]
+        let java = None;
+
+        let options = InstallOptions {
+            minecraft_directory: Some(mc_dir.clone()),
+            temp_path: Some(temp),
+            java,
+            ..Default::default()
+        };
+        (mc_dir, options)
+    }
+
+    async fn install(manifest: InstallManifest, options: InstallOptions) -> LibResult<()> {
+        with_context(
+            fixture_context("client"),
+            ClientBuilder::install(
+                manifest,
+                options,
+                &|_event: Event| {},
+                &CancellationToken::new(),
+            ),
+        )
+        .await
+    }
+
+    fn version_file(mc_dir: &Path, id: &str, ext: &str) -> PathBuf {
+        mc_dir
+            .join("versions")
+            .join(id)
+            .join(format!("{}.{}", id, ext))
+    }
+
+    #[cfg(unix)]
     #[tokio::test]
     async fn test_optifine_install() {
-        if let Err(err) = ClientBuilder::install(
+        let (mc_dir, options) = setup();
+        let temp = options.temp_path.clone().unwrap();
+
+        let result = install(
             InstallManifest::new("1.18.1".into(), Loader::Optifine),
-            None,
-            |event| {
-                println!("{:#?}", event);
-            },
-            None,
-            Some(PathBuf::from("C:\\Users\\Collin\\Downloads\\")),
-            None,
-            None,
+            options,
         )
-        .await
-        {
-            eprintln!("{}", err);
-        }
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        assert!(version_file(&mc_dir, "1.18.1", "jar").is_file());
+        let optifine =
+            std::fs::read_to_string(version_file(&mc_dir, "1.18.1-OptiFine_HD_U_H4", "json"))
+                .unwrap();
+        assert!(optifine.contains(r#""inheritsFrom":"1.18.1""#));
+        // the installer jars are not cached by default
+        assert_eq!(std::fs::read_dir(temp).unwrap().count(), 0);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
     async fn test_forge_install() {
-        if let Err(err) = ClientBuilder::install(
+        let (mc_dir, mut options) = setup();
+        options.desired_forge_version = Some("1.18.1-forge-39.0.75".into());
+        let temp = options.temp_path.clone().unwrap();
+
+        let result = install(
             InstallManifest::new("1.18.1".into(), Loader::Forge),
-            None,
-            |event| {
-                println!("{:#?}", event);
-            },
-            None,
-            Some(PathBuf::from("C:\\Users\\Collin\\Downloads\\")),
-            None,
-            None,
+            options,
         )
-        .await
-        {
-            eprintln!("{}", err);
-        }
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        assert!(version_file(&mc_dir, "1.18.1", "jar").is_file());
+        let forge =
+            std::fs::read_to_string(version_file(&mc_dir, "1.18.1-forge-39.0.75", "json")).unwrap();
+        assert!(forge.contains(r#""inheritsFrom":"1.18.1""#));
+        assert!(mc_dir
+            .join("mods")
+            .join("flywheel-forge-1.18-0.6.4.jar")
+            .is_file());
+        assert!(mc_dir
+            .join("mods")
+            .join("create-mc1.18.2_v0.5.0c.jar")
+            .is_file());
+        assert_eq!(std::fs::read_dir(temp).unwrap().count(), 0);
     }
 
+    #[tokio::test]
+    async fn test_forge_install_needs_desired_version() {
+        let (mc_dir, options) = setup();
+
+        let result = install(
+            InstallManifest::new("1.18.1".into(), Loader::Forge),
+            options,
+        )
+        .await;
+        assert!(matches!(result, Err(LauncherLibError::General(_))));
+        assert!(!mc_dir.exists());
+    }
+
+    #[cfg(unix)]
     #[tokio::test]
     async fn test_fabric_install() {
-        if let Err(err) = ClientBuilder::install(
+        let (mc_dir, options) = setup();
+        let temp = options.temp_path.clone().unwrap();
+
+        let result = install(
             InstallManifest::new("1.18.1".into(), Loader::Fabric),
-            None,
-            |event| {
-                println!("{:#?}", event);
-            },
-            None,
-            Some(PathBuf::from("C:\\Users\\Collin\\Downloads\\")),
-            None,
-            None,
+            options,
         )
-        .await
-        {
-            eprintln!("{}", err);
-        }
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        assert!(version_file(&mc_dir, "1.18.1", "jar").is_file());
+        let fabric = std::fs::read_to_string(version_file(
+            &mc_dir,
+            "fabric-loader-0.12.12-1.18.1",
+            "json",
+        ))
+        .unwrap();
+        assert!(fabric.contains(r#""inheritsFrom":"1.18.1""#));
+        assert_eq!(std::fs::read_dir(temp).unwrap().count(), 0);
     }
 
     #[tokio::test]
     async fn test_vinilla_install() {
-        if let Err(err) = ClientBuilder::install(
-            InstallManifest::new("1.17.1".into(), Loader::Vanilla),
-            None,
-            |event| {
-                println!("{:#?}", event);
-            },
-            None,
-            None,
-            None,
-            None,
+        let (mc_dir, options) = setup();
+
+        let result = install(
+            InstallManifest::new("1.18.1".into(), Loader::Vanilla),
+            options,
         )
-        .await
-        {
-            eprintln!("{}", err);
-        }
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        assert!(version_file(&mc_dir, "1.18.1", "json").is_file());
+        assert!(version_file(&mc_dir, "1.18.1", "jar").is_file());
+        assert!(mc_dir
+            .join("assets")
+            .join("indexes")
+            .join("fixture.json")
+            .is_file());
     }
 
     #[tokio::test]
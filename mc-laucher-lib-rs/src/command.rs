@@ -1,7 +1,10 @@
 use crate::utils::{parse_rule_list,get_classpath_separator, read_manifest_inherit};
 use crate::runtime::{get_exectable_path};
+use crate::install::{ get_library_files, prepare_legacy_assets };
 use crate::expections::{LauncherLibError,LibResult};
-use crate::natives::{get_library_data, get_natives};
+use crate::template::{ split_args, Variable, Variables };
+use crate::rules::{ has_feature_rule, is_allowed, RuleEnvironment, FEATURE_QUICK_PLAY_MULTIPLAYER };
+use crate::natives::extract_natives;
 use crate::json::{
     game_settings::GameOptions,
     install::{ 
@@ -10,7 +13,7 @@ use crate::json::{
         Argument
     }
 };
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 
 fn get_libraries_string(libs: &Vec<Library>, jar: Option<String>, id: String, path: PathBuf)-> LibResult<String> {
     let seperator = get_classpath_separator();
@@ -26,23 +29,16 @@ fn get_libraries_string(libs: &Vec<Library>, jar: Option<String>, id: String, pa
 
         let mut current_path = path.join("libraries");
 
-        let (lib_path,name,version) = match get_library_data(i.name.clone()) {
-            Ok(value) => value,
-            Err(err) => return Err(err)
-        };
+        let (parts,jar_filename,jar_filename_native) = get_library_files(i)?;
 
-        for lib_part in lib_path.split(".").collect::<Vec<&str>>() {
-            current_path = current_path.join(lib_part);
+        for part in parts {
+            current_path = current_path.join(part);
         }
 
-        current_path = current_path.join(name.clone()).join(version.clone());
-
-        let native = get_natives(&i);
-
-        let jar_filename = if native.is_empty() {
-            format!("{}-{}.jar",name,version)
+        let jar_filename = if jar_filename_native.is_empty() {
+            jar_filename
         } else {
-            format!("{}-{}-{}.jar",name,version,native)
+            jar_filename_native
         };
 
         current_path = current_path.join(jar_filename);
@@ -59,114 +55,85 @@ fn get_libraries_string(libs: &Vec<Library>, jar: Option<String>, id: String, pa
     Ok(libstr)
 }
 
-fn replace_argument(ogargs: String, manifest: &VersionManifest, path: &PathBuf, options: &GameOptions) -> String {
-
-    let mut argstr = ogargs;
-
-    argstr = argstr.replace("${game_assets}", path.join("assets").join("virtual").join("legacy").to_str().expect("Failed to make string"));
-    
-    let lib_path = path.join("libraries").to_str().expect("Failed to make str").to_string();
-    argstr = argstr.replace("${game_assets}", &lib_path);
-    argstr = argstr.replace("${library_directory}", &lib_path);
-
-    argstr = argstr.replace("${game_assets}", &get_classpath_separator());
-    
-    argstr = argstr.replace("${version_name}", &manifest.id);
-
-    argstr = argstr.replace("${assets_root}", path.join("assets").to_str().expect("Failed to make string"));
-
-    argstr = argstr.replace("${user_type}", &options.user_type.to_string());
-
-    argstr = argstr.replace("${classpath_separator}", &get_classpath_separator());
-
-    argstr = argstr.replace("${user_properties}", "{}");
-
-    if let Some(natives_dir) = options.navtives_directory.clone() {
-       argstr = argstr.replace("${natives_directory}", natives_dir.to_str().expect("Failed to make string"));
-    }
-
-    if let Some(launcher_name) = options.launcher_name.clone().or(Some("rusty-minecraft-launcher".to_string())) {
-        argstr = argstr.replace("${launcher_name}", &launcher_name);
-    } 
-
-    if let Some(launcher_version) = options.launcher_version.clone().or(Some(env!("CARGO_PKG_VERSION").to_string())) {
-        argstr = argstr.replace("${launcher_version}", &launcher_version);
-    } 
-
-    if let Some(classpath) = options.classpath.clone() {
-        argstr = argstr.replace("${classpath}", &classpath);
-    }
-
-    if let Some(auth_player_name) = options.username.clone() {
-        argstr = argstr.replace("${auth_player_name}", &auth_player_name);
-    }
-
-    if let Some(release_type) = &manifest.release_type.clone() {
-        argstr = argstr.replace("${version_type}", &release_type);
-    }
-
-    if let Some(game_directory) = options.game_directory.clone().or(Some(path.clone())) {
-        argstr = argstr.replace("${game_directory}", game_directory.to_str().expect("Failed to make string"));
-    }
-
-    if let Some(assets_index_name) = &manifest.assets.clone().or(Some(manifest.id.clone())) {
-        argstr = argstr.replace("${assets_index_name}", &assets_index_name);
-    } 
-
-    if let Some(uuid) = options.uuid.clone().or(Some("{uuid}".to_string())) {
-        argstr = argstr.replace("${auth_uuid}", &uuid);
-    } 
-
-    if let Some(xuid) = options.xuid.clone().or(Some("{xuid}".to_string())) {
-        argstr = argstr.replace("${auth_xuid}", &xuid);
-    } 
-
-    if let Some(token) = options.token.clone().or(Some("{token}".to_string())) {
-        argstr = argstr.replace("${auth_access_token}", &token);
-        argstr = argstr.replace("${auth_session}", &token);
-    } 
+/// JVM arguments the launcher adds for versions whose json has none, ie those with `minecraftArguments`
+const LEGACY_JVM_ARGUMENTS: [&str; 3] = ["-Djava.library.path=${natives_directory}", "-cp", "${classpath}"];
 
-    if let Some(resolution_width) = options.resolution_width.clone().or(Some("854".to_string())) {
-        argstr = argstr.replace("${resolution_width}", &resolution_width);
-    }
-
-    if let Some(resolution_height) = options.resolution_height.clone().or(Some("480".to_string())) {
-        argstr = argstr.replace("${resolution_height}", &resolution_height);
-    } 
-
-    if let Some(client_id) = options.client_id.clone().or(Some("{clientId}".to_string())) {
-        argstr = argstr.replace("${clientid}", &client_id);
+/// `host` or `host:port` of the server to join on launch
+fn get_server_address(options: &GameOptions) -> Option<String> {
+    match (&options.server, &options.port) {
+        (Some(server), Some(port)) => Some(format!("{}:{}",server,port)),
+        (Some(server), None) => Some(server.clone()),
+        _ => None
     }
+}
 
-    argstr
+/// The values of every placeholder the launch arguments can use. Names, uuids and tokens
+/// that aren't known stay unset so the arguments using them fail to render.
+fn get_variables(manifest: &VersionManifest, path: &Path, options: &GameOptions, game_assets: &Path) -> Variables {
+    let to_string = |path: &Path| path.to_str().expect("Failed to make string").to_string();
+    let game_directory = options.game_directory.clone().unwrap_or(path.to_path_buf());
+
+    let mut variables = Variables::default();
+    variables
+        .set(Variable::GameAssets, to_string(game_assets))
+        .set(Variable::LibraryDirectory, to_string(&path.join("libraries")))
+        .set(Variable::VersionName, manifest.id.clone())
+        .set(Variable::AssetsRoot, to_string(&path.join("assets")))
+        .set(Variable::UserType, options.user_type.to_string())
+        .set(Variable::ClasspathSeparator, get_classpath_separator())
+        .set(Variable::UserProperties, "{}")
+        .set_some(Variable::NativesDirectory, options.navtives_directory.as_deref().map(to_string))
+        .set(Variable::LauncherName, options.launcher_name.clone().unwrap_or("rusty-minecraft-launcher".into()))
+        .set(Variable::LauncherVersion, options.launcher_version.clone().unwrap_or(env!("CARGO_PKG_VERSION").into()))
+        .set_some(Variable::Classpath, options.classpath.clone())
+        .set_some(Variable::AuthPlayerName, options.username.clone())
+        .set_some(Variable::VersionType, manifest.release_type.clone())
+        .set(Variable::GameDirectory, to_string(&game_directory))
+        .set(Variable::AssetsIndexName, manifest.assets.clone().unwrap_or(manifest.id.clone()))
+        .set_some(Variable::AuthUuid, options.uuid.clone())
+        .set_some(Variable::AuthAccessToken, options.token.clone())
+        .set_some(Variable::AuthSession, options.token.clone())
+        // only microsoft accounts have an xuid, and the client id only goes to telemetry
+        .set(Variable::AuthXuid, options.xuid.clone().unwrap_or("0".into()))
+        .set(Variable::ClientId, options.client_id.clone().unwrap_or("0".into()))
+        .set(Variable::ResolutionWidth, options.resolution_width.clone().unwrap_or("854".into()))
+        .set(Variable::ResolutionHeight, options.resolution_height.clone().unwrap_or("480".into()))
+        .set_some(Variable::QuickPlayMultiplayer, get_server_address(options))
+        .set_some(Variable::QuickPlaySingleplayer, options.quick_play_singleplayer.clone())
+        .set_some(Variable::QuickPlayRealms, options.quick_play_realms.clone())
+        .set_some(Variable::QuickPlayPath, options.quick_play_path.as_ref().map(|quick_play| to_string(&game_directory.join(quick_play))));
+    variables
 }
 
-fn get_arguments(args: &Vec<Argument>, manifest: &VersionManifest, path: PathBuf, options: &GameOptions) -> Vec<String> {
+fn get_arguments(args: &Vec<Argument>, variables: &Variables, options: &GameOptions) -> LibResult<Vec<String>> {
     let mut args_list: Vec<String> = vec![];
+    let environment = RuleEnvironment::from_options(options);
 
     for i in args {
-        match i {
-            Argument::Arg(arg) => {
-                args_list.push(replace_argument(arg.clone(), &manifest, &path, &options));
-            },
+        let values = match i {
+            Argument::Arg(arg) => vec![arg],
             Argument::RuleMulti { rules, value } => {
-                if !parse_rule_list(&rules, &options) {
+                if !is_allowed(rules, &environment) {
                     continue;
                 }
-                for a in value {
-                    args_list.push(replace_argument(a.clone(), &manifest, &path, &options))
-                }
+                value.iter().collect()
             },
             Argument::RuleSingle{ rules, value } => {
-                if !parse_rule_list(&rules, &options) {
+                if !is_allowed(rules, &environment) {
                     continue;
                 }
-                args_list.push(value.clone());
+                vec![value]
+            }
+        };
+        for value in values {
+            match variables.render(value) {
+                Ok(arg) => args_list.push(arg),
+                Err(err) => return Err(err)
             }
         }
     } 
 
-    args_list
+    Ok(args_list)
 }
 
 pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut GameOptions) -> LibResult<(String, Vec<String> )> {
@@ -181,8 +148,12 @@ pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut
         Err(err) => return Err(err)
     };
 
+    // a directory given by the caller is theirs to fill, the default one is rebuilt
+    // every launch so natives left by an older library version don't get loaded
     if options.navtives_directory.is_none() {
-        options.navtives_directory = Some(mc_dir.join("versions").join(manifest.id.clone()).join("natives"));
+        let natives_dir = mc_dir.join("versions").join(manifest.id.clone()).join("natives");
+        extract_natives(&manifest.libraries, &mc_dir, &natives_dir)?;
+        options.navtives_directory = Some(natives_dir);
     }
 
     match get_libraries_string(&manifest.libraries, manifest.jar.clone(), manifest.id.clone(), mc_dir.clone()) {
@@ -214,13 +185,35 @@ pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut
         command.push("java".into())
     }
 
-    if let Some(args) = options.jvm_arguments.clone() {
-        let mut jvm_args = args.split(" ").map(|e|e.to_string()).collect::<Vec<String>>();
-        command.append(&mut jvm_args);
+    if let Some(args) = &options.jvm_arguments {
+        match split_args(args) {
+            Ok(mut jvm_args) => command.append(&mut jvm_args),
+            Err(err) => return Err(err)
+        }
+    }
+
+    // 1.6 to 1.12 read their assets by name from a copy, older versions from the game directory
+    let game_dir = options.game_directory.clone().unwrap_or(mc_dir.clone());
+    let game_assets = match &manifest.assets {
+        Some(index) => prepare_legacy_assets(&mc_dir, &game_dir, index).await?.unwrap_or(mc_dir.join("assets")),
+        None => mc_dir.join("assets")
+    };
+
+    let mut variables = get_variables(&manifest, &mc_dir, options, &game_assets);
+
+    if manifest.arguments.jvm.is_empty() {
+        for arg in LEGACY_JVM_ARGUMENTS {
+            match variables.render(arg) {
+                Ok(value) => command.push(value),
+                Err(err) => return Err(err)
+            }
+        }
     }
 
-    let mut jvm_args = get_arguments(&manifest.arguments.jvm, &manifest, mc_dir.clone(), &options);
-    command.append(&mut jvm_args);
+    match get_arguments(&manifest.arguments.jvm, &variables, options) {
+        Ok(mut jvm_args) => command.append(&mut jvm_args),
+        Err(err) => return Err(err)
+    }
     
     if options.enable_logging_config  {
         if let Some(logger) = &manifest.logging {
@@ -232,8 +225,11 @@ pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut
                     };
 
                     let logger_file = logger_path.join(id);
-                    let cmd =  client.argument.replace("${path}",logger_file.to_str().expect("Failed to transform"));
-                    command.push(cmd);
+                    variables.set(Variable::Path, logger_file.to_str().expect("Failed to transform"));
+                    match variables.render(&client.argument) {
+                        Ok(cmd) => command.push(cmd),
+                        Err(err) => return Err(err)
+                    }
                 }
             }
         }
@@ -241,8 +237,34 @@ pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut
 
     command.push(manifest.main_class.clone());
 
-    let mut launch_args = get_arguments(&manifest.arguments.game, &manifest, mc_dir.clone(), &options);
-    command.append(&mut launch_args);
+    if let Some(legacy) = &manifest.minecraft_arguments {
+        for arg in legacy.split_whitespace() {
+            match variables.render(arg) {
+                Ok(value) => command.push(value),
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    match get_arguments(&manifest.arguments.game, &variables, options) {
+        Ok(mut launch_args) => command.append(&mut launch_args),
+        Err(err) => return Err(err)
+    }
+
+    // versions before Quick Play join a server with --server and --port, and can't open a world or realm
+    if !has_feature_rule(&manifest.arguments.game, FEATURE_QUICK_PLAY_MULTIPLAYER) {
+        if options.quick_play_singleplayer.is_some() || options.quick_play_realms.is_some() {
+            return Err(LauncherLibError::Unsupported(format!("{} has no Quick Play to open a world or realm with",manifest.id)));
+        }
+        if let Some(server) = &options.server {
+            command.push("--server".into());
+            command.push(server.clone());
+            if let Some(port) = &options.port {
+                command.push("--port".into());
+                command.push(port.clone());
+            }
+        }
+    }
 
     let exec = command.remove(0);
 
@@ -250,3 +272,176 @@ pub async fn get_launch_command(version: String, mc_dir: PathBuf, options: &mut
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_legacy_launch_command() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-command-{}",uuid::Uuid::new_v4()));
+        let version_dir = mc_dir.join("versions").join("1.7.10");
+        std::fs::create_dir_all(&version_dir).unwrap();
+        std::fs::write(version_dir.join("1.7.10.json"), r#"{
+            "id": "1.7.10",
+            "assets": "legacy",
+            "minecraftArguments": "--username ${auth_player_name} --version ${version_name} --assetsDir ${game_assets} --assetIndex ${assets_index_name} --userProperties ${user_properties}",
+            "libraries": [],
+            "mainClass": "net.minecraft.client.main.Main",
+            "releaseTime": "2014-05-14T17:29:23+00:00",
+            "time": "2014-05-14T17:29:23+00:00",
+            "type": "release"
+        }"#).unwrap();
+        let indexes = mc_dir.join("assets").join("indexes");
+        std::fs::create_dir_all(&indexes).unwrap();
+        std::fs::write(indexes.join("legacy.json"), r#"{ "virtual": true, "objects": {} }"#).unwrap();
+
+        let mut options = GameOptions {
+            username: Some("Steve".into()),
+            executable_path: Some(PathBuf::from("java")),
+            ..Default::default()
+        };
+        let (exec, args) = get_launch_command("1.7.10".into(), mc_dir.clone(), &mut options).await.unwrap();
+        assert_eq!(exec, "java");
+
+        let natives = mc_dir.join("versions").join("1.7.10").join("natives");
+        assert_eq!(args[0], format!("-Djava.library.path={}",natives.to_str().unwrap()));
+        assert_eq!(args[1], "-cp");
+        assert_eq!(args[3], "net.minecraft.client.main.Main");
+        let virtual_assets = mc_dir.join("assets").join("virtual").join("legacy");
+        assert_eq!(&args[4..], &[
+            "--username", "Steve",
+            "--version", "1.7.10",
+            "--assetsDir", virtual_assets.to_str().unwrap(),
+            "--assetIndex", "legacy",
+            "--userProperties", "{}"
+        ]);
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_resources_in_game_directory() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-command-{}",uuid::Uuid::new_v4()));
+        let game_dir = mc_dir.join("instances").join("classic");
+        let version_dir = mc_dir.join("versions").join("1.5.2");
+        std::fs::create_dir_all(&version_dir).unwrap();
+        std::fs::write(version_dir.join("1.5.2.json"), r#"{
+            "id": "1.5.2",
+            "assets": "pre-1.6",
+            "minecraftArguments": "${auth_player_name} --gameDir ${game_directory} --assetsDir ${game_assets}",
+            "libraries": [],
+            "mainClass": "net.minecraft.launchwrapper.Launch",
+            "releaseTime": "2013-04-25T15:45:00+00:00",
+            "time": "2013-04-25T15:45:00+00:00",
+            "type": "release"
+        }"#).unwrap();
+        let hash = "3a1d589e96137edd0616bcf57c0ebfe1ef2c76b2";
+        let objects = mc_dir.join("assets").join("objects").join("3a");
+        std::fs::create_dir_all(&objects).unwrap();
+        std::fs::copy("tests/fixtures/install/en_us.json", objects.join(hash)).unwrap();
+        let indexes = mc_dir.join("assets").join("indexes");
+        std::fs::create_dir_all(&indexes).unwrap();
+        std::fs::write(indexes.join("pre-1.6.json"), format!(r#"{{ "map_to_resources": true, "objects": {{ "lang/en_us.json": {{ "hash": "{}", "size": 28 }} }} }}"#, hash)).unwrap();
+
+        let mut options = GameOptions {
+            username: Some("Steve".into()),
+            executable_path: Some(PathBuf::from("java")),
+            game_directory: Some(game_dir.clone()),
+            ..Default::default()
+        };
+        let (_, args) = get_launch_command("1.5.2".into(), mc_dir.clone(), &mut options).await.unwrap();
+
+        let resources = game_dir.join("resources");
+        let at = args.iter().position(|arg| arg == "--assetsDir").unwrap();
+        assert_eq!(args[at + 1], resources.to_str().unwrap());
+        assert_eq!(std::fs::read(resources.join("lang").join("en_us.json")).unwrap(), std::fs::read("tests/fixtures/install/en_us.json").unwrap());
+        assert!(!mc_dir.join("resources").exists());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    fn install_fixture(mc_dir: &Path, id: &str) {
+        let version_dir = mc_dir.join("versions").join(id);
+        std::fs::create_dir_all(&version_dir).unwrap();
+        std::fs::copy(format!("tests/fixtures/rules/{}.json",id), version_dir.join(format!("{}.json",id))).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_join_server_on_launch() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-command-{}",uuid::Uuid::new_v4()));
+        install_fixture(&mc_dir, "1.20.1");
+        install_fixture(&mc_dir, "1.12.2");
+
+        let options = GameOptions {
+            executable_path: Some(PathBuf::from("java")),
+            username: Some("Steve".into()),
+            uuid: Some("uuid".into()),
+            token: Some("token".into()),
+            server: Some("seabreyh.ml".into()),
+            port: Some("25565".into()),
+            ..Default::default()
+        };
+
+        let (_, args) = get_launch_command("1.20.1".into(), mc_dir.clone(), &mut options.clone()).await.unwrap();
+        let at = args.iter().position(|arg| arg == "--quickPlayMultiplayer").unwrap();
+        assert_eq!(args[at + 1], "seabreyh.ml:25565");
+        assert!(!args.contains(&"--server".to_string()));
+        assert!(!args.contains(&"--quickPlayPath".to_string()));
+
+        let (_, args) = get_launch_command("1.12.2".into(), mc_dir.clone(), &mut options.clone()).await.unwrap();
+        assert_eq!(&args[args.len() - 4..], &["--server", "seabreyh.ml", "--port", "25565"]);
+
+        let mut world = GameOptions {
+            executable_path: Some(PathBuf::from("java")),
+            username: Some("Steve".into()),
+            uuid: Some("uuid".into()),
+            token: Some("token".into()),
+            quick_play_singleplayer: Some("New World".into()),
+            quick_play_path: Some(PathBuf::from("quickPlay").join("log.json")),
+            ..Default::default()
+        };
+        let (_, args) = get_launch_command("1.20.1".into(), mc_dir.clone(), &mut world.clone()).await.unwrap();
+        let at = args.iter().position(|arg| arg == "--quickPlaySingleplayer").unwrap();
+        assert_eq!(args[at + 1], "New World");
+        let at = args.iter().position(|arg| arg == "--quickPlayPath").unwrap();
+        assert_eq!(args[at + 1], mc_dir.join("quickPlay").join("log.json").to_str().unwrap());
+
+        match get_launch_command("1.12.2".into(), mc_dir.clone(), &mut world).await {
+            Err(LauncherLibError::Unsupported(_)) => {}
+            other => panic!("{:?}", other)
+        }
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_launch_arguments_are_validated() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-command-{}",uuid::Uuid::new_v4()));
+        install_fixture(&mc_dir, "1.20.1");
+
+        let mut options = GameOptions {
+            executable_path: Some(PathBuf::from("java")),
+            jvm_arguments: Some(r#"-Xmx2G "-Djava.io.tmpdir=C:\Program Files\tmp""#.into()),
+            username: Some("Steve".into()),
+            uuid: Some("uuid".into()),
+            demo: true,
+            ..Default::default()
+        };
+
+        match get_launch_command("1.20.1".into(), mc_dir.clone(), &mut options.clone()).await {
+            Err(LauncherLibError::UnresolvedPlaceholder { name, .. }) => assert_eq!(name, "auth_access_token"),
+            other => panic!("{:?}", other)
+        }
+
+        options.token = Some("token".into());
+        let (_, args) = get_launch_command("1.20.1".into(), mc_dir.clone(), &mut options).await.unwrap();
+        assert_eq!(&args[0..2], &["-Xmx2G", r"-Djava.io.tmpdir=C:\Program Files\tmp"]);
+        assert!(args.contains(&"--demo".to_string()));
+        assert!(!args.iter().any(|arg| arg.contains("${") || arg.contains("{token}")));
+        let at = args.iter().position(|arg| arg == "--xuid").unwrap();
+        assert_eq!(args[at + 1], "0");
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::endpoints::Endpoints;
+use crate::expections::LibResult;
+use crate::http::{BandwidthLimiter, HttpConfig};
+use crate::retry::{Mirror, RetryPolicy};
+use crate::transport::{ReqwestTransport, Transport};
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+
+tokio::task_local! {
+    static SCOPED_CONTEXT: Arc<LauncherContext>;
+}
+
+/// Process wide settings used by the library when talking to remote services
+#[derive(Debug, Clone, Default)]
+pub struct LauncherContext {
+    pub endpoints: Endpoints,
+    pub http: HttpConfig,
+    pub retry: RetryPolicy,
+    /// tried in order when a request to the original url fails
+    pub mirrors: Vec<Mirror>,
+    /// replaces the reqwest transport, ie to replay recorded responses in tests
+    transport: Option<Arc<dyn Transport>>,
+    client: OnceLock<reqwest::Client>,
+    limiter: OnceLock<Arc<BandwidthLimiter>>,
+}
+impl LauncherContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default context with endpoints and http settings overridden from the environment
+    pub fn from_env() -> Self {
+        Self {
+            endpoints: Endpoints::from_env(),
+            http: HttpConfig::from_env(),
+            ..Default::default()
+        }
+    }
+
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.http = http;
+        self.client = OnceLock::new();
+        self.limiter = OnceLock::new();
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn mirrors(mut self, mirrors: Vec<Mirror>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// The transport every request made with this context goes through
+    pub fn get_transport(&self) -> LibResult<Arc<dyn Transport>> {
+        if let Some(transport) = &self.transport {
+            return Ok(transport.clone());
+        }
+        match self.get_client() {
+            Ok(client) => Ok(Arc::new(ReqwestTransport::new(client))),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The http client shared by every request made with this context, built on first use
+    pub fn get_client(&self) -> LibResult<reqwest::Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client.clone());
+        }
+        match self.http.build_client() {
+            Ok(client) => Ok(self.client.get_or_init(|| client).clone()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_limiter(&self) -> Arc<BandwidthLimiter> {
+        self.limiter
+            .get_or_init(|| Arc::new(BandwidthLimiter::new(self.http.max_bytes_per_second)))
+            .clone()
+    }
+}
+
+fn context_lock() -> &'static RwLock<Arc<LauncherContext>> {
+    static CONTEXT: OnceLock<RwLock<Arc<LauncherContext>>> = OnceLock::new();
+    CONTEXT.get_or_init(|| RwLock::new(Arc::new(LauncherContext::from_env())))
+}
+
+/// Returns the context currently used by the library
+pub fn get_context() -> Arc<LauncherContext> {
+    if let Ok(context) = SCOPED_CONTEXT.try_with(|value| value.clone()) {
+        return context;
+    }
+    match context_lock().read() {
+        Ok(value) => value.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Replaces the context used by the library
+pub fn set_context(context: LauncherContext) {
+    let mut lock = match context_lock().write() {
+        Ok(value) => value,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *lock = Arc::new(context);
+}
+
+/// Runs `future` with `context` instead of the process wide context.
+/// Tasks spawned from the future fall back to the process wide context.
+pub async fn with_context<F: Future>(context: LauncherContext, future: F) -> F::Output {
+    SCOPED_CONTEXT.scope(Arc::new(context), future).await
+}
+
+/// Context answering every request from the fixtures in `tests/fixtures/<flow>`
+#[cfg(test)]
+pub(crate) fn fixture_context(flow: &str) -> LauncherContext {
+    let dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(flow);
+    let transport = crate::transport::ReplayTransport::from_dir(dir).expect("Fixture should load");
+    LauncherContext::new()
+        .retry_policy(RetryPolicy::none())
+        .transport(Arc::new(transport))
+}
@@ -0,0 +1,606 @@
+use crate::expections::{LauncherLibError, LibResult};
+use crate::staging::MODS_DIR;
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const CRASH_REPORTS_DIR: &str = "crash-reports";
+pub const LATEST_LOG: &str = "logs/latest.log";
+
+/// What a mod jar says about itself
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModJar {
+    pub jar: PathBuf,
+    /// ids from `META-INF/mods.toml`, `fabric.mod.json` or `mcmod.info`
+    pub mod_ids: Vec<String>,
+    /// mixin configs from the manifest's `MixinConfigs` or `fabric.mod.json`
+    pub mixin_configs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuspectedMod {
+    pub mod_id: String,
+    pub name: Option<String>,
+    pub jar: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixinFailure {
+    /// `config:Mixin`, or the config when the mixin isn't named
+    pub mixin: String,
+    pub target: Option<String>,
+    pub mod_id: Option<String>,
+    pub jar: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingDependency {
+    pub mod_id: String,
+    pub required_by: String,
+    pub version_range: String,
+    /// the installed version, `None` when it isn't installed
+    pub found: Option<String>,
+    pub required_by_jar: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CrashAnalysis {
+    pub crash_report: Option<PathBuf>,
+    pub description: Option<String>,
+    /// the exception and every `Caused by`, outermost first
+    pub exceptions: Vec<String>,
+    pub suspected_mods: Vec<SuspectedMod>,
+    pub mixin_failures: Vec<MixinFailure>,
+    pub missing_dependencies: Vec<MissingDependency>,
+}
+
+fn read_zip_entry(zip: &mut zip::ZipArchive<File>, name: &str) -> Option<String> {
+    let mut entry = zip.by_name(name).ok()?;
+    let mut buffer = String::new();
+    entry.read_to_string(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+/// Reads the mod ids and mixin configs of a mod jar
+pub fn read_mod_jar(path: &Path) -> LibResult<ModJar> {
+    let file = match File::open(path) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: format!("Failed to open {}", path.display()),
+            })
+        }
+    };
+    let mut zip = match zip::ZipArchive::new(file) {
+        Ok(value) => value,
+        Err(err) => return Err(LauncherLibError::ZipError(err)),
+    };
+
+    let mut mod_jar = ModJar {
+        jar: path.to_path_buf(),
+        ..Default::default()
+    };
+
+    if let Some(toml) = read_zip_entry(&mut zip, "META-INF/mods.toml") {
+        let mod_id = Regex::new(r#"(?m)^\s*modId\s*=\s*"([^"]+)""#).expect("Invalid mod id regex");
+        mod_jar.mod_ids.extend(
+            mod_id
+                .captures_iter(&toml)
+                .map(|captures| captures[1].to_string()),
+        );
+    }
+    if let Some(json) = read_zip_entry(&mut zip, "fabric.mod.json") {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(&json) {
+            if let Some(id) = value["id"].as_str() {
+                mod_jar.mod_ids.push(id.to_string());
+            }
+            for mixin in value["mixins"].as_array().into_iter().flatten() {
+                match mixin.as_str().or_else(|| mixin["config"].as_str()) {
+                    Some(config) => mod_jar.mixin_configs.push(config.to_string()),
+                    None => continue,
+                }
+            }
+        }
+    }
+    if let Some(json) = read_zip_entry(&mut zip, "mcmod.info") {
+        if let Ok(serde_json::Value::Array(mods)) = serde_json::from_str(&json) {
+            mod_jar.mod_ids.extend(
+                mods.iter()
+                    .filter_map(|info| info["modid"].as_str())
+                    .map(|id| id.to_string()),
+            );
+        }
+    }
+    if let Some(manifest) = read_zip_entry(&mut zip, "META-INF/MANIFEST.MF") {
+        for line in manifest.lines() {
+            if let Some(configs) = line.strip_prefix("MixinConfigs:") {
+                mod_jar.mixin_configs.extend(
+                    configs
+                        .split(',')
+                        .map(|config| config.trim().to_string())
+                        .filter(|config| !config.is_empty()),
+                );
+            }
+        }
+    }
+
+    Ok(mod_jar)
+}
+
+/// Every readable jar in the mods directory
+pub fn get_mod_jars(mods_dir: &Path) -> Vec<ModJar> {
+    let entries = match fs::read_dir(mods_dir) {
+        Ok(value) => value,
+        Err(_) => return vec![],
+    };
+    let mut jars: Vec<ModJar> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|ext| ext == "jar").unwrap_or(false))
+        .filter_map(|path| read_mod_jar(&path).ok())
+        .collect();
+    jars.sort_by(|a, b| a.jar.cmp(&b.jar));
+    jars
+}
+
+fn find_jar_by_id(jars: &[ModJar], mod_id: &str) -> Option<PathBuf> {
+    jars.iter()
+        .find(|jar| jar.mod_ids.iter().any(|id| id == mod_id))
+        .map(|jar| jar.jar.clone())
+}
+
+/// The newest `.txt` in the crash reports directory
+pub fn get_latest_crash_report(game_dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(game_dir.join(CRASH_REPORTS_DIR))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .map(|ext| ext == "txt")
+                .unwrap_or(false)
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .map(|(_, path)| path)
+}
+
+fn get_exception(line: &str) -> Option<String> {
+    let exception = Regex::new(
+        r"^(?:Caused by: )?((?:[a-zA-Z_$][\w$]*\.)+[\w$]*(?:Exception|Error|Throwable)[\w$]*(?::.*)?)$",
+    )
+    .expect("Invalid exception regex");
+    exception
+        .captures(line.trim())
+        .map(|captures| captures[1].to_string())
+}
+
+/// Every exception with its causes. Stack frames and suppressed exceptions are skipped.
+fn parse_exceptions(text: &str) -> Vec<Vec<String>> {
+    let mut chains: Vec<Vec<String>> = vec![];
+    let mut in_chain = false;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if in_chain && line.starts_with("Caused by: ") {
+            if let (Some(exception), Some(chain)) = (get_exception(line), chains.last_mut()) {
+                chain.push(exception);
+            }
+        } else if in_chain && !line.is_empty() && line.starts_with(char::is_whitespace) {
+            continue;
+        } else {
+            in_chain = false;
+            if line.starts_with(char::is_whitespace) {
+                continue;
+            }
+            if let Some(exception) = get_exception(line) {
+                chains.push(vec![exception]);
+                in_chain = true;
+            }
+        }
+    }
+    chains
+}
+
+/// `Suspected Mods:` either lists `Name (id)` on the line or one mod per indented line,
+/// followed by details indented further
+fn parse_suspected_mods(text: &str) -> Vec<(String, Option<String>)> {
+    let entry = Regex::new(r"([^,()]+?)\s*\(([a-z0-9_.\-]+)\)").expect("Invalid mod regex");
+    let mut mods = vec![];
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let rest = match line
+            .find("Suspected Mod")
+            .and_then(|start| line[start..].find(':').map(|end| &line[start + end + 1..]))
+        {
+            Some(value) => value,
+            None => continue,
+        };
+        let mut entries = vec![rest.to_string()];
+        while let Some(next) = lines.peek() {
+            if !next.starts_with(char::is_whitespace) || next.trim().is_empty() {
+                break;
+            }
+            if !next.starts_with("\t\t") && !next.starts_with("    ") {
+                entries.push(next.to_string());
+            }
+            lines.next();
+        }
+        for text in entries {
+            for captures in entry.captures_iter(&text) {
+                let id = captures[2].to_string();
+                if !mods.iter().any(|(mod_id, _)| *mod_id == id) {
+                    mods.push((id, Some(captures[1].trim().to_string())));
+                }
+            }
+        }
+    }
+    // mods forge failed to load each get a section
+    let section = Regex::new(r"(?m)^-- MOD (\S+) --").expect("Invalid mod section regex");
+    for captures in section.captures_iter(text) {
+        let id = captures[1].to_string();
+        if !mods.iter().any(|(mod_id, _)| *mod_id == id) {
+            mods.push((id, None));
+        }
+    }
+    mods
+}
+
+fn parse_mixin_failures(text: &str, jars: &[ModJar]) -> Vec<MixinFailure> {
+    let apply = Regex::new(r"Mixin apply for mod (\S+) failed (\S+) from mod (\S+) -> ([^\s:]+)")
+        .expect("Invalid mixin regex");
+    let phase =
+        Regex::new(r"Mixin \[([^\]]+)\] from phase \[[^\]]*\] in config \[([^\]]+)\] FAILED")
+            .expect("Invalid mixin regex");
+
+    let mut failures: Vec<MixinFailure> = vec![];
+    for line in text.lines() {
+        let (mixin, target, mod_id) = if let Some(captures) = apply.captures(line) {
+            (
+                captures[2].to_string(),
+                Some(captures[4].to_string()),
+                Some(captures[3].to_string()),
+            )
+        } else if let Some(captures) = phase.captures(line) {
+            (captures[1].to_string(), None, None)
+        } else {
+            continue;
+        };
+        if failures.iter().any(|failure| failure.mixin == mixin) {
+            continue;
+        }
+
+        let config = mixin.split(':').next().unwrap_or_default();
+        let by_config = jars
+            .iter()
+            .find(|jar| jar.mixin_configs.iter().any(|value| value == config));
+        let mod_id = match (mod_id, by_config) {
+            (Some(value), _) => Some(value),
+            (None, Some(jar)) => jar.mod_ids.first().cloned(),
+            (None, None) => None,
+        };
+        let jar = match (by_config, &mod_id) {
+            (Some(jar), _) => Some(jar.jar.clone()),
+            (None, Some(id)) => find_jar_by_id(jars, id),
+            (None, None) => None,
+        };
+        failures.push(MixinFailure {
+            mixin,
+            target,
+            mod_id,
+            jar,
+        });
+    }
+    failures
+}
+
+fn parse_missing_dependencies(text: &str, jars: &[ModJar]) -> Vec<MissingDependency> {
+    let dependency = Regex::new(
+        r"Mod ID: '([^']+)', Requested by: '([^']+)', Expected range: '([^']+)', Actual version: '([^']+)'",
+    )
+    .expect("Invalid dependency regex");
+    let mut missing: Vec<MissingDependency> = vec![];
+    for captures in dependency.captures_iter(text) {
+        let mod_id = captures[1].to_string();
+        let required_by = captures[2].to_string();
+        if missing
+            .iter()
+            .any(|value| value.mod_id == mod_id && value.required_by == required_by)
+        {
+            continue;
+        }
+        missing.push(MissingDependency {
+            required_by_jar: find_jar_by_id(jars, &required_by),
+            mod_id,
+            required_by,
+            version_range: captures[3].to_string(),
+            found: match &captures[4] {
+                "[MISSING]" => None,
+                version => Some(version.to_string()),
+            },
+        });
+    }
+    missing
+}
+
+fn read_text(path: &Path) -> Option<String> {
+    fs::read(path)
+        .ok()
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Reads the newest crash report and `logs/latest.log` of the game directory and
+/// ties what went wrong to the jars in its mods directory
+pub fn analyze_crash(game_dir: &Path) -> CrashAnalysis {
+    let jars = get_mod_jars(&game_dir.join(MODS_DIR));
+    let crash_report = get_latest_crash_report(game_dir);
+    let report = crash_report
+        .as_deref()
+        .and_then(read_text)
+        .unwrap_or_default();
+    let log = read_text(&game_dir.join(LATEST_LOG)).unwrap_or_default();
+    let text = format!("{}\n{}", report, log);
+
+    // the report is about one crash, the log can hold exceptions the game recovered from
+    let exceptions = match parse_exceptions(&report).into_iter().next() {
+        Some(chain) => chain,
+        None => parse_exceptions(&log).pop().unwrap_or_default(),
+    };
+
+    CrashAnalysis {
+        description: report
+            .lines()
+            .find_map(|line| line.strip_prefix("Description: "))
+            .map(|value| value.trim().to_string()),
+        crash_report,
+        exceptions,
+        suspected_mods: parse_suspected_mods(&report)
+            .into_iter()
+            .map(|(mod_id, name)| SuspectedMod {
+                jar: find_jar_by_id(&jars, &mod_id),
+                mod_id,
+                name,
+            })
+            .collect(),
+        mixin_failures: parse_mixin_failures(&text, &jars),
+        missing_dependencies: parse_missing_dependencies(&text, &jars),
+    }
+}
+
+fn describe_jar(jar: &Option<PathBuf>) -> String {
+    match jar.as_ref().and_then(|jar| jar.file_name()) {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => "no installed jar".into(),
+    }
+}
+
+impl CrashAnalysis {
+    /// A few lines on what most likely went wrong, for players to read or send us
+    pub fn diagnosis(&self) -> String {
+        let mut lines = vec![];
+        for dependency in &self.missing_dependencies {
+            let found = match &dependency.found {
+                Some(version) => format!("{} is installed", version),
+                None => "it is not installed".into(),
+            };
+            lines.push(format!(
+                "{} ({}) needs {} {} but {}",
+                dependency.required_by,
+                describe_jar(&dependency.required_by_jar),
+                dependency.mod_id,
+                dependency.version_range,
+                found
+            ));
+        }
+        for failure in &self.mixin_failures {
+            lines.push(format!(
+                "Mixin {} of {} ({}) failed to apply{}",
+                failure.mixin,
+                failure.mod_id.as_deref().unwrap_or("an unknown mod"),
+                describe_jar(&failure.jar),
+                failure
+                    .target
+                    .as_ref()
+                    .map(|target| format!(" to {}", target))
+                    .unwrap_or_default()
+            ));
+        }
+        if !self.suspected_mods.is_empty() {
+            let mods: Vec<String> = self
+                .suspected_mods
+                .iter()
+                .map(|suspect| {
+                    format!(
+                        "{} ({})",
+                        suspect.name.as_deref().unwrap_or(&suspect.mod_id),
+                        describe_jar(&suspect.jar)
+                    )
+                })
+                .collect();
+            lines.push(format!("Suspected mods: {}", mods.join(", ")));
+        }
+        if let Some(exception) = self.exceptions.last() {
+            lines.push(format!("Crashed with {}", exception));
+        }
+        if lines.is_empty() {
+            return "No crash report or known error was found".into();
+        }
+        if let Some(description) = &self.description {
+            lines.insert(0, description.clone());
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_jar;
+
+    #[test]
+    fn test_analyze_crash() {
+        let game_dir = std::env::temp_dir().join(format!("mc-crash-{}", uuid::Uuid::new_v4()));
+        let mods_dir = game_dir.join(MODS_DIR);
+        let reports_dir = game_dir.join(CRASH_REPORTS_DIR);
+        fs::create_dir_all(&mods_dir).unwrap();
+        fs::create_dir_all(&reports_dir).unwrap();
+        fs::create_dir_all(game_dir.join("logs")).unwrap();
+
+        write_jar(
+            &mods_dir.join("create-mc1.18.2_v0.5.0c.jar"),
+            &[
+                (
+                    "META-INF/mods.toml",
+                    "[[mods]]\nmodId=\"create\"\nversion=\"0.5.0c\"\n",
+                ),
+                (
+                    "META-INF/MANIFEST.MF",
+                    "Manifest-Version: 1.0\nMixinConfigs: create.mixins.json\n",
+                ),
+            ],
+        );
+        write_jar(
+            &mods_dir.join("BetterFoliage-1.18.2.jar"),
+            &[(
+                "fabric.mod.json",
+                r#"{"id": "betterfoliage", "mixins": ["betterfoliage.mixins.json"]}"#,
+            )],
+        );
+        write_jar(
+            &mods_dir.join("flywheel-forge-1.18-0.6.4.jar"),
+            &[("META-INF/mods.toml", "modId = \"flywheel\"")],
+        );
+        fs::write(mods_dir.join("broken.jar"), "not a zip").unwrap();
+
+        // an older report that must not be picked
+        let old = reports_dir.join("crash-2022-07-01_12.00.00-client.txt");
+        fs::write(
+            &old,
+            "Description: Old\n\njava.lang.IllegalStateException: old\n",
+        )
+        .unwrap();
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let report = reports_dir.join("crash-2022-08-01_12.00.00-client.txt");
+        fs::copy(
+            "tests/fixtures/crash/crash-2022-08-01_12.00.00-client.txt",
+            &report,
+        )
+        .unwrap();
+        fs::copy("tests/fixtures/crash/latest.log", game_dir.join(LATEST_LOG)).unwrap();
+
+        let analysis = analyze_crash(&game_dir);
+        assert_eq!(analysis.crash_report, Some(report));
+        assert_eq!(
+            analysis.description.as_deref(),
+            Some("Mod loading error has occurred")
+        );
+        assert_eq!(analysis.exceptions.len(), 2);
+        assert_eq!(
+            analysis.exceptions[0],
+            "java.lang.Exception: Mod Loading has failed"
+        );
+        assert!(analysis.exceptions[1].starts_with("java.lang.NullPointerException: Cannot invoke"));
+
+        assert_eq!(
+            analysis.suspected_mods,
+            vec![
+                SuspectedMod {
+                    mod_id: "create".into(),
+                    name: Some("Create".into()),
+                    jar: Some(mods_dir.join("create-mc1.18.2_v0.5.0c.jar")),
+                },
+                SuspectedMod {
+                    mod_id: "flywheel".into(),
+                    name: Some("Flywheel".into()),
+                    jar: Some(mods_dir.join("flywheel-forge-1.18-0.6.4.jar")),
+                },
+                SuspectedMod {
+                    mod_id: "sodiumextras".into(),
+                    name: None,
+                    jar: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            analysis.mixin_failures,
+            vec![
+                MixinFailure {
+                    mixin: "create.mixins.json:ContraptionDriverInteractMixin".into(),
+                    target: Some("net.minecraft.world.entity.Entity".into()),
+                    mod_id: Some("create".into()),
+                    jar: Some(mods_dir.join("create-mc1.18.2_v0.5.0c.jar")),
+                },
+                MixinFailure {
+                    mixin: "betterfoliage.mixins.json:MixinBlockState".into(),
+                    target: None,
+                    mod_id: Some("betterfoliage".into()),
+                    jar: Some(mods_dir.join("BetterFoliage-1.18.2.jar")),
+                },
+            ]
+        );
+
+        assert_eq!(analysis.missing_dependencies.len(), 2);
+        assert_eq!(
+            analysis.missing_dependencies[0].found.as_deref(),
+            Some("0.6.4")
+        );
+        assert_eq!(analysis.missing_dependencies[1].mod_id, "architectury");
+        assert_eq!(analysis.missing_dependencies[1].found, None);
+
+        let diagnosis = analysis.diagnosis();
+        assert!(diagnosis.starts_with("Mod loading error has occurred\n"));
+        assert!(diagnosis.contains(
+            "betterfoliage (BetterFoliage-1.18.2.jar) needs architectury [4.5,) but it is not installed"
+        ));
+        assert!(diagnosis.contains(
+            "Suspected mods: Create (create-mc1.18.2_v0.5.0c.jar), Flywheel (flywheel-forge-1.18-0.6.4.jar), sodiumextras (no installed jar)"
+        ));
+
+        let _ = fs::remove_dir_all(game_dir);
+    }
+
+    #[test]
+    fn test_analyze_without_crash() {
+        let game_dir = std::env::temp_dir().join(format!("mc-crash-{}", uuid::Uuid::new_v4()));
+        let analysis = analyze_crash(&game_dir);
+        assert_eq!(analysis, CrashAnalysis::default());
+        assert_eq!(
+            analysis.diagnosis(),
+            "No crash report or known error was found"
+        );
+
+        // without a report the last exception in the log is the one that ended the game
+        fs::create_dir_all(game_dir.join("logs")).unwrap();
+        fs::write(
+            game_dir.join(LATEST_LOG),
+            "[12:00:00] [main/WARN]: Failed to load a texture\n\
+             java.io.FileNotFoundException: missing.png\n\
+             \tat Loader.load(Loader.java:1)\n\
+             [12:00:01] [Render thread/FATAL]: Unreported exception thrown!\n\
+             java.lang.IllegalStateException: Rendersystem called from wrong thread\n\
+             \tat RenderSystem.assertOnRenderThread(RenderSystem.java:1)\n\
+             Caused by: java.lang.RuntimeException: inner\n",
+        )
+        .unwrap();
+        let analysis = analyze_crash(&game_dir);
+        assert_eq!(
+            analysis.exceptions,
+            vec![
+                "java.lang.IllegalStateException: Rendersystem called from wrong thread",
+                "java.lang.RuntimeException: inner"
+            ]
+        );
+        assert!(analysis
+            .diagnosis()
+            .ends_with("Crashed with java.lang.RuntimeException: inner"));
+
+        let _ = fs::remove_dir_all(game_dir);
+    }
+}
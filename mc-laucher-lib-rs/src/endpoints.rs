@@ -0,0 +1,148 @@
+use crate::expections::{LauncherLibError, LibResult};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::env::var;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+/// Env variable holding the path to a json file with endpoint overrides
+pub const ENDPOINTS_FILE_ENV: &str = "MC_LAUNCHER_ENDPOINTS";
+/// Prefix of the env variables overriding a single endpoint, ie `MC_LAUNCHER_ENDPOINT_FORGE_MAVEN`
+pub const ENDPOINT_ENV_PREFIX: &str = "MC_LAUNCHER_ENDPOINT_";
+
+/// Base urls of every remote service the library talks to.
+/// Urls that are read from version manifests are not affected, use mirrors for those.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Endpoints {
+    pub minecraft_manifest: String,
+    pub jvm_manifest: String,
+    pub libraries: String,
+    /// maven the ARM64 builds of LWJGL are fetched from when a version doesn't list them
+    pub lwjgl_maven: String,
+    pub resources: String,
+    pub forge_maven: String,
+    /// `{version}` is replaced with `<minecraft>-<forge>`
+    pub forge_installer: String,
+    pub forge_cli: String,
+    pub fabric_meta: String,
+    pub fabric_installer_maven: String,
+    pub optifine: String,
+    pub optifine_headless: String,
+    pub ms_authorize: String,
+    pub ms_token: String,
+    pub xbox_user_auth: String,
+    pub xsts_authorize: String,
+    pub minecraft_services: String,
+    pub mods_list: String,
+}
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            minecraft_manifest: "https://launchermeta.mojang.com/mc/game/version_manifest.json".into(),
+            jvm_manifest: "https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json".into(),
+            libraries: "https://libraries.minecraft.net/".into(),
+            lwjgl_maven: "https://repo1.maven.org/maven2/".into(),
+            resources: "https://resources.download.minecraft.net/".into(),
+            forge_maven: "https://maven.minecraftforge.net/net/minecraftforge/forge/".into(),
+            forge_installer: "https://files.minecraftforge.net/maven/net/minecraftforge/forge/{version}/forge-{version}-installer.jar".into(),
+            forge_cli: "https://github.com/TeamKun/ForgeCLI/releases/download/1.0.1/ForgeCLI-1.0.1-all.jar".into(),
+            fabric_meta: "https://meta.fabricmc.net/v2/versions/".into(),
+            fabric_installer_maven: "https://maven.fabricmc.net/net/fabricmc/fabric-installer/".into(),
+            optifine: "https://optifine.net/".into(),
+            optifine_headless: "https://github.com/VisualSource/mc-installer-v2/raw/master/wellknowns/jars/optifineheadless.jar".into(),
+            ms_authorize: "https://login.live.com/oauth20_authorize.srf".into(),
+            ms_token: "https://login.live.com/oauth20_token.srf".into(),
+            xbox_user_auth: "https://user.auth.xboxlive.com/user/authenticate".into(),
+            xsts_authorize: "https://xsts.auth.xboxlive.com/xsts/authorize".into(),
+            minecraft_services: "https://api.minecraftservices.com/".into(),
+            mods_list: "https://raw.githubusercontent.com/Seabreyh/seabreyh-mc-mod-installer/main/assets/mods.md".into(),
+        }
+    }
+}
+impl Endpoints {
+    /// Reads endpoints from a json file, missing keys keep their default value
+    pub fn from_file(path: PathBuf) -> LibResult<Self> {
+        match read_to_string(path) {
+            Ok(raw) => match serde_json::from_str::<Endpoints>(&raw) {
+                Ok(value) => Ok(value),
+                Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
+            },
+            Err(err) => Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to read endpoints file".into(),
+            }),
+        }
+    }
+
+    /// Defaults, overridden by the file in `MC_LAUNCHER_ENDPOINTS` and then
+    /// by any `MC_LAUNCHER_ENDPOINT_<NAME>` variables.
+    pub fn from_env() -> Self {
+        let mut endpoints = match var(ENDPOINTS_FILE_ENV) {
+            Ok(path) => match Endpoints::from_file(PathBuf::from(path)) {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("{}", err);
+                    Endpoints::default()
+                }
+            },
+            Err(_) => Endpoints::default(),
+        };
+
+        for (name, value) in endpoints.fields_mut() {
+            if let Ok(url) = var(format!("{}{}", ENDPOINT_ENV_PREFIX, name.to_uppercase())) {
+                *value = url;
+            }
+        }
+
+        endpoints
+    }
+
+    fn fields_mut(&mut self) -> Vec<(&'static str, &mut String)> {
+        vec![
+            ("minecraft_manifest", &mut self.minecraft_manifest),
+            ("jvm_manifest", &mut self.jvm_manifest),
+            ("libraries", &mut self.libraries),
+            ("lwjgl_maven", &mut self.lwjgl_maven),
+            ("resources", &mut self.resources),
+            ("forge_maven", &mut self.forge_maven),
+            ("forge_installer", &mut self.forge_installer),
+            ("forge_cli", &mut self.forge_cli),
+            ("fabric_meta", &mut self.fabric_meta),
+            ("fabric_installer_maven", &mut self.fabric_installer_maven),
+            ("optifine", &mut self.optifine),
+            ("optifine_headless", &mut self.optifine_headless),
+            ("ms_authorize", &mut self.ms_authorize),
+            ("ms_token", &mut self.ms_token),
+            ("xbox_user_auth", &mut self.xbox_user_auth),
+            ("xsts_authorize", &mut self.xsts_authorize),
+            ("minecraft_services", &mut self.minecraft_services),
+            ("mods_list", &mut self.mods_list),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints_partial_json() {
+        let endpoints = serde_json::from_str::<Endpoints>(
+            r#"{ "forge_maven": "http://localhost:8080/forge/" }"#,
+        )
+        .unwrap();
+        assert_eq!(endpoints.forge_maven, "http://localhost:8080/forge/");
+        assert_eq!(endpoints.libraries, Endpoints::default().libraries);
+    }
+
+    #[test]
+    fn test_endpoints_from_env() {
+        std::env::set_var("MC_LAUNCHER_ENDPOINT_FABRIC_META", "http://localhost:8080/fabric/");
+        let endpoints = Endpoints::from_env();
+        std::env::remove_var("MC_LAUNCHER_ENDPOINT_FABRIC_META");
+
+        assert_eq!(endpoints.fabric_meta, "http://localhost:8080/fabric/");
+        assert_eq!(endpoints.forge_maven, Endpoints::default().forge_maven);
+    }
+}
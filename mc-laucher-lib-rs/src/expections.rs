@@ -24,10 +24,41 @@ pub enum LauncherLibError {
       #[source]
       source: reqwest::Error
   },
+  #[error("Minecraft Launcher Lib | Http Status | {status} | {url}")]
+  HttpStatus {
+    url: String,
+    status: u16,
+    /// seconds from the Retry-After header
+    retry_after: Option<u64>
+  },
+  #[error("Minecraft Launcher Lib | Timeout | {0}")]
+  Timeout(String),
   #[error("Minecraft Launcher Lib | Parse Json Error | {0}")]
   PraseJsonReqwest(#[from] reqwest::Error),
   #[error("Minecraft Launcher Lib | Parse Json Error | {0}")]
   ParseJsonSerde(#[from] serde_json::Error),
+  #[error("Minecraft Launcher Lib | Hash Mismatch | {url} | expected {expected} got {actual}")]
+  HashMismatch {
+    url: String,
+    expected: String,
+    actual: String
+  },
+  #[error("Minecraft Launcher Lib | Cancelled")]
+  Cancelled,
+  #[error("Minecraft Launcher Lib | Version In Use | {id} is inherited by {}", .dependents.join(", "))]
+  VersionInUse {
+    id: String,
+    dependents: Vec<String>
+  },
+  #[error("Minecraft Launcher Lib | Unresolved Placeholder | ${{{name}}} in {argument}")]
+  UnresolvedPlaceholder {
+    name: String,
+    argument: String
+  },
+  #[error("Minecraft Launcher Lib | Invalid Arguments | {0}")]
+  InvalidArguments(String),
+  #[error("Minecraft Launcher Lib | Invalid Version Id | {0}")]
+  InvalidVersionId(String),
   #[error("Minecraft Launcher Lib | Not Found Error | {0}")]
   NotFound(String),
   #[error("Minecraft Launcher Lib | General | {0}")]
@@ -0,0 +1,424 @@
+use crate::command::get_launch_command;
+use crate::expections::{LauncherLibError, LibResult};
+use crate::json::game_settings::GameOptions;
+use crate::utils::get_classpath_separator;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Env variable the exported scripts read the access token from when it is redacted
+pub const ACCESS_TOKEN_ENV: &str = "MC_ACCESS_TOKEN";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptFormat {
+    /// `launch.sh` for sh and bash
+    Sh,
+    /// `launch.bat` for cmd
+    Bat,
+    /// `launch.ps1` for powershell
+    Ps1,
+    /// the command as json, for looking at the classpath and arguments
+    Json,
+}
+impl ScriptFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ScriptFormat::Sh => "launch.sh",
+            ScriptFormat::Bat => "launch.bat",
+            ScriptFormat::Ps1 => "launch.ps1",
+            ScriptFormat::Json => "launch.json",
+        }
+    }
+}
+
+/// A piece of an argument, either written out or read from an env variable when the script runs
+enum Part<'a> {
+    Text(&'a str),
+    Env(&'a str),
+}
+
+/// A resolved launch command that can be written out as a script
+#[derive(Debug, Clone, Serialize)]
+pub struct LaunchExport {
+    pub java: String,
+    pub arguments: Vec<String>,
+    /// the game directory, which the game expects to be started in
+    pub working_directory: PathBuf,
+    /// variables the script sets before starting java
+    pub environment: BTreeMap<String, String>,
+    /// the entries of the `-cp` argument, only informational
+    pub classpath: Vec<String>,
+    /// name of the env variable that holds the access token, `None` when it is written out
+    pub token_env: Option<String>,
+    #[serde(skip)]
+    token: Option<String>,
+}
+
+/// Resolves the launch command of the version the way `Client::start` would. With
+/// `redact_token` the access token is left out and read from [`ACCESS_TOKEN_ENV`] instead.
+pub async fn export_launch_command(
+    version: String,
+    mc_dir: PathBuf,
+    options: &mut GameOptions,
+    redact_token: bool,
+) -> LibResult<LaunchExport> {
+    let (java, arguments) = match get_launch_command(version, mc_dir.clone(), options).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    let classpath = match &options.classpath {
+        Some(value) => value
+            .split(get_classpath_separator().as_str())
+            .map(|entry| entry.to_string())
+            .collect(),
+        None => vec![],
+    };
+
+    let token = options.token.clone().filter(|token| !token.is_empty());
+    Ok(LaunchExport {
+        java,
+        arguments,
+        working_directory: options.game_directory.clone().unwrap_or(mc_dir),
+        environment: BTreeMap::new(),
+        classpath,
+        token_env: match (&token, redact_token) {
+            (Some(_), true) => Some(ACCESS_TOKEN_ENV.to_string()),
+            _ => None,
+        },
+        token,
+    })
+}
+
+fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quotes for the way java splits its windows command line, with `%` doubled so cmd leaves it alone
+fn quote_bat(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in value.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        if c == '%' {
+            quoted.push_str("%%");
+        } else {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+    quoted
+}
+
+fn quote_ps1(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl LaunchExport {
+    /// Adds a variable the script sets before starting java
+    pub fn set_env(&mut self, name: &str, value: &str) -> &mut Self {
+        self.environment.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// The argument split around the redacted token
+    fn get_parts<'a>(&'a self, argument: &'a str) -> Vec<Part<'a>> {
+        let (token, env) = match (&self.token, &self.token_env) {
+            (Some(token), Some(env)) => (token.as_str(), env.as_str()),
+            _ => return vec![Part::Text(argument)],
+        };
+        let mut parts = vec![];
+        let mut rest = argument;
+        while let Some(at) = rest.find(token) {
+            if at > 0 {
+                parts.push(Part::Text(&rest[..at]));
+            }
+            parts.push(Part::Env(env));
+            rest = &rest[at + token.len()..];
+        }
+        if !rest.is_empty() || parts.is_empty() {
+            parts.push(Part::Text(rest));
+        }
+        parts
+    }
+
+    fn render_sh(&self) -> String {
+        let mut script = String::from("#!/bin/sh\nset -e\n");
+        if let Some(env) = &self.token_env {
+            script.push_str(&format!(
+                ": \"${{{env}:?Set {env} to the access token}}\"\n",
+                env = env
+            ));
+        }
+        for (name, value) in &self.environment {
+            script.push_str(&format!("export {}={}\n", name, quote_sh(value)));
+        }
+        script.push_str(&format!(
+            "cd {}\nexec {}",
+            quote_sh(&self.working_directory.to_string_lossy()),
+            quote_sh(&self.java)
+        ));
+        for argument in &self.arguments {
+            script.push_str(" \\\n  ");
+            for part in self.get_parts(argument) {
+                match part {
+                    Part::Text(text) => script.push_str(&quote_sh(text)),
+                    Part::Env(env) => script.push_str(&format!("\"${}\"", env)),
+                }
+            }
+        }
+        script.push('\n');
+        script
+    }
+
+    fn render_bat(&self) -> String {
+        let mut script = String::from("@echo off\r\nsetlocal\r\n");
+        if let Some(env) = &self.token_env {
+            script.push_str(&format!(
+                "if not defined {env} (\r\n  echo Set {env} to the access token\r\n  exit /b 1\r\n)\r\n",
+                env = env
+            ));
+        }
+        for (name, value) in &self.environment {
+            script.push_str(&format!(
+                "set \"{}={}\"\r\n",
+                name,
+                value.replace('%', "%%")
+            ));
+        }
+        script.push_str(&format!(
+            "cd /d {}\r\n{}",
+            quote_bat(&self.working_directory.to_string_lossy()),
+            quote_bat(&self.java)
+        ));
+        for argument in &self.arguments {
+            script.push_str(" ^\r\n  ");
+            for part in self.get_parts(argument) {
+                match part {
+                    Part::Text(text) => script.push_str(&quote_bat(text)),
+                    Part::Env(env) => script.push_str(&format!("%{}%", env)),
+                }
+            }
+        }
+        script.push_str("\r\n");
+        script
+    }
+
+    fn render_ps1(&self) -> String {
+        let mut script = String::from("$ErrorActionPreference = 'Stop'\n");
+        if let Some(env) = &self.token_env {
+            script.push_str(&format!(
+                "if (-not $env:{env}) {{ throw 'Set {env} to the access token' }}\n",
+                env = env
+            ));
+        }
+        for (name, value) in &self.environment {
+            script.push_str(&format!("$env:{} = {}\n", name, quote_ps1(value)));
+        }
+        script.push_str(&format!(
+            "Set-Location -LiteralPath {}\n$arguments = @(\n",
+            quote_ps1(&self.working_directory.to_string_lossy())
+        ));
+        for argument in &self.arguments {
+            let parts: Vec<String> = self
+                .get_parts(argument)
+                .into_iter()
+                .map(|part| match part {
+                    Part::Text(text) => quote_ps1(text),
+                    Part::Env(env) => format!("$env:{}", env),
+                })
+                .collect();
+            script.push_str(&format!("  ({})\n", parts.join(" + ")));
+        }
+        script.push_str(&format!(
+            ")\n& {} @arguments\nexit $LASTEXITCODE\n",
+            quote_ps1(&self.java)
+        ));
+        script
+    }
+
+    fn render_json(&self) -> LibResult<String> {
+        let mut redacted = self.clone();
+        if let Some(env) = &self.token_env {
+            redacted.arguments = self
+                .arguments
+                .iter()
+                .map(|argument| {
+                    self.get_parts(argument)
+                        .into_iter()
+                        .map(|part| match part {
+                            Part::Text(text) => text.to_string(),
+                            Part::Env(_) => format!("${{env:{}}}", env),
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+        match serde_json::to_string_pretty(&redacted) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
+        }
+    }
+
+    pub fn render(&self, format: ScriptFormat) -> LibResult<String> {
+        match format {
+            ScriptFormat::Sh => Ok(self.render_sh()),
+            ScriptFormat::Bat => Ok(self.render_bat()),
+            ScriptFormat::Ps1 => Ok(self.render_ps1()),
+            ScriptFormat::Json => self.render_json(),
+        }
+    }
+
+    /// Writes the script into `dir` under the format's file name and returns its path
+    pub fn write(&self, format: ScriptFormat, dir: &Path) -> LibResult<PathBuf> {
+        let contents = self.render(format)?;
+        let path = dir.join(format.file_name());
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to create directory".into(),
+            });
+        }
+        if let Err(err) = std::fs::write(&path, contents) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to write launch script".into(),
+            });
+        }
+        #[cfg(unix)]
+        if format == ScriptFormat::Sh {
+            use std::os::unix::fs::PermissionsExt;
+            if let Err(err) =
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to make launch script executable".into(),
+                });
+            }
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get_export(mc_dir: &Path, redact_token: bool) -> LaunchExport {
+        let version_dir = mc_dir.join("versions").join("1.20.1");
+        std::fs::create_dir_all(&version_dir).unwrap();
+        std::fs::copy(
+            "tests/fixtures/rules/1.20.1.json",
+            version_dir.join("1.20.1.json"),
+        )
+        .unwrap();
+        // prints every argument it gets on a line of its own
+        let java = mc_dir.join("java.sh");
+        std::fs::write(
+            &java,
+            "#!/bin/sh\nfor arg in \"$@\"; do echo \"$arg\"; done\n",
+        )
+        .unwrap();
+
+        let mut options = GameOptions {
+            executable_path: Some(java),
+            jvm_arguments: Some(r#""-Dmotd=it's 100% $HOME `x`""#.into()),
+            username: Some("Steve".into()),
+            uuid: Some("uuid".into()),
+            token: Some("secret-token".into()),
+            ..Default::default()
+        };
+        export_launch_command(
+            "1.20.1".into(),
+            mc_dir.to_path_buf(),
+            &mut options,
+            redact_token,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_export_redacts_token() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-export-{}", uuid::Uuid::new_v4()));
+        let export = get_export(&mc_dir, true).await;
+        assert!(export.arguments.contains(&"secret-token".to_string()));
+
+        for format in [
+            ScriptFormat::Sh,
+            ScriptFormat::Bat,
+            ScriptFormat::Ps1,
+            ScriptFormat::Json,
+        ] {
+            let script = export.render(format).unwrap();
+            assert!(!script.contains("secret-token"), "{:?}", format);
+            assert!(script.contains(ACCESS_TOKEN_ENV), "{:?}", format);
+        }
+        assert!(export
+            .render(ScriptFormat::Bat)
+            .unwrap()
+            .contains(r#""-Dmotd=it's 100%% $HOME `x`""#));
+        assert!(export
+            .render(ScriptFormat::Ps1)
+            .unwrap()
+            .contains("('-Dmotd=it''s 100% $HOME `x`')"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export.render(ScriptFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["token_env"], ACCESS_TOKEN_ENV);
+        assert!(json["classpath"].as_array().unwrap().len() > 1);
+
+        let plain = get_export(&mc_dir, false).await;
+        assert!(plain
+            .render(ScriptFormat::Sh)
+            .unwrap()
+            .contains("'secret-token'"));
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_export_sh_runs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mc_dir = std::env::temp_dir().join(format!("mc-export-{}", uuid::Uuid::new_v4()));
+        let mut export = get_export(&mc_dir, true).await;
+        std::fs::set_permissions(&export.java, std::fs::Permissions::from_mode(0o755)).unwrap();
+        export.set_env("LAUNCH_TEST", "it's set");
+        let script = export.write(ScriptFormat::Sh, &mc_dir).unwrap();
+
+        let output = std::process::Command::new(&script)
+            .env(ACCESS_TOKEN_ENV, "from-env")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(lines[0], "-Dmotd=it's 100% $HOME `x`");
+        let at = lines
+            .iter()
+            .position(|line| line == "--accessToken")
+            .unwrap();
+        assert_eq!(lines[at + 1], "from-env");
+
+        let missing = std::process::Command::new(&script)
+            .env_remove(ACCESS_TOKEN_ENV)
+            .output()
+            .unwrap();
+        assert!(!missing.status.success());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
@@ -1,9 +1,13 @@
+use crate::cancel::{ run_command, CancellationToken };
 use crate::expections::{ LauncherLibError,LibResult};
-use crate::utils::{get_http_client, download_file };
+use crate::context::get_context;
+use crate::utils::{get_json, download_file };
 use crate::vanilla::get_vanilla_versions;
 use crate::mod_utiles::get_metadata;
+use crate::progress::PHASE_LOADER;
 use crate::runtime::get_exectable_path;
-use crate::install::install_minecraft_version;
+use crate::install::{ install_minecraft_version, is_version_installed };
+use crate::journal::open_journal;
 use crate::json::{
     runtime::MinecraftJavaRuntime,
     install::{ Callback, Event }
@@ -13,9 +17,6 @@ use serde::{ Deserialize, Serialize };
 use tokio::process::{Command};
 use std::process::Stdio;
 
-const FABRIC_API_ROOT: &str = "https://meta.fabricmc.net/v2/versions/";
-const FABRIC_INSTALLER_MAVEN: &str = "https://maven.fabricmc.net/net/fabricmc/fabric-installer/";
-
 #[derive(Serialize,Deserialize, Debug,Clone)]
 pub struct FabricVersionItem {
     pub version: String,
@@ -28,23 +29,7 @@ struct FabricLoaderVersion {
 }
 
 pub async fn get_supported_mc_versions() -> LibResult<Vec<FabricVersionItem>> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get(format!("{}game",FABRIC_API_ROOT).as_str()).send().await {
-        Ok(value) => {
-            match value.json::<Vec<FabricVersionItem>>().await {
-                Ok(versions) => Ok(versions),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        }
-        Err(error) => Err(LauncherLibError::HTTP{ 
-            source: error,
-            msg: "Failed to maker request".into()
-        })
-    }
+    get_json::<Vec<FabricVersionItem>>(format!("{}game",get_context().endpoints.fabric_meta).as_str()).await
 }
 
 pub async fn get_supported_stable_versions() -> LibResult<Vec<FabricVersionItem>> {
@@ -84,23 +69,7 @@ pub async fn is_supported(mc_version: String) -> LibResult<bool> {
 }
 
 async fn get_loader_versions() -> LibResult<Vec<FabricLoaderVersion>> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get(format!("{}loader",FABRIC_API_ROOT).as_str()).send().await {
-        Ok(value) => {
-            match value.json::<Vec<FabricLoaderVersion>>().await {
-                Ok(versions) => Ok(versions),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make http request".into()
-        })
-    }
+    get_json::<Vec<FabricLoaderVersion>>(format!("{}loader",get_context().endpoints.fabric_meta).as_str()).await
 }
 
 async fn get_latest_loader_version() -> LibResult<String> {
@@ -115,15 +84,13 @@ async fn get_latest_loader_version() -> LibResult<String> {
 }
 
 async fn get_latest_installer() -> LibResult<String> {
-    match get_metadata(FABRIC_INSTALLER_MAVEN).await {
+    match get_metadata(&get_context().endpoints.fabric_installer_maven).await {
         Ok(value) => Ok(value.versioning.release.clone()),
         Err(err) => return Err(err)
     }
 }
 
-pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>, callback: Callback, java: Option<PathBuf>, temp_path: PathBuf) -> LibResult<()> {
-
-    let mc_path = mc_dir.join("versions").join(mc.clone()).join(format!("{}.json",mc));
+pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>, callback: Callback<'_>, cancel: &CancellationToken, java: Option<PathBuf>, temp_path: PathBuf) -> LibResult<()> {
 
     // check if given mc version is a offical version.
     match get_vanilla_versions().await {
@@ -155,17 +122,13 @@ pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>,
         }
     };
 
-    if !mc_path.is_file() {
-        if let Err(err) = install_minecraft_version(mc.clone(), mc_dir.clone(), callback).await {
-            return Err(err);
-        }
+    if !is_version_installed(&mc, &mc_dir) {
+        install_minecraft_version(mc.clone(), mc_dir.clone(), callback, cancel).await?;
     }
 
     let fabric_mc = format!("fabric-loader-{}-{}",loaderv,mc).to_string();
 
-    let fabric = mc_dir.join("versions").join(fabric_mc.clone()).join(format!("{}.json",fabric_mc));
-
-    if fabric.is_file() {
+    if is_version_installed(&fabric_mc, &mc_dir) {
         return Ok(());
     }
 
@@ -175,17 +138,16 @@ pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>,
     };
 
     let installer_url = format!("{maven}{version}/fabric-installer-{version}.jar",
-        maven=FABRIC_INSTALLER_MAVEN,
+        maven=get_context().endpoints.fabric_installer_maven,
         version=installer_version
     ).to_string();
 
     let installer_file = temp_path.join("fabric-install.js");
 
-    callback(Event::progress(0, 1));
-    if let Err(err) = download_file(installer_url, installer_file.clone(), callback, None, false).await {
-        return Err(err);
-    }
-    callback(Event::progress(1, 1));
+    callback.report(Event::phase(PHASE_LOADER, 0));
+    callback.report(Event::progress(0, 1));
+    download_file(installer_url, installer_file.clone(), callback, cancel, None, false).await?;
+    callback.report(Event::progress(1, 1));
 
     let exec: String = match java {
         Some(value) => value.to_str().expect("Failed to make string").into(),
@@ -215,11 +177,17 @@ pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>,
         "-noprofile"
     ];
 
-    match Command::new(exec).args(args).stdout(Stdio::inherit()).output().await {
+    let journal = open_journal(&mc_dir);
+    let loader_step = format!("{}:loader",fabric_mc);
+    journal.start_step(&loader_step)?;
+
+    match run_command(Command::new(exec).args(args).stdout(Stdio::inherit()), cancel).await {
         Ok(value) => {
-            callback(Event::Status(String::from_utf8_lossy(&value.stderr).to_string()));
-            callback(Event::Status(String::from_utf8_lossy(&value.stdout).to_string()));
-            callback(Event::Status(value.status.to_string()));
+            journal.complete_step(&loader_step)?;
+
+            callback.report(Event::Status(String::from_utf8_lossy(&value.stderr).to_string()));
+            callback.report(Event::Status(String::from_utf8_lossy(&value.stdout).to_string()));
+            callback.report(Event::Status(value.status.to_string()));
 
             if let Err(err) = std::fs::remove_file(installer_file) {
                 return Err(LauncherLibError::OS {
@@ -229,32 +197,45 @@ pub async fn install_fabric(mc: String, mc_dir: PathBuf, loader: Option<String>,
             }
 
         }
-        Err(err) => return Err(LauncherLibError::OS {
-            source: err,
-            msg: "Failed to run command".into()
-        })
+        Err(err) => return Err(err)
     };
 
-    install_minecraft_version(fabric_mc, mc_dir, callback).await
+    install_minecraft_version(fabric_mc, mc_dir, callback, cancel).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::{ fixture_context, with_context };
+
+    #[tokio::test]
+    async fn test_fabric_versions_from_fixture() {
+        with_context(fixture_context("fabric"), async {
+            assert!(is_supported("1.18.1".into()).await.unwrap());
+            assert!(!is_supported("1.56.1".into()).await.unwrap());
+            assert_eq!(get_supported_stable_versions().await.unwrap().len(),2);
+            assert_eq!(get_latest_supported().await.unwrap(),"22w03a");
+            assert_eq!(get_latest_loader_version().await.unwrap(),"0.12.12");
+        }).await;
+    }
 
     #[tokio::test]
     async fn test_get_loader_versions() {
-        match get_loader_versions().await {
-            Ok(value) => println!("{:#?}",value),
-            Err(err) => eprintln!("{}",err)
-        }
+        with_context(fixture_context("fabric"), async {
+            let loaders = get_loader_versions().await.unwrap();
+            let versions: Vec<&str> = loaders.iter().map(|loader| loader.version.as_str()).collect();
+            assert_eq!(versions,vec!["0.12.12","0.12.11"]);
+        }).await;
     }
 
     #[tokio::test]
     async fn test_get_supported_mc_versions() {
-        match get_supported_mc_versions().await {
-            Ok(value) => println!("{:#?}",value),
-            Err(err) => eprintln!("{}",err)
-        }
+        with_context(fixture_context("fabric"), async {
+            let versions = get_supported_mc_versions().await.unwrap();
+            assert_eq!(versions.len(),3);
+            assert_eq!(versions[0].version,"22w03a");
+            assert!(!versions[0].stable);
+            assert!(versions.iter().any(|version| version.version == "1.18.1" && version.stable));
+        }).await;
     }
 }
\ No newline at end of file
@@ -1,11 +1,15 @@
+use crate::cancel::{run_command, CancellationToken};
+use crate::context::get_context;
 use crate::expections::{LauncherLibError, LibResult};
-use crate::install::install_minecraft_version;
+use crate::install::{install_minecraft_version, is_version_installed};
+use crate::journal::open_journal;
 use crate::json::install::VersionManifest;
 use crate::json::{
     install::{Callback, Event},
     runtime::MinecraftJavaRuntime,
 };
 use crate::mod_utiles::get_metadata;
+use crate::progress::PHASE_LOADER;
 use crate::runtime::{does_runtime_exist, get_exectable_path, install_jvm_runtime};
 use crate::utils::{download_file, read_manifest_inherit};
 use log::info;
@@ -14,13 +18,8 @@ use std::process::Stdio;
 use tokio::fs::remove_file;
 use tokio::process::Command;
 
-const FORGE_DOWNLOAD_URL: &str = "https://files.minecraftforge.net/maven/net/minecraftforge/forge/{version}/forge-{version}-installer.jar";
-const FORGE_HEADLESS_URL: &str =
-    "https://github.com/TeamKun/ForgeCLI/releases/download/1.0.1/ForgeCLI-1.0.1-all.jar";
-const FORGE_MAVEN_ROOT: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge/";
-
 pub async fn get_forge_versions() -> LibResult<Vec<String>> {
-    match get_metadata(FORGE_MAVEN_ROOT).await {
+    match get_metadata(&get_context().endpoints.forge_maven).await {
         Ok(value) => Ok(value.versioning.versions.version),
         Err(err) => return Err(err),
     }
@@ -74,7 +73,8 @@ pub async fn install_forge(
     mc: String,
     mc_dir: PathBuf,
     temp_path: PathBuf,
-    callback: Callback,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
     cache_path: Option<PathBuf>,
     loader: Option<String>,
     java: Option<PathBuf>,
@@ -134,16 +134,9 @@ pub async fn install_forge(
         temp_path.clone()
     };
 
-    callback(Event::Status("Checking for vanilla minecraft".into()));
-    if !mc_dir
-        .join("versions")
-        .join(mc.clone())
-        .join(format!("{}.json", mc.clone()))
-        .is_file()
-    {
-        if let Err(err) = install_minecraft_version(mc.clone(), mc_dir.clone(), callback).await {
-            return Err(err);
-        }
+    callback.report(Event::Status("Checking for vanilla minecraft".into()));
+    if !is_version_installed(&mc, &mc_dir) {
+        install_minecraft_version(mc.clone(), mc_dir.clone(), callback, cancel).await?;
     }
 
     let forge_id = format!("{}-forge-{}", mc.clone(), loader_version.clone()).to_string();
@@ -151,12 +144,16 @@ pub async fn install_forge(
     let headless_file = headless_path.join("ForgeCLI.jar");
     let forge_jar_file = forge_jar.join(format!("{}.jar", forge_id.clone()));
 
-    callback(Event::Status("Downloading ForgeCLI".into()));
-    callback(Event::progress(0, 2));
+    let endpoints = get_context().endpoints.clone();
+
+    callback.report(Event::phase(PHASE_LOADER, 0));
+    callback.report(Event::Status("Downloading ForgeCLI".into()));
+    callback.report(Event::progress(0, 2));
     if let Err(err) = download_file(
-        FORGE_HEADLESS_URL.into(),
+        endpoints.forge_cli.clone(),
         headless_file.clone(),
         callback,
+        cancel,
         None,
         false,
     )
@@ -164,20 +161,26 @@ pub async fn install_forge(
     {
         return Err(err);
     }
-    callback(Event::progress(1, 2));
+    callback.report(Event::progress(1, 2));
 
-    let forge_url = FORGE_DOWNLOAD_URL
+    let forge_url = endpoints
+        .forge_installer
         .replace(
             "{version}",
             format!("{}-{}", mc.clone(), loader_version.clone()).as_str(),
         )
         .to_string();
-    callback(Event::Status("Downloading Forge".into()));
-    if let Err(err) = download_file(forge_url, forge_jar_file.clone(), callback, None, false).await
-    {
-        return Err(err);
-    }
-    callback(Event::progress(2, 2));
+    callback.report(Event::Status("Downloading Forge".into()));
+    download_file(
+        forge_url,
+        forge_jar_file.clone(),
+        callback,
+        cancel,
+        None,
+        false,
+    )
+    .await?;
+    callback.report(Event::progress(2, 2));
 
     // Manifest
     let version_manifest = mc_dir
@@ -195,7 +198,7 @@ pub async fn install_forge(
             Ok(value) => {
                 if !value {
                     if let Err(err) =
-                        install_jvm_runtime(java.component, mc_dir.clone(), callback).await
+                        install_jvm_runtime(java.component, mc_dir.clone(), callback, cancel).await
                     {
                         return Err(err);
                     }
@@ -225,19 +228,21 @@ pub async fn install_forge(
         mc_dir.to_str().expect("Failed to convert to str"),
     ];
 
-    match Command::new(exec)
-        .args(args)
-        .stdout(Stdio::inherit())
-        .output()
-        .await
-    {
+    // a forge json left by an installer that didn't finish must not be taken as installed
+    let journal = open_journal(&mc_dir);
+    let loader_step = format!("{}:loader", forge_id);
+    journal.start_step(&loader_step)?;
+
+    match run_command(Command::new(exec).args(args).stdout(Stdio::inherit()), cancel).await {
         Ok(output) => {
+            journal.complete_step(&loader_step)?;
+
             info!("Stderr: {}", String::from_utf8_lossy(&output.stderr));
             info!("Stdout: {}", String::from_utf8_lossy(&output.stdout));
             info!("Status: {}", output.status);
 
-            callback(Event::Status("Starting cleanup".into()));
-            callback(Event::progress(0, 2));
+            callback.report(Event::Status("Starting cleanup".into()));
+            callback.report(Event::progress(0, 2));
 
             if !cache_headless {
                 if let Err(err) = remove_file(headless_file).await {
@@ -247,7 +252,7 @@ pub async fn install_forge(
                     });
                 }
             }
-            callback(Event::progress(1, 2));
+            callback.report(Event::progress(1, 2));
             if !cache_installer {
                 if let Err(err) = remove_file(forge_jar_file).await {
                     return Err(LauncherLibError::OS {
@@ -256,14 +261,9 @@ pub async fn install_forge(
                     });
                 }
             }
-            callback(Event::progress(2, 2));
-        }
-        Err(err) => {
-            return Err(LauncherLibError::OS {
-                source: err,
-                msg: "Failed to run command".into(),
-            })
+            callback.report(Event::progress(2, 2));
         }
+        Err(err) => return Err(err),
     };
 
     Ok(())
@@ -272,56 +272,41 @@ pub async fn install_forge(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::{fixture_context, with_context};
 
     #[tokio::test]
-    async fn test_get_forge_versions() {
-        match get_forge_versions().await {
-            Ok(value) => println!("{:#?}", value),
-            Err(err) => eprintln!("{}", err),
-        }
+    async fn test_forge_versions_from_fixture() {
+        with_context(fixture_context("forge"), async {
+            let versions = get_forge_versions().await.unwrap();
+            assert_eq!(versions.len(), 4);
+            assert!(is_supported("1.18.1".into()).await.unwrap());
+            assert!(!is_supported("1.56.1".into()).await.unwrap());
+            assert!(vaild_forge_version("39.0.75".into(), Some("1.18.1".into()))
+                .await
+                .unwrap());
+            assert!(vaild_forge_version("1.0.0".into(), None).await.is_err());
+        })
+        .await;
     }
 
     #[tokio::test]
     async fn test_forge_is_supported() {
-        match is_supported("1.18.1".into()).await {
-            Ok(value) => {
-                assert_eq!(value, true);
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                panic!();
-            }
-        }
-        match is_supported("1.56.1".into()).await {
-            Ok(value) => {
-                assert_eq!(value, false);
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                panic!();
-            }
-        }
+        with_context(fixture_context("forge"), async {
+            assert!(is_supported("1.18.1".into()).await.unwrap());
+            assert!(is_supported("1.16.5".into()).await.unwrap());
+            assert!(!is_supported("1.56.1".into()).await.unwrap());
+        })
+        .await;
     }
 
     #[tokio::test]
     async fn test_vaild_forge_version() {
-        match vaild_forge_version("39.0.75".into(), None).await {
-            Ok(value) => {
-                assert_eq!(value, true);
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                panic!();
-            }
-        }
-        match vaild_forge_version("39.0.75".into(), Some("1.18.1".into())).await {
-            Ok(value) => {
-                assert_eq!(value, true);
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                panic!();
-            }
-        }
+        with_context(fixture_context("forge"), async {
+            assert!(vaild_forge_version("39.0.75".into(), None).await.unwrap());
+            assert!(vaild_forge_version("39.0.75".into(), Some("1.18.1".into()))
+                .await
+                .unwrap());
+        })
+        .await;
     }
 }
@@ -0,0 +1,328 @@
+use crate::expections::{LauncherLibError, LibResult};
+use crate::install::{get_library_files, IndexAssetsMap};
+use crate::journal::open_journal;
+use crate::json::install::VersionManifest;
+use crate::utils::get_local_installed_versions;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files and directories that no installed version uses
+#[derive(Debug, Default)]
+pub struct GcPlan {
+    mc_dir: PathBuf,
+    /// files and directories that will be removed
+    pub paths: Vec<PathBuf>,
+    /// ids of the version directories that will be removed
+    pub versions: Vec<String>,
+    /// java runtime components that will be removed
+    pub runtimes: Vec<String>,
+    /// version directories whose json could not be read. Their libraries, assets
+    /// and runtime are unknown, so only broken version directories are collected
+    pub unreadable: Vec<String>,
+    /// bytes that deleting frees
+    pub freed_bytes: u64,
+}
+
+/// Bytes taken by a file or everything below a directory
+pub(crate) fn get_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(value) => value,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| get_size(&entry.path()))
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+fn list_dir(path: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn get_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Directories of the libraries the versions list, relative to the libraries folder
+fn get_library_dirs(versions: &[VersionManifest]) -> HashSet<PathBuf> {
+    let mut dirs = HashSet::new();
+    for version in versions {
+        for library in &version.libraries {
+            if let Ok((parts, _, _)) = get_library_files(library) {
+                dirs.insert(parts.iter().collect::<PathBuf>());
+            }
+            if let Some(downloads) = &library.downloads {
+                let files = downloads
+                    .classifiers
+                    .iter()
+                    .flat_map(|classifiers| classifiers.values())
+                    .chain(std::iter::once(&downloads.artifact));
+                for file in files {
+                    if let Some(parent) =
+                        file.path.as_ref().and_then(|path| Path::new(path).parent())
+                    {
+                        dirs.insert(parent.to_path_buf());
+                    }
+                }
+            }
+        }
+    }
+    dirs
+}
+
+/// Collects the directories below `dir` that hold files
+fn find_file_dirs(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = list_dir(dir);
+    if entries.iter().any(|entry| entry.is_file()) {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    for entry in entries {
+        if entry.is_dir() {
+            find_file_dirs(&entry, found);
+        }
+    }
+}
+
+/// Works out what can be removed from `mc_dir`: version directories that hold no
+/// readable version, library versions superseded by the ones in use, asset objects
+/// and indexes no version points to, and runtimes no version needs. Versions listed
+/// in `keep` and the versions they inherit from are never removed.
+///
+/// Libraries are only removed when another version of the same artifact is in use,
+/// so files that loader installers generate next to the libraries are left alone.
+pub async fn plan_gc(mc_dir: PathBuf, keep: &[String]) -> LibResult<GcPlan> {
+    let versions = match get_local_installed_versions(mc_dir.clone()).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    let mut plan = GcPlan {
+        mc_dir: mc_dir.clone(),
+        ..Default::default()
+    };
+
+    let mut live: HashSet<String> = keep.iter().cloned().collect();
+    for version in &versions {
+        live.insert(version.id.clone());
+        if let Some(parent) = &version.inherits_from {
+            live.insert(parent.clone());
+        }
+    }
+
+    for dir in list_dir(&mc_dir.join("versions")) {
+        if !dir.is_dir() {
+            continue;
+        }
+        let id = get_name(&dir);
+        if live.contains(&id) {
+            continue;
+        }
+        // a json that didn't parse may still be a version someone plays
+        if dir.join(format!("{}.json", id)).is_file() {
+            plan.unreadable.push(id);
+            continue;
+        }
+        plan.versions.push(id);
+        plan.paths.push(dir);
+    }
+
+    if plan.unreadable.is_empty() {
+        let used = get_library_dirs(&versions);
+        let artifacts: HashSet<&Path> = used.iter().filter_map(|dir| dir.parent()).collect();
+        let libraries = mc_dir.join("libraries");
+        let mut dirs = vec![];
+        find_file_dirs(&libraries, &mut dirs);
+        for dir in dirs {
+            let relative = match dir.strip_prefix(&libraries) {
+                Ok(value) => value.to_path_buf(),
+                Err(_) => continue,
+            };
+            let superseded = match relative.parent() {
+                Some(artifact) => artifacts.contains(artifact),
+                None => false,
+            };
+            if superseded && !used.contains(&relative) {
+                plan.paths.push(dir);
+            }
+        }
+
+        collect_assets(&mc_dir, &versions, &mut plan);
+
+        let runtimes: HashSet<String> = versions
+            .iter()
+            .filter_map(|version| version.java_version.as_ref())
+            .map(|java| java.component.to_string())
+            .collect();
+        for dir in list_dir(&mc_dir.join("runtime")) {
+            let component = get_name(&dir);
+            if dir.is_dir() && !runtimes.contains(&component) {
+                plan.runtimes.push(component);
+                plan.paths.push(dir);
+            }
+        }
+    }
+
+    plan.freed_bytes = plan.paths.iter().map(|path| get_size(path)).sum();
+    Ok(plan)
+}
+
+fn collect_assets(mc_dir: &Path, versions: &[VersionManifest], plan: &mut GcPlan) {
+    let indexes_dir = mc_dir.join("assets").join("indexes");
+    let mut indexes = HashSet::new();
+    let mut objects = HashSet::new();
+    for assets in versions
+        .iter()
+        .filter_map(|version| version.assets.as_ref())
+    {
+        let index = format!("{}.json", assets);
+        let parsed = fs::read_to_string(indexes_dir.join(&index))
+            .ok()
+            .and_then(|raw| serde_json::from_str::<IndexAssetsMap>(&raw).ok());
+        match parsed {
+            Some(value) => objects.extend(value.objects.into_values().map(|object| object.hash)),
+            // which objects this version needs is unknown
+            None => return,
+        }
+        indexes.insert(index);
+    }
+
+    for index in list_dir(&indexes_dir) {
+        if index.is_file() && !indexes.contains(&get_name(&index)) {
+            plan.paths.push(index);
+        }
+    }
+    for prefix in list_dir(&mc_dir.join("assets").join("objects")) {
+        for object in list_dir(&prefix) {
+            if object.is_file() && !objects.contains(&get_name(&object)) {
+                plan.paths.push(object);
+            }
+        }
+    }
+}
+
+impl GcPlan {
+    /// Removes everything in the plan and returns the bytes that were freed
+    pub fn delete(&self) -> LibResult<u64> {
+        let journal = open_journal(&self.mc_dir);
+        let mut freed = 0;
+        for path in &self.paths {
+            let size = get_size(path);
+            let result = if path.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            };
+            match result {
+                Ok(_) => freed += size,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: format!("Failed to remove {}", path.display()),
+                    })
+                }
+            }
+            journal.forget_dir(path);
+            journal.forget_file(path);
+        }
+
+        // a later install must not skip steps whose files are gone
+        for version in &self.versions {
+            journal.reset_version(version)?;
+        }
+        for runtime in &self.runtimes {
+            journal.reset_step(&format!("runtime:{}", runtime))?;
+        }
+        journal.save()?;
+        Ok(freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancellationToken;
+    use crate::context::{fixture_context, with_context};
+    use crate::install::install_minecraft_version;
+    use crate::json::install::Event;
+
+    fn write(path: PathBuf, contents: &str) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_gc_removes_unreferenced_files() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-gc-{}", uuid::Uuid::new_v4()));
+        let result = with_context(
+            fixture_context("install"),
+            install_minecraft_version(
+                "fixture-1.0".into(),
+                mc_dir.clone(),
+                &|_: Event| {},
+                &CancellationToken::new(),
+            ),
+        )
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        let libraries = mc_dir.join("libraries");
+        let old_library = write(libraries.join("org/example/demo/0.9/demo-0.9.jar"), "old");
+        let generated = write(
+            libraries.join("net/minecraft/client/1/client-1-srg.jar"),
+            "srg",
+        );
+        let old_object = write(mc_dir.join("assets/objects/ab/abcdef"), "object");
+        let old_index = write(mc_dir.join("assets/indexes/old.json"), "{}");
+        let runtime = write(mc_dir.join("runtime/jre-legacy/linux/.version"), "8");
+        let broken = write(mc_dir.join("versions/broken/natives/lib.so"), "so");
+        let pinned = write(mc_dir.join("versions/pinned/pinned.jar"), "jar");
+
+        let plan = plan_gc(mc_dir.clone(), &["pinned".to_string()])
+            .await
+            .unwrap();
+        assert!(plan.unreadable.is_empty());
+        assert_eq!(plan.versions, vec!["broken".to_string()]);
+        assert_eq!(plan.runtimes, vec!["jre-legacy".to_string()]);
+        assert_eq!(plan.paths.len(), 5);
+        assert_eq!(plan.freed_bytes, 3 + 6 + 2 + 1 + 2);
+        // nothing is removed until the plan is confirmed
+        assert!(old_library.is_file());
+
+        assert_eq!(plan.delete().unwrap(), plan.freed_bytes);
+        for path in [old_library, old_object, old_index, runtime, broken] {
+            assert!(!path.exists(), "{}", path.display());
+        }
+        assert!(generated.is_file());
+        assert!(pinned.is_file());
+        assert!(libraries
+            .join("org/example/demo/1.0/demo-1.0.jar")
+            .is_file());
+        assert!(mc_dir
+            .join("assets/objects/3a/3a1d589e96137edd0616bcf57c0ebfe1ef2c76b2")
+            .is_file());
+        assert!(mc_dir.join("assets/indexes/fixture.json").is_file());
+        assert!(mc_dir
+            .join("versions/fixture-1.0/fixture-1.0.jar")
+            .is_file());
+
+        let _ = fs::remove_dir_all(mc_dir);
+    }
+}
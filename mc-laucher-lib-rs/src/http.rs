@@ -0,0 +1,213 @@
+use crate::expections::{LauncherLibError, LibResult};
+use std::env::var;
+use std::fs::read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Env variable with a proxy url (`http://`, `https://` or `socks5://`) used for every request
+pub const PROXY_ENV: &str = "MC_LAUNCHER_PROXY";
+/// Env variable with extra root certificate files, separated like `PATH`
+pub const EXTRA_CA_ENV: &str = "MC_LAUNCHER_EXTRA_CA";
+
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+impl ProxyConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// Settings for the http client shared by the whole library
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    /// used for all requests instead of the system proxy
+    pub proxy: Option<ProxyConfig>,
+    /// use the proxy from `HTTP_PROXY`/`HTTPS_PROXY` or the os settings when no proxy is set
+    pub system_proxy: bool,
+    /// pem or der files trusted in addition to the system roots
+    pub root_certificates: Vec<PathBuf>,
+    pub connect_timeout: Option<Duration>,
+    /// max time to wait for data, for downloads this applies to each chunk
+    pub read_timeout: Option<Duration>,
+    pub max_bytes_per_second: Option<u64>,
+}
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("rustymodclient/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            system_proxy: true,
+            root_certificates: vec![],
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(60)),
+            max_bytes_per_second: None,
+        }
+    }
+}
+impl HttpConfig {
+    /// Default config with the proxy and extra certificates read from the environment
+    pub fn from_env() -> Self {
+        let mut config = HttpConfig::default();
+        if let Ok(proxy) = var(PROXY_ENV) {
+            config.proxy = Some(ProxyConfig::new(&proxy));
+        }
+        if let Ok(paths) = var(EXTRA_CA_ENV) {
+            config.root_certificates = std::env::split_paths(&paths).collect();
+        }
+        config
+    }
+
+    pub fn build_client(&self) -> LibResult<reqwest::Client> {
+        let mut builder = reqwest::ClientBuilder::new().user_agent(self.user_agent.clone());
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let mut value = match reqwest::Proxy::all(proxy.url.as_str()) {
+                Ok(value) => value,
+                Err(err) => {
+                    return Err(LauncherLibError::HTTP {
+                        msg: format!("Invaild proxy url | {}", proxy.url),
+                        source: err,
+                    })
+                }
+            };
+            if let Some(username) = &proxy.username {
+                value = value.basic_auth(username, proxy.password.as_deref().unwrap_or_default());
+            }
+            builder = builder.proxy(value);
+        } else if !self.system_proxy {
+            builder = builder.no_proxy();
+        }
+
+        for path in &self.root_certificates {
+            for certificate in read_certificates(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        match builder.build() {
+            Ok(value) => Ok(value),
+            Err(err) => Err(LauncherLibError::HTTP {
+                msg: "Failed to create http client".into(),
+                source: err,
+            }),
+        }
+    }
+}
+
+/// Reads every certificate from a pem bundle or a single der file
+fn read_certificates(path: &Path) -> LibResult<Vec<reqwest::Certificate>> {
+    let raw = match read(path) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: format!("Failed to read certificate {:?}", path),
+            })
+        }
+    };
+
+    const PEM_END: &str = "-----END CERTIFICATE-----";
+    let text = String::from_utf8_lossy(&raw);
+    let parsed = if text.contains(PEM_END) {
+        text.split_inclusive(PEM_END)
+            .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+            .map(|block| reqwest::Certificate::from_pem(block.trim_start().as_bytes()))
+            .collect::<Result<Vec<reqwest::Certificate>, reqwest::Error>>()
+    } else {
+        reqwest::Certificate::from_der(&raw).map(|value| vec![value])
+    };
+
+    match parsed {
+        Ok(value) => Ok(value),
+        Err(err) => Err(LauncherLibError::HTTP {
+            msg: format!("Invaild certificate {:?}", path),
+            source: err,
+        }),
+    }
+}
+
+/// Caps the combined speed of all downloads
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: Option<u64>,
+    state: Mutex<(Instant, u64)>,
+}
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.filter(|value| *value > 0),
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Time to wait after `bytes` more have been transferred to stay under the limit
+    pub fn get_delay(&self, bytes: u64) -> Duration {
+        let rate = match self.bytes_per_second {
+            Some(value) => value,
+            None => return Duration::ZERO,
+        };
+        let mut state = match self.state.lock() {
+            Ok(value) => value,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let elapsed = state.0.elapsed();
+        let budget = Duration::from_secs_f64(state.1 as f64 / rate as f64);
+        // don't let idle time be spent as a burst later on
+        if elapsed > budget + Duration::from_secs(1) {
+            *state = (Instant::now(), 0);
+        }
+
+        state.1 += bytes;
+        let expected = Duration::from_secs_f64(state.1 as f64 / rate as f64);
+        expected.saturating_sub(state.0.elapsed())
+    }
+
+    pub async fn consume(&self, bytes: u64) {
+        let delay = self.get_delay(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bandwidth_limiter() {
+        let unlimited = BandwidthLimiter::new(None);
+        assert_eq!(unlimited.get_delay(10_000_000), Duration::ZERO);
+
+        let limiter = BandwidthLimiter::new(Some(1000));
+        let delay = limiter.get_delay(2000);
+        assert!(delay > Duration::from_millis(1900) && delay <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_build_client() {
+        let mut config = HttpConfig {
+            proxy: Some(ProxyConfig::new("socks5://127.0.0.1:1080")),
+            ..Default::default()
+        };
+        assert!(config.build_client().is_ok());
+
+        config.proxy = Some(ProxyConfig::new("not a url"));
+        assert!(config.build_client().is_err());
+    }
+}
@@ -1,26 +1,92 @@
+use crate::cancel::CancellationToken;
 use crate::utils::{ parse_rule_list, download_file, read_manifest_inherit };
 use crate::vanilla::get_vanilla_versions;
-use crate::natives::{ extract_natives_file, get_library_data, get_natives };
+use crate::natives::{ extract_natives, get_library_classifier, get_library_data, get_natives };
 use crate::expections::{ LauncherLibError, LibResult };
+use crate::context::get_context;
+use crate::journal::open_journal;
 use crate::runtime::{ install_jvm_runtime, does_runtime_exist };
+use crate::progress::{ PHASE_ASSETS, PHASE_CLIENT, PHASE_LIBRARIES };
 use crate::json::{
     launcher_version::VersionsManifestVersion,
     game_settings::GameOptions,
     install::{Library,VersionManifest,Callback, Event}
 };
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
 use tokio::fs::read_to_string;
 use log::{ error };
 use serde::Deserialize;
 
-async fn install_libraries(id: String, libraries: &Vec<Library>, path: PathBuf, callback: Callback ) -> LibResult<()> {
+/// Bytes the libraries that apply to this platform are expected to take
+fn get_libraries_size(libraries: &Vec<Library>) -> u64 {
+    let mut size = 0;
+    for i in libraries {
+        if let Some(rules) = &i.rules {
+            if !parse_rule_list(rules, &GameOptions::default()) {
+                continue;
+            }
+        }
+        if let Some(downloads) = &i.downloads {
+            size += downloads.artifact.size as u64;
+            if let Some(classifiers) = &downloads.classifiers {
+                if let Some(nat) = classifiers.get(&get_natives(i)) {
+                    size += nat.size as u64;
+                }
+            }
+        }
+    }
+    size
+}
+
+/// Where a library goes below the libraries folder: the directories leading to it,
+/// the jar file name and the natives jar file name, which is empty when it has no natives
+pub(crate) fn get_library_files(library: &Library) -> LibResult<(Vec<String>,String,String)> {
+    let (lib_path,name,version) = get_library_data(library.name.clone())?;
+
+    let mut parts: Vec<String> = lib_path.split(".").map(|part| part.to_string()).collect();
+
+    let version_at = version.split("@").collect::<Vec<&str>>();
+
+    let (version_lib,mut fileend) = if version_at.len() == 2 {
+        (version_at[0],version_at[1])
+    } else {
+        (version.as_str(),"jar")
+    };
+
+    // 1.19+ names their natives group:artifact:version:classifier
+    let raw_classifier = library.name.split(":").nth(3).unwrap_or_default();
+    if let Some((_,ext)) = raw_classifier.split_once("@") {
+        fileend = ext;
+    }
+
+    let jar_filename = match get_library_classifier(&library.name) {
+        Some(classifier) => format!("{}-{}-{}.{}",name,version_lib,classifier,fileend).to_string(),
+        None => format!("{}-{}.{}",name,version_lib,fileend).to_string()
+    };
+
+    parts.push(name.clone());
+    parts.push(version_lib.to_string());
+
+    let native = get_natives(library);
+
+    let jar_filename_native = if !native.is_empty() {
+        format!("{}-{}-{}.jar",name,version,native).to_string()
+    } else {
+        String::default()
+    };
+
+    Ok((parts,jar_filename,jar_filename_native))
+}
+
+async fn install_libraries(id: String, libraries: &Vec<Library>, path: PathBuf, callback: Callback<'_>, cancel: &CancellationToken ) -> LibResult<()> {
 
     let max = libraries.len();
+    callback.report(Event::phase(PHASE_LIBRARIES, get_libraries_size(libraries)));
     
     for (count, i) in libraries.iter().enumerate() {
         if let Some(rules) = &i.rules {
             if !parse_rule_list(&rules, &mut GameOptions::default()) {
-                callback(Event::progress(count, max));
+                callback.report(Event::progress(count, max));
                 continue;
             }
         }
@@ -37,10 +103,10 @@ async fn install_libraries(id: String, libraries: &Vec<Library>, path: PathBuf,
                 url.clone()
             }
         } else {
-            "https://libraries.minecraft.net".into()
+            get_context().endpoints.libraries.trim_end_matches('/').into()
         };
 
-        let (lib_path,name,version) = match get_library_data(i.name.clone()) {
+        let (parts,jar_filename,jar_filename_native) = match get_library_files(i) {
             Ok(value) => value,
             Err(err) => {
                 error!("{}",err);
@@ -48,85 +114,123 @@ async fn install_libraries(id: String, libraries: &Vec<Library>, path: PathBuf,
             }
         };
 
-        for lib_part in lib_path.split(".").collect::<Vec<&str>>() {
-            current_path = current_path.join(lib_part);
-            download_url = format!("{}/{}",download_url,lib_part).to_string();
+        for part in parts {
+            current_path = current_path.join(&part);
+            download_url = format!("{}/{}",download_url,part).to_string();
         }
-        
-        let version_at = version.split("@").collect::<Vec<&str>>();
-
-        let (version_lib,fileend) = if version_at.len() == 2 {
-            (version_at[0],version_at[1])
-        } else {
-            (version.as_str(),"jar")
-        };
-
-        let jar_filename = format!("{}-{}.{}",name,version_lib,fileend).to_string();
-
-        download_url = format!("{}/{}/{}",download_url,name,version_lib).to_string();
-
-        current_path = current_path.join(name.clone()).join(version_lib);
 
         let native = get_natives(&i);
 
-        let jar_filename_native = if !native.is_empty() {
-            format!("{}-{}-{}.jar",name,version,native).to_string()
-        } else {
-            String::default()
-        };
-
         download_url = format!("{}/{}",download_url,jar_filename).to_string();
 
-        if let Err(err) = download_file(download_url, current_path.join(jar_filename.clone()), callback, None,false).await {
+        let sha1 = i.downloads.as_ref().map(|downloads| downloads.artifact.sha1.clone());
+        if let Err(err) = download_file(download_url, current_path.join(jar_filename.clone()), callback, cancel, sha1,false).await {
+            if let LauncherLibError::Cancelled = err {
+                return Err(err);
+            }
             error!("{}",err);
         }
 
-        if i.downloads.is_none() {
-            if let Some(extract) = &i.extract {
-                if let Err(err) = extract_natives_file(current_path.join(jar_filename_native), &path.join("versions").join(id.clone()).join("natives"), &extract) {
-                    return Err(err);
-                }
-                continue;
-            }
-        } 
-
         if let Some(downloads) = &i.downloads {
 
-            if let Err(err) = download_file(downloads.artifact.url.clone(), current_path.join(jar_filename), callback,Some(downloads.artifact.sha1.clone()), false).await {
-                return Err(err);
-            }
+            download_file(downloads.artifact.url.clone(), current_path.join(jar_filename), callback, cancel,Some(downloads.artifact.sha1.clone()), false).await?;
 
             if !native.is_empty() {
                 if let Some(classifiers) = &downloads.classifiers {
                     if let Some(nat) = classifiers.get(&native) {
-                        if let Err(err) = download_file(nat.url.clone(), current_path.join(jar_filename_native.clone()), callback, Some(nat.sha1.clone()), false).await {
-                            return Err(err);
-                        }
-                    }
-                    if let Some(extract) = &i.extract {
-                        if let Err(err) = extract_natives_file(current_path.join(jar_filename_native), &path.join("versions").join(id.clone()).join("natives"), &extract) {
-                            return Err(err);
-                        }
+                        download_file(nat.url.clone(), current_path.join(jar_filename_native.clone()), callback, cancel, Some(nat.sha1.clone()), false).await?;
                     }
                 }
             }
         }
-        callback(Event::progress(count,max));
+        callback.report(Event::progress(count,max));
     }
-    Ok(())
+
+    extract_natives(libraries, &path, &path.join("versions").join(id).join("natives"))
 }
 
 #[derive(Deserialize)]
-struct IndexAssetsItem {
-    hash: String,
-    //size: usize
+pub(crate) struct IndexAssetsItem {
+    pub hash: String,
+    pub size: u64
 }
 #[derive(Deserialize)]
-struct IndexAssetsMap {
-    objects: std::collections::HashMap<String,IndexAssetsItem>
+pub(crate) struct IndexAssetsMap {
+    pub objects: std::collections::HashMap<String,IndexAssetsItem>,
+    /// 1.6 and 1.7 read the assets by name from `assets/virtual/<index>`
+    #[serde(default, rename = "virtual")]
+    pub is_virtual: bool,
+    /// before 1.6 the assets are read by name from `resources` in the game directory
+    #[serde(default)]
+    pub map_to_resources: bool
 }
 
-async fn install_assets(manifest: &VersionManifest, path: PathBuf, callback: Callback) -> LibResult<()> {
+/// Directory `${game_assets}` points to for the asset index, `None` when the game reads
+/// the objects by hash from `assets` itself. The objects are copied there first, as the
+/// oldest versions read them from the `resources` of whichever game directory they run in.
+pub(crate) async fn prepare_legacy_assets(mc_dir: &Path, game_dir: &Path, index_name: &str) -> LibResult<Option<PathBuf>> {
+    let index = match read_to_string(mc_dir.join("assets").join("indexes").join(format!("{}.json",index_name))).await {
+        Ok(raw) => match serde_json::from_str::<IndexAssetsMap>(&raw) {
+            Ok(value) => value,
+            Err(_) => return Ok(None)
+        },
+        Err(_) => return Ok(None)
+    };
+    let target = match get_legacy_target(&index, mc_dir, game_dir, index_name) {
+        Some(value) => value,
+        None => return Ok(None)
+    };
+    materialise_assets(&index, &target, mc_dir).await?;
+    Ok(Some(target))
+}
+
+fn get_legacy_target(index: &IndexAssetsMap, mc_dir: &Path, game_dir: &Path, index_name: &str) -> Option<PathBuf> {
+    if index.map_to_resources {
+        Some(game_dir.join("resources"))
+    } else if index.is_virtual {
+        Some(mc_dir.join("assets").join("virtual").join(index_name))
+    } else {
+        None
+    }
+}
+
+/// Copies the objects of a legacy asset index to the names the old versions look them up by
+async fn materialise_assets(index: &IndexAssetsMap, target: &Path, path: &Path) -> LibResult<()> {
+    for (key, value) in &index.objects {
+        let pre = match value.hash.get(0..2) {
+            Some(value) => value,
+            None => continue
+        };
+        let object = path.join("assets").join("objects").join(pre).join(&value.hash);
+        // an object that was never downloaded is left for the next install to put in place
+        if !object.is_file() {
+            continue;
+        }
+        let output = target.join(key);
+        if let Ok(metadata) = tokio::fs::metadata(&output).await {
+            if metadata.len() == value.size {
+                continue;
+            }
+        }
+        if let Some(parent) = output.parent() {
+            if let Err(err) = tokio::fs::create_dir_all(parent).await {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to create directory".into()
+                });
+            }
+        }
+        if let Err(err) = tokio::fs::copy(&object, &output).await {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: format!("Failed to copy asset {}",key)
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn install_assets(manifest: &VersionManifest, path: PathBuf, callback: Callback<'_>, cancel: &CancellationToken) -> LibResult<()> {
 
     let assets = match &manifest.assets {
         Some(value) => value,
@@ -135,11 +239,10 @@ async fn install_assets(manifest: &VersionManifest, path: PathBuf, callback: Cal
 
     let index_path = path.join("assets").join("indexes").join(format!("{}.json",assets));
     if let Some(asset_index) = &manifest.asset_index {
-        if let Err(err) = download_file(asset_index.url.clone(), index_path.clone(), callback, Some(asset_index.sha1.clone()), false).await {
-            return Err(err);
-        }
+        callback.report(Event::phase(PHASE_ASSETS, (asset_index.size + asset_index.total_size.unwrap_or_default()) as u64));
+        download_file(asset_index.url.clone(), index_path.clone(), callback, cancel, Some(asset_index.sha1.clone()), false).await?;
 
-        let assets: IndexAssetsMap = match read_to_string(index_path).await {
+        let index: IndexAssetsMap = match read_to_string(index_path).await {
             Ok(raw) => {
                 match serde_json::from_str::<IndexAssetsMap>(&raw) {
                     Ok(value) => value,
@@ -152,33 +255,70 @@ async fn install_assets(manifest: &VersionManifest, path: PathBuf, callback: Cal
             })
         };
 
-        let max = assets.objects.len();
+        let resources = get_context().endpoints.resources.clone();
+        let max = index.objects.len();
         let mut count = 0;
-        for (key, value) in assets.objects {
-            callback(Event::Status(format!("Asset: {}",key)));
+        for (key, value) in &index.objects {
+            callback.report(Event::Status(format!("Asset: {}",key)));
             let pre = value.hash.get(0..2).expect("Should have this value");
-            let url = format!("https://resources.download.minecraft.net/{}/{}",pre,value.hash.clone());
+            let url = format!("{}{}/{}",resources,pre,value.hash.clone());
             let outpath = path.join("assets").join("objects").join(pre).join(value.hash.clone());
-            if let Err(err) = download_file(url, outpath, callback, Some(value.hash.clone()), false).await {
-                return Err(err);
-            }
+            download_file(url, outpath, callback, cancel, Some(value.hash.clone()), false).await?;
             count += 1;
-            callback(Event::progress(count,max));
+            callback.report(Event::progress(count,max));
+        }
+
+        // the copy for a game directory other than the minecraft directory is made on launch
+        if let Some(target) = get_legacy_target(&index, &path, &path, assets) {
+            callback.report(Event::Status("Copying legacy assets".into()));
+            materialise_assets(&index, &target, &path).await?;
         }
     } 
 
     Ok(())
 }
 
-async fn do_version_install(version_id: String, path: PathBuf, callback: Callback, url: Option<String>) -> LibResult<()> {
+/// Downloads the client jar and its logging config
+async fn install_client(manifest: &VersionManifest, path: PathBuf, callback: Callback<'_>, cancel: &CancellationToken) -> LibResult<()> {
+    let mut client_size = 0;
+    if let Some(logging) = &manifest.logging {
+        if let Some(client) = logging.get("client") {
+            client_size += client.file.size as u64;
+        }
+    }
+    if let Some(downloads) = &manifest.downloads {
+        if let Some(client) = downloads.get("client") {
+            client_size += client.size as u64;
+        }
+    }
+    callback.report(Event::phase(PHASE_CLIENT, client_size));
+
+    if let Some(logging) = &manifest.logging {
+        callback.report(Event::Status("Setting up logging".into()));
+        if let Some(client) = logging.get("client") {
+            if let Some(id) = &client.file.id {
+                let logging_file = path.join("assets").join("log_configs").join(id);
+                download_file(client.file.url.clone(), logging_file, callback, cancel, Some(client.file.sha1.clone()), false).await?;
+            }
+        }
+    }
+
+    if let Some(downloads) = &manifest.downloads {
+        callback.report(Event::Status("Installing downloads".into()));
+        if let Some(client) = downloads.get("client") {
+            download_file(client.url.clone(), path.join("versions").join(manifest.id.clone()).join(format!("{}.jar",manifest.id.clone())), callback, cancel, Some(client.sha1.clone()), false).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn do_version_install(version_id: String, path: PathBuf, callback: Callback<'_>, cancel: &CancellationToken, url: Option<String>) -> LibResult<()> {
 
    
     let version_manifest = path.join("versions").join(version_id.clone()).join(format!("{}.json",version_id.clone()));
-    callback(Event::Status("Getting version.json file".into()));
+    callback.report(Event::Status("Getting version.json file".into()));
     if let Some(url_d) = url {
-        if let Err(err) = download_file(url_d, version_manifest.clone(), callback, None, false).await {
-            return Err(err);
-        }
+        download_file(url_d, version_manifest.clone(), callback, cancel, None, false).await?;
     }
 
     
@@ -187,58 +327,60 @@ async fn do_version_install(version_id: String, path: PathBuf, callback: Callbac
         Err(err) => return Err(err)
     };
 
-    callback(Event::Status("Installing libraries".into()));
-    if let Err(err) = install_libraries(manifest.id.clone(), &manifest.libraries, path.clone(), callback).await {
-        return Err(err);
+    let journal = open_journal(&path);
+    let libraries_step = format!("{}:libraries",manifest.id);
+    let assets_step = format!("{}:assets",manifest.id);
+    let client_step = format!("{}:client",manifest.id);
+    let runtime_step = format!("{}:runtime",manifest.id);
+
+    // steps completed by an earlier run are skipped while their files are still in place,
+    // the first unfinished one is where it picks up
+    if !journal.is_step_done(&libraries_step) {
+        journal.start_step(&libraries_step)?;
+        callback.report(Event::Status("Installing libraries".into()));
+        install_libraries(manifest.id.clone(), &manifest.libraries, path.clone(), callback, cancel).await?;
+        journal.complete_step(&libraries_step)?;
     }
 
-    callback(Event::Status("Installing Assets".into()));
-    if let Err(err) = install_assets(&manifest, path.clone(), callback).await {
-        return Err(err);
-    }   
-
-
-
-    if let Some(logging) = manifest.logging {
-        callback(Event::Status("Setting up logging".into()));
-        if let Some(client) = logging.get("client") {
-            if let Some(id) = &client.file.id {
-                let logging_file = path.join("assets").join("log_configs").join(id);
-                if let Err(err) = download_file(client.file.url.clone(), logging_file, callback, Some(client.file.sha1.clone()), false).await {
-                    return Err(err);
-                }
-            }
-        }
+    if !journal.is_step_done(&assets_step) {
+        journal.start_step(&assets_step)?;
+        callback.report(Event::Status("Installing Assets".into()));
+        install_assets(&manifest, path.clone(), callback, cancel).await?;
+        journal.complete_step(&assets_step)?;
     }
 
-    if let Some(downloads) = manifest.downloads {
-        callback(Event::Status("Installing downloads".into()));
-        if let Some(client) = downloads.get("client") {
-            if let Err(err) = download_file(client.url.clone(), path.join("versions").join(manifest.id.clone()).join(format!("{}.jar",manifest.id.clone())), callback, Some(client.sha1.clone()), false).await {
-                return Err(err);
-            }
-        }
+    if !journal.is_step_done(&client_step) {
+        journal.start_step(&client_step)?;
+        install_client(&manifest, path.clone(), callback, cancel).await?;
+        journal.complete_step(&client_step)?;
     }
 
     if let Some(java) = manifest.java_version {
-        callback(Event::Status("Installing java runtime".into()));
-        match does_runtime_exist(java.component.clone(), path.clone()) {
-            Ok(value) => {
-                if !value {
-                    if let Err(err) = install_jvm_runtime(java.component, path, callback).await {
-                        return Err(err);
+        if !journal.is_step_done(&runtime_step) {
+            journal.start_step(&runtime_step)?;
+            callback.report(Event::Status("Installing java runtime".into()));
+            match does_runtime_exist(java.component.clone(), path.clone()) {
+                Ok(value) => {
+                    if !value {
+                        install_jvm_runtime(java.component, path, callback, cancel).await?;
                     }
                 }
+                Err(err) => return Err(err)
             }
-            Err(err) => return Err(err)
+            journal.complete_step(&runtime_step)?;
         }
     }
     Ok(())
 }
 
-pub async fn install_minecraft_version(version_id: String, mc_dir: PathBuf, callback: Callback) -> LibResult<()> {
+/// Whether the version json is in place and no install step of the version was left unfinished
+pub(crate) fn is_version_installed(version_id: &str, mc_dir: &Path) -> bool {
+    mc_dir.join("versions").join(version_id).join(format!("{}.json",version_id)).is_file() && !open_journal(mc_dir).has_interrupted_steps(version_id)
+}
+
+pub async fn install_minecraft_version(version_id: String, mc_dir: PathBuf, callback: Callback<'_>, cancel: &CancellationToken) -> LibResult<()> {
     if mc_dir.join("versions").join(version_id.clone()).join(format!("{}.json",version_id)).is_file() {
-       return do_version_install(version_id, mc_dir, callback, None).await;
+       return do_version_install(version_id, mc_dir, callback, cancel, None).await;
     }
     match get_vanilla_versions().await {
         Ok(versions) => {
@@ -246,11 +388,157 @@ pub async fn install_minecraft_version(version_id: String, mc_dir: PathBuf, call
             let version: Vec<&VersionsManifestVersion> = versions.iter().filter(| item | item.id == version_id).collect();
 
             if let Some(item) = version.get(0) {
-                return do_version_install(item.id.clone(), mc_dir, callback, Some(item.url.clone())).await;
+                return do_version_install(item.id.clone(), mc_dir, callback, cancel, Some(item.url.clone())).await;
             }
 
             Ok(())
         }
         Err(err) => Err(err)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{ fixture_context, with_context };
+    use crate::json::install::{ ChannelReporter, DownloadState };
+    use crate::progress::ProgressTracker;
+
+    #[tokio::test]
+    async fn test_install_minecraft_version_from_fixture() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-install-{}",uuid::Uuid::new_v4()));
+
+        let (reporter, mut events) = ChannelReporter::channel();
+
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &CancellationToken::new())).await;
+        assert!(result.is_ok(),"{:?}",result);
+
+        let mut tracker = ProgressTracker::new(&[PHASE_LIBRARIES, PHASE_ASSETS, PHASE_CLIENT]);
+        let mut percent = 0.0;
+        let mut downloads = 0;
+        while let Ok(event) = events.try_recv() {
+            if let Event::Download { .. } = event {
+                downloads += 1;
+            }
+            tracker.update(&event);
+            assert!(tracker.get_percent() >= percent);
+            percent = tracker.get_percent();
+        }
+        assert!(downloads >= 5);
+        assert_eq!(percent, 100.0);
+
+        assert!(mc_dir.join("versions").join("fixture-1.0").join("fixture-1.0.json").is_file());
+        assert!(mc_dir.join("versions").join("fixture-1.0").join("fixture-1.0.jar").is_file());
+        assert!(mc_dir.join("libraries").join("org").join("example").join("demo").join("1.0").join("demo-1.0.jar").is_file());
+        assert!(mc_dir.join("assets").join("indexes").join("fixture.json").is_file());
+        assert!(mc_dir.join("assets").join("objects").join("3a").join("3a1d589e96137edd0616bcf57c0ebfe1ef2c76b2").is_file());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_install_minecraft_version_cancelled() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-install-{}",uuid::Uuid::new_v4()));
+        let cancel = CancellationToken::new();
+
+        // cancel as soon as the first file is in place
+        let reporter = |event: Event| {
+            if let Event::Download { .. } = event {
+                cancel.cancel();
+            }
+        };
+
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &cancel)).await;
+        assert!(matches!(result, Err(LauncherLibError::Cancelled)),"{:?}",result);
+
+        assert!(mc_dir.join("versions").join("fixture-1.0").join("fixture-1.0.json").is_file());
+        assert!(!mc_dir.join("versions").join("fixture-1.0").join("fixture-1.0.jar").exists());
+        let libraries = mc_dir.join("libraries").join("org").join("example").join("demo").join("1.0");
+        assert!(!libraries.join("demo-1.0.jar").exists());
+        assert!(!libraries.join("demo-1.0.jar.part").exists());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_install_resumes_from_journal() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-install-{}",uuid::Uuid::new_v4()));
+        let cancel = CancellationToken::new();
+        let reporter = |event: Event| {
+            if let Event::Download { .. } = event {
+                cancel.cancel();
+            }
+        };
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &cancel)).await;
+        assert!(matches!(result, Err(LauncherLibError::Cancelled)),"{:?}",result);
+
+        // a jar left half written, ie by a crash of an older installer
+        let library = mc_dir.join("libraries").join("org").join("example").join("demo").join("1.0").join("demo-1.0.jar");
+        std::fs::create_dir_all(library.parent().unwrap()).unwrap();
+        std::fs::write(&library, "PK").unwrap();
+
+        let (reporter, mut events) = ChannelReporter::channel();
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &CancellationToken::new())).await;
+        assert!(result.is_ok(),"{:?}",result);
+        let mut downloaded = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let Event::Download { state: DownloadState::Download | DownloadState::DownloadChecked, msg } = event {
+                downloaded.push(msg);
+            }
+        }
+        assert!(downloaded.iter().any(|url| url.ends_with("demo-1.0.jar")));
+        assert!(!downloaded.iter().any(|url| url.ends_with("fixture-1.0.json")));
+        let expected = std::fs::read("tests/fixtures/install/demo-1.0.jar").unwrap();
+        assert_eq!(std::fs::read(&library).unwrap(), expected);
+
+        // every step is complete, so nothing is looked at again
+        let (reporter, mut events) = ChannelReporter::channel();
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &CancellationToken::new())).await;
+        assert!(result.is_ok(),"{:?}",result);
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event, Event::Download { .. }),"{:?}",event);
+        }
+
+        // a file removed after its step completed is put back
+        std::fs::remove_file(&library).unwrap();
+        let (reporter, mut events) = ChannelReporter::channel();
+        let result = with_context(fixture_context("install"), install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &CancellationToken::new())).await;
+        assert!(result.is_ok(),"{:?}",result);
+        let mut downloaded = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let Event::Download { state: DownloadState::Download | DownloadState::DownloadChecked, msg } = event {
+                downloaded.push(msg);
+            }
+        }
+        assert_eq!(downloaded.len(), 1,"{:?}",downloaded);
+        assert!(downloaded[0].ends_with("demo-1.0.jar"));
+        assert_eq!(std::fs::read(&library).unwrap(), expected);
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_materialise_virtual_assets() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-install-{}",uuid::Uuid::new_v4()));
+        let hash = "3a1d589e96137edd0616bcf57c0ebfe1ef2c76b2";
+        let objects = mc_dir.join("assets").join("objects").join("3a");
+        std::fs::create_dir_all(&objects).unwrap();
+        std::fs::copy("tests/fixtures/install/en_us.json", objects.join(hash)).unwrap();
+        let indexes = mc_dir.join("assets").join("indexes");
+        std::fs::create_dir_all(&indexes).unwrap();
+
+        for (name, flags, expected) in [
+            ("legacy", r#""virtual": true"#, mc_dir.join("assets").join("virtual").join("legacy")),
+            ("pre-1.6", r#""map_to_resources": true"#, mc_dir.join("resources")),
+        ] {
+            let raw = format!(r#"{{ {}, "objects": {{ "lang/en_us.json": {{ "hash": "{}", "size": 28 }} }} }}"#, flags, hash);
+            std::fs::write(indexes.join(format!("{}.json",name)), &raw).unwrap();
+
+            let target = prepare_legacy_assets(&mc_dir, &mc_dir, name).await.unwrap().unwrap();
+            assert_eq!(target, expected);
+            assert_eq!(std::fs::read(target.join("lang").join("en_us.json")).unwrap(), std::fs::read("tests/fixtures/install/en_us.json").unwrap());
+        }
+        assert!(prepare_legacy_assets(&mc_dir, &mc_dir, "missing").await.unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::expections::{LauncherLibError, LibResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+
+const JOURNAL_FILE: &str = ".install-journal.json";
+/// files recorded between two writes of the journal
+const SAVE_INTERVAL: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StepState {
+    Started,
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct VerifiedFile {
+    size: u64,
+    /// modification time in seconds, to notice files changed since they were verified
+    modified: u64,
+    sha1: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JournalData {
+    steps: BTreeMap<String, StepState>,
+    /// keyed by the path relative to the minecraft directory
+    files: BTreeMap<String, VerifiedFile>,
+    /// files recorded while a step was running, so a complete step can be checked cheaply
+    #[serde(default)]
+    step_files: BTreeMap<String, BTreeSet<String>>,
+    #[serde(skip)]
+    unsaved: usize,
+}
+
+/// Persisted record of the install steps that finished and the files that were
+/// verified in a minecraft directory, so an interrupted install can pick up where it stopped
+#[derive(Debug)]
+pub struct InstallJournal {
+    mc_dir: PathBuf,
+    data: Mutex<JournalData>,
+}
+
+fn get_file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    Some((metadata.len(), modified))
+}
+
+fn add_to_running_steps(data: &mut JournalData, key: &str) {
+    let running: Vec<String> = data
+        .steps
+        .iter()
+        .filter(|(_, state)| **state == StepState::Started)
+        .map(|(step, _)| step.clone())
+        .collect();
+    for step in running {
+        data.step_files
+            .entry(step)
+            .or_default()
+            .insert(key.to_string());
+    }
+}
+
+fn journals() -> &'static Mutex<HashMap<PathBuf, Arc<InstallJournal>>> {
+    static JOURNALS: OnceLock<Mutex<HashMap<PathBuf, Arc<InstallJournal>>>> = OnceLock::new();
+    JOURNALS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Opens the journal of `mc_dir`. Installs into the same directory share one journal.
+pub fn open_journal(mc_dir: &Path) -> Arc<InstallJournal> {
+    let mut journals = match journals().lock() {
+        Ok(value) => value,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    journals
+        .entry(mc_dir.to_path_buf())
+        .or_insert_with(|| Arc::new(InstallJournal::load(mc_dir)))
+        .clone()
+}
+
+/// The open journal of the minecraft directory holding `path`, if any
+pub fn find_journal(path: &Path) -> Option<Arc<InstallJournal>> {
+    let journals = match journals().lock() {
+        Ok(value) => value,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    journals
+        .iter()
+        .filter(|(mc_dir, _)| path.starts_with(mc_dir))
+        // the most specific directory wins when they are nested
+        .max_by_key(|(mc_dir, _)| mc_dir.components().count())
+        .map(|(_, journal)| journal.clone())
+}
+
+impl InstallJournal {
+    /// Reads the journal of `mc_dir`, a missing or unreadable journal starts empty
+    fn load(mc_dir: &Path) -> Self {
+        let data = match fs::read_to_string(mc_dir.join(JOURNAL_FILE)) {
+            Ok(raw) => serde_json::from_str::<JournalData>(&raw).unwrap_or_default(),
+            Err(_) => JournalData::default(),
+        };
+        Self {
+            mc_dir: mc_dir.to_path_buf(),
+            data: Mutex::new(data),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JournalData> {
+        match self.data.lock() {
+            Ok(value) => value,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn get_key(&self, path: &Path) -> String {
+        path.strip_prefix(&self.mc_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    fn write(&self, data: &mut JournalData) -> LibResult<()> {
+        let raw = match serde_json::to_string(&*data) {
+            Ok(value) => value,
+            Err(err) => return Err(LauncherLibError::ParseJsonSerde(err)),
+        };
+        if let Err(err) = fs::create_dir_all(&self.mc_dir) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to create minecraft directory".into(),
+            });
+        }
+
+        // write then rename so a crash never leaves a half written journal
+        let path = self.mc_dir.join(JOURNAL_FILE);
+        let temp = self.mc_dir.join(format!("{}.tmp", JOURNAL_FILE));
+        if let Err(err) = fs::write(&temp, raw) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to write install journal".into(),
+            });
+        }
+        if let Err(err) = fs::rename(&temp, &path) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to write install journal".into(),
+            });
+        }
+        data.unsaved = 0;
+        Ok(())
+    }
+
+    /// Writes the journal to disk
+    pub fn save(&self) -> LibResult<()> {
+        let mut data = self.lock();
+        self.write(&mut data)
+    }
+
+    pub fn get_step(&self, step: &str) -> Option<StepState> {
+        self.lock().steps.get(step).copied()
+    }
+
+    pub fn is_step_complete(&self, step: &str) -> bool {
+        self.get_step(step) == Some(StepState::Complete)
+    }
+
+    /// Whether the step completed and the files it recorded are still in place
+    pub fn is_step_done(&self, step: &str) -> bool {
+        self.is_step_complete(step) && self.is_step_intact(step)
+    }
+
+    /// Whether the step was started but never completed
+    pub fn is_step_interrupted(&self, step: &str) -> bool {
+        self.get_step(step) == Some(StepState::Started)
+    }
+
+    /// Whether any step of the version was started but never completed.
+    /// Version steps are named `<version id>:<step>`.
+    pub fn has_interrupted_steps(&self, version_id: &str) -> bool {
+        let prefix = format!("{}:", version_id);
+        self.lock()
+            .steps
+            .iter()
+            .any(|(step, state)| step.starts_with(&prefix) && *state == StepState::Started)
+    }
+
+    pub fn start_step(&self, step: &str) -> LibResult<()> {
+        let mut data = self.lock();
+        data.steps.insert(step.to_string(), StepState::Started);
+        data.step_files.remove(step);
+        self.write(&mut data)
+    }
+
+    pub fn complete_step(&self, step: &str) -> LibResult<()> {
+        let mut data = self.lock();
+        data.steps.insert(step.to_string(), StepState::Complete);
+        self.write(&mut data)
+    }
+
+    /// Forgets a step so it runs again on the next install
+    pub fn reset_step(&self, step: &str) -> LibResult<()> {
+        let mut data = self.lock();
+        data.steps.remove(step);
+        data.step_files.remove(step);
+        self.write(&mut data)
+    }
+
+    /// Forgets every step of the version, ie once its files were removed
+    pub fn reset_version(&self, version_id: &str) -> LibResult<()> {
+        let prefix = format!("{}:", version_id);
+        let mut data = self.lock();
+        data.steps.retain(|step, _| !step.starts_with(&prefix));
+        data.step_files.retain(|step, _| !step.starts_with(&prefix));
+        self.write(&mut data)
+    }
+
+    /// Records that the file at `path` is complete, with its sha1 when it was checked.
+    /// The file is counted towards every step that is running.
+    pub fn record_file(&self, path: &Path, sha1: Option<String>) {
+        let (size, modified) = match get_file_stamp(path) {
+            Some(value) => value,
+            None => return,
+        };
+        let key = self.get_key(path);
+        let mut data = self.lock();
+        add_to_running_steps(&mut data, &key);
+        data.files.insert(
+            key,
+            VerifiedFile {
+                size,
+                modified,
+                sha1,
+            },
+        );
+        data.unsaved += 1;
+        if data.unsaved >= SAVE_INTERVAL {
+            // losing the record only means the file is checked again next time
+            let _ = self.write(&mut data);
+        }
+    }
+
+    /// Counts a file that was already verified towards every step that is running
+    pub fn use_file(&self, path: &Path) {
+        let key = self.get_key(path);
+        let mut data = self.lock();
+        if data.files.contains_key(&key) {
+            add_to_running_steps(&mut data, &key);
+            data.unsaved += 1;
+        }
+    }
+
+    /// Whether every file recorded while the step ran is still on disk with its recorded size
+    pub fn is_step_intact(&self, step: &str) -> bool {
+        let data = self.lock();
+        let files = match data.step_files.get(step) {
+            Some(value) => value,
+            None => return true,
+        };
+        files.iter().all(|key| {
+            let size = match get_file_stamp(&self.mc_dir.join(key)) {
+                Some((size, _)) => size,
+                None => return false,
+            };
+            data.files.get(key).map(|file| file.size) == Some(size)
+        })
+    }
+
+    pub fn forget_file(&self, path: &Path) {
+        let key = self.get_key(path);
+        let mut data = self.lock();
+        if data.files.remove(&key).is_some() {
+            data.unsaved += 1;
+        }
+    }
+
+    /// Forgets the files below `dir`
+    pub fn forget_dir(&self, dir: &Path) {
+        let prefix = format!("{}/", self.get_key(dir));
+        let mut data = self.lock();
+        let count = data.files.len();
+        data.files.retain(|key, _| !key.starts_with(&prefix));
+        if data.files.len() != count {
+            data.unsaved += 1;
+        }
+    }
+
+    /// Whether `path` was recorded as complete and has not changed since.
+    /// When `sha1` is given the recorded file must have been verified against it.
+    pub fn is_file_verified(&self, path: &Path, sha1: Option<&str>) -> bool {
+        let stamp = match get_file_stamp(path) {
+            Some(value) => value,
+            None => return false,
+        };
+        let data = self.lock();
+        match data.files.get(&self.get_key(path)) {
+            Some(file) => {
+                (file.size, file.modified) == stamp
+                    && match sha1 {
+                        Some(sha1) => file.sha1.as_deref() == Some(sha1),
+                        None => true,
+                    }
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_persists_steps_and_files() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-journal-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(mc_dir.join("libraries")).unwrap();
+        let jar = mc_dir.join("libraries").join("a.jar");
+        fs::write(&jar, "jar").unwrap();
+
+        let journal = open_journal(&mc_dir);
+        assert!(Arc::ptr_eq(&journal, &find_journal(&jar).unwrap()));
+
+        journal.start_step("1.18.2:assets").unwrap();
+        journal.record_file(&jar, Some("abc".into()));
+        assert!(journal.is_file_verified(&jar, None));
+        assert!(journal.is_file_verified(&jar, Some("abc")));
+        assert!(!journal.is_file_verified(&jar, Some("def")));
+        journal.complete_step("1.18.2:libraries").unwrap();
+
+        let reloaded = InstallJournal::load(&mc_dir);
+        assert!(reloaded.is_step_interrupted("1.18.2:assets"));
+        assert!(reloaded.is_step_complete("1.18.2:libraries"));
+        assert!(reloaded.has_interrupted_steps("1.18.2"));
+        assert!(!reloaded.has_interrupted_steps("1.18"));
+        assert!(reloaded.is_file_verified(&jar, Some("abc")));
+
+        assert!(reloaded.is_step_intact("1.18.2:assets"));
+        assert!(reloaded.is_step_intact("1.18.2:libraries"));
+
+        // a file that changed size is no longer trusted
+        fs::write(&jar, "changed jar").unwrap();
+        assert!(!reloaded.is_file_verified(&jar, None));
+        assert!(!reloaded.is_step_intact("1.18.2:assets"));
+        fs::remove_file(&jar).unwrap();
+        assert!(!reloaded.is_step_intact("1.18.2:assets"));
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+}
@@ -6,7 +6,7 @@ use std::path::PathBuf;
 pub mod client {
     use super::*;
 
-    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
     pub enum Loader {
         #[serde(rename = "fabric")]
         Fabric,
@@ -255,8 +255,15 @@ pub mod game_settings {
         pub resolution_height: Option<String>,
         pub enable_logging_config: bool,
         pub logging_path: Option<PathBuf>,
+        /// server to join on launch, with Quick Play or `--server` on versions without it
         pub server: Option<String>,
         pub port: Option<String>,
+        /// singleplayer world to open on launch, needs a version with Quick Play
+        pub quick_play_singleplayer: Option<String>,
+        /// realm to join on launch, needs a version with Quick Play
+        pub quick_play_realms: Option<String>,
+        /// where the game logs the Quick Play session, relative to the game directory
+        pub quick_play_path: Option<PathBuf>,
         pub xuid: Option<String>,
         pub client_id: Option<String>,
     }
@@ -285,18 +292,27 @@ pub mod runtime {
         pub files: HashMap<String, JVMFileProps>,
     }
 
-    #[derive(Deserialize, Debug, Clone)]
+    #[derive(Debug, Clone)]
     pub enum MinecraftJavaRuntime {
-        #[serde(rename = "java-runtime-alpha")]
         JavaRuntimeAlpha,
-        #[serde(rename = "java-runtime-beta")]
         JavaRuntimeBeta,
-        #[serde(rename = "minecraft-java-exe")]
         MinecraftJavaExe,
-        #[serde(rename = "jre-legacy")]
         JreLegacy,
         Unkown(String),
     }
+    /// Components added after this list, ie java-runtime-gamma, are kept as `Unkown`
+    impl<'de> Deserialize<'de> for MinecraftJavaRuntime {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            Ok(match value.as_str() {
+                "java-runtime-alpha" => MinecraftJavaRuntime::JavaRuntimeAlpha,
+                "java-runtime-beta" => MinecraftJavaRuntime::JavaRuntimeBeta,
+                "minecraft-java-exe" => MinecraftJavaRuntime::MinecraftJavaExe,
+                "jre-legacy" => MinecraftJavaRuntime::JreLegacy,
+                _ => MinecraftJavaRuntime::Unkown(value),
+            })
+        }
+    }
     impl MinecraftJavaRuntime {
         pub fn to_string(&self) -> String {
             match self {
@@ -389,6 +405,10 @@ pub mod install {
         Status(String),
         Download { state: DownloadState, msg: String },
         Progress { max: usize, current: usize },
+        /// A new install phase started, `total` is the number of bytes it expects to fetch (0 if unknown)
+        Phase { name: String, total: u64 },
+        /// Bytes of `url` that are on disk so far
+        Bytes { url: String, current: u64, total: Option<u64> },
     }
     impl Event {
         pub fn download(state: DownloadState, msg: String) -> Self {
@@ -397,6 +417,19 @@ pub mod install {
         pub fn progress(current: usize, max: usize) -> Self {
             Event::Progress { max, current }
         }
+        pub fn phase(name: &str, total: u64) -> Self {
+            Event::Phase {
+                name: name.to_string(),
+                total,
+            }
+        }
+        pub fn bytes(url: String, current: u64, total: Option<u64>) -> Self {
+            Event::Bytes {
+                url,
+                current,
+                total,
+            }
+        }
     }
 
     impl Display for Event {
@@ -408,11 +441,58 @@ pub mod install {
                 Event::Progress { max, current } => {
                     write!(f, "Progress {}%", *current as f32 / *max as f32 * 100.0)
                 }
+                Event::Phase { name, total: _ } => write!(f, "Installing {}...", name),
+                Event::Bytes {
+                    url,
+                    current,
+                    total,
+                } => match total {
+                    Some(total) => write!(f, "Retrieving {} ({}/{} bytes)", url, current, total),
+                    None => write!(f, "Retrieving {} ({} bytes)", url, current),
+                },
             }
         }
     }
 
-    pub type Callback = fn(Event);
+    /// Receives the events emitted while installing
+    pub trait Reporter: Send + Sync {
+        fn report(&self, event: Event);
+    }
+
+    /// Lets plain `fn(Event)` callbacks and closures be used as a reporter
+    impl<F> Reporter for F
+    where
+        F: Fn(Event) + Send + Sync,
+    {
+        fn report(&self, event: Event) {
+            self(event)
+        }
+    }
+
+    /// Reporter that forwards every event into a channel
+    #[derive(Debug, Clone)]
+    pub struct ChannelReporter {
+        sender: tokio::sync::mpsc::UnboundedSender<Event>,
+    }
+    impl ChannelReporter {
+        pub fn new(sender: tokio::sync::mpsc::UnboundedSender<Event>) -> Self {
+            Self { sender }
+        }
+
+        /// Creates a reporter along with the receiving end of its channel
+        pub fn channel() -> (Self, tokio::sync::mpsc::UnboundedReceiver<Event>) {
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            (Self::new(sender), receiver)
+        }
+    }
+    impl Reporter for ChannelReporter {
+        fn report(&self, event: Event) {
+            // the receiver going away should not abort the install
+            let _ = self.sender.send(event);
+        }
+    }
+
+    pub type Callback<'a> = &'a dyn Reporter;
 
     #[derive(Deserialize, Debug, Clone)]
     pub struct Rule {
@@ -435,14 +515,15 @@ pub mod install {
         },
     }
 
-    #[derive(Deserialize, Debug, Clone)]
+    #[derive(Deserialize, Debug, Clone, Default)]
     pub struct Arguments {
+        #[serde(default)]
         pub game: Vec<Argument>,
         #[serde(default)]
         pub jvm: Vec<Argument>,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Debug, Clone)]
     pub struct JavaComponent {
         pub component: runtime::MinecraftJavaRuntime,
         #[serde(rename = "majorVersion")]
@@ -470,7 +551,7 @@ pub mod install {
         pub rules: Option<Vec<Rule>>,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Debug, Clone)]
     pub struct LoggingConfig {
         pub argument: String,
         pub file: DownloadableFile,
@@ -478,10 +559,14 @@ pub mod install {
         pub logging_type: String,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Debug, Clone)]
     pub struct VersionManifest {
         pub _comment_: Option<Vec<String>>,
+        /// versions before 1.13 only have `minecraftArguments`
+        #[serde(default)]
         pub arguments: Arguments,
+        #[serde(rename = "minecraftArguments")]
+        pub minecraft_arguments: Option<String>,
         #[serde(rename = "assetIndex")]
         pub asset_index: Option<DownloadableFile>,
         pub assets: Option<String>,
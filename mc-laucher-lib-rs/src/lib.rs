@@ -1,14 +1,35 @@
+pub mod cancel;
+pub mod catalogue;
 pub mod client;
 mod command;
+pub mod context;
+pub mod crash;
+pub mod endpoints;
+pub mod export;
 pub mod expections;
 pub mod fabric;
 pub mod forge;
+pub mod gc;
+pub mod http;
 mod install;
+pub mod journal;
 pub mod json;
 pub mod login;
 mod mod_utiles;
 mod natives;
 pub mod optifine;
+pub mod preflight;
+pub mod process;
+pub mod progress;
+pub mod retry;
+pub mod rules;
 mod runtime;
+pub mod staging;
+pub mod support;
+pub mod template;
+#[cfg(test)]
+mod testing;
+pub mod transport;
 pub mod utils;
 pub mod vanilla;
+pub mod verify;
@@ -1,5 +1,7 @@
-use crate::utils::get_http_client;
+use crate::utils::send_json;
+use crate::transport::HttpRequest;
 use crate::expections::{LauncherLibError,LibResult};
+use crate::context::get_context;
 use crate::json::{
     minecraft_account::PlayerProfile,
     authentication_microsoft::{
@@ -12,11 +14,10 @@ use crate::json::{
 };
 use serde_json::json;
 
-const MS_TOKEN_AUTHORIZATION_URL: &str = "https://login.live.com/oauth20_token.srf";
-
 pub fn ms_login_url(client_id: String, redirect_uri: String) -> String {
     format!(
-        "https://login.live.com/oauth20_authorize.srf?client_id={client_id}&response_type=code&redirect_uri={redirect_uri}&scope=XboxLive.signin%20offline_access&state=<optional;",
+        "{authorize}?client_id={client_id}&response_type=code&redirect_uri={redirect_uri}&scope=XboxLive.signin%20offline_access&state=<optional;",
+        authorize=get_context().endpoints.ms_authorize,
         client_id=client_id,
         redirect_uri=redirect_uri    
     ).to_string()
@@ -41,66 +42,28 @@ pub fn get_auth_code(url: String) -> Option<String> {
 }
 
 async fn refresh_auth_token(client_id: String, redirect_uri: String, refresh_token: String) -> LibResult<AuthoriztionJson> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    let params = std::collections::HashMap::from([
+    let params = [
         ("client_id", client_id.as_str()),
         ("refresh_token",refresh_token.as_str()),
         ("grant_type","refresh_token"),
         ("redirect_uri",redirect_uri.as_str())
-    ]);
-
+    ];
 
-    match client.post(MS_TOKEN_AUTHORIZATION_URL).form(&params).send().await {
-        Ok(value) => {
-            match value.json::<AuthoriztionJson>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make authorization request".into()
-        })
-    }
+    send_json::<AuthoriztionJson>(HttpRequest::post(&get_context().endpoints.ms_token).form(&params)).await
 }
 
 async fn get_authorization_token(client_id: String, redirect_uri: String, auth_code: String) -> LibResult<AuthoriztionJson> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    let params = std::collections::HashMap::from([
+    let params = [
         ("client_id", client_id.as_str()),
         ("redirect_uri",redirect_uri.as_str()),
         ("code",auth_code.as_str()),
         ("grant_type","authorization_code")
-    ]);
+    ];
 
-    match client.post(MS_TOKEN_AUTHORIZATION_URL).form(&params).send().await {
-        Ok(value) => {
-            match value.json::<AuthoriztionJson>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make authorization request".into()
-        })
-    }
+    send_json::<AuthoriztionJson>(HttpRequest::post(&get_context().endpoints.ms_token).form(&params)).await
 }
 
 async fn authenticate_with_xbl(access_token: String) -> LibResult<XboxLiveJson> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
     let payload = json!({
         "Properties": {
             "AuthMethod": "RPS",
@@ -111,18 +74,7 @@ async fn authenticate_with_xbl(access_token: String) -> LibResult<XboxLiveJson>
         "TokenType": "JWT"
      });
 
-    match client.post("https://user.auth.xboxlive.com/user/authenticate").json(&payload).send().await {
-        Ok(res) => {
-            match res.json::<XboxLiveJson>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to authenticate with xbox live".into()
-        })
-    }
+    send_json::<XboxLiveJson>(HttpRequest::post(&get_context().endpoints.xbox_user_auth).json(payload)).await
 }
 
 async fn _get_display_info(_access_token: String) -> LibResult<()> {
@@ -153,11 +105,6 @@ async fn _get_display_info(_access_token: String) -> LibResult<()> {
 }
 
 async fn authenticate_with_xsts(xbl_token: String) -> LibResult<XboxLiveJson> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
     let payload = json!({
         "Properties": {
             "SandboxId": "RETAIL",
@@ -169,90 +116,37 @@ async fn authenticate_with_xsts(xbl_token: String) -> LibResult<XboxLiveJson> {
         "TokenType": "JWT"
     });
 
-    match client.post("https://xsts.auth.xboxlive.com/xsts/authorize").json(&payload).send().await {
-        Ok(res) => {
-            match res.json::<XboxLiveJson>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to authenticate with XSTS".into()
-        })
-    }
+    send_json::<XboxLiveJson>(HttpRequest::post(&get_context().endpoints.xsts_authorize).json(payload)).await
 }
 
 async fn authenticate_with_minecraft(userhash: String, xsts_token: String) -> LibResult<MinecraftJson> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
     let payload = json!({
         "identityToken": format!("XBL3.0 x={};{}",userhash,xsts_token).to_string()
     });
 
-    match client.post("https://api.minecraftservices.com/authentication/login_with_xbox").json(&payload).send().await {
-        Ok(res) => {
-            match res.json::<MinecraftJson>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to authenticate with minecraft".into()
-        })
-    }
+    let url = format!("{}authentication/login_with_xbox",get_context().endpoints.minecraft_services);
+    send_json::<MinecraftJson>(HttpRequest::post(&url).json(payload)).await
 }
 
 async fn check_game_ownership(access_token: String) -> LibResult<()> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get("https://api.minecraftservices.com/entitlements/mcstore").bearer_auth(access_token).send().await {
-        Ok(res) => {
-            match res.json::<GameOwnership>().await {
-                Ok(value) => {
-                    if value.items.is_empty() {
-                        return Err(LauncherLibError::General("Account does not own a copy of minecraft".into()))
-                    }
+    let url = format!("{}entitlements/mcstore",get_context().endpoints.minecraft_services);
+    match send_json::<GameOwnership>(HttpRequest::get(&url).bearer_auth(&access_token)).await {
+        Ok(value) => {
+            if value.items.is_empty() {
+                return Err(LauncherLibError::General("Account does not own a copy of minecraft".into()))
+            }
 
-                    eprintln!("Did not check jwt signatures, may not be legitimate");
+            eprintln!("Did not check jwt signatures, may not be legitimate");
 
-                    Ok(())
-                },
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
+            Ok(())
         },
-        Err(err) => Err(LauncherLibError::HTTP{
-            source: err,
-            msg: "Failed to check game ownership".into()
-        })
+        Err(err) => Err(err)
     }
 }
 
 async fn get_minecraft_profile(token: String) -> LibResult<PlayerProfile> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get("https://api.minecraftservices.com/minecraft/profile").bearer_auth(token).send().await {
-        Ok(res) => {
-            match res.json::<PlayerProfile>().await {
-                Ok(value) => Ok(value),
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to get minecraft profile".into()
-        })
-    }
+    let url = format!("{}minecraft/profile",get_context().endpoints.minecraft_services);
+    send_json::<PlayerProfile>(HttpRequest::get(&url).bearer_auth(&token)).await
 }
 
 pub async fn login_microsoft(client_id: String, redirect_uri: String, auth_code: String) -> LibResult<Account> {
@@ -364,6 +258,16 @@ pub async fn login_microsoft_refresh(client_id: String, redirect_uri: String, re
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::{ fixture_context, with_context };
+
+    #[tokio::test]
+    async fn test_login_microsoft_from_fixture() {
+        let account = with_context(fixture_context("login"), login_microsoft("client".into(),"https://localhost".into(),"code".into())).await.unwrap();
+
+        assert_eq!(account.xuid,"2535428504476914");
+        assert_eq!(account.profile.name,"Fixture");
+        assert_eq!(account.refresh_token,"ms-refresh-token");
+    }
     #[test]
     fn test_ms_login_url() {
         let client_id = std::env::var("CLIENT_ID").expect("Expected CLIENT ID").to_string();
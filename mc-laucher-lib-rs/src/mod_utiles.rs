@@ -1,12 +1,15 @@
+use crate::cancel::CancellationToken;
+use crate::context::get_context;
 use crate::expections::{LauncherLibError, LibResult};
-use crate::utils::{download_file_to_string, get_http_client};
+use crate::utils::{download_file_to_string, get_text};
 use serde::Deserialize;
 
 use crate::json::install::{Callback, Event};
+use crate::progress::PHASE_MODS;
+use crate::staging::{recover_update, StagedUpdate, CONFIG_DIR, MODS_DIR, SHADERPACKS_DIR};
 use crate::utils::download_file;
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::Write;
 use std::path::PathBuf;
 
 fn get_jar_name(url: &str) -> &str {
@@ -28,26 +31,31 @@ fn get_mod_name_id(jar: &str) -> Option<String> {
     mod_name
 }
 
-pub async fn install_mods(mc_dir: PathBuf, callback: Callback) -> LibResult<()> {
-    let mut mods_list = String::new();
-    if let Err(err) = download_file_to_string(
-        "https://raw.githubusercontent.com/Seabreyh/seabreyh-mc-mod-installer/main/assets/mods.md"
-            .to_string(),
-        &mut mods_list,
-        callback,
-    )
-    .await
-    {
-        return Err(err);
+/// Checks that a downloaded mod or shaderpack is a readable archive
+fn verify_archive(path: &PathBuf) -> LibResult<()> {
+    let file = match File::open(path) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: format!("Failed to open {}", path.display()),
+            })
+        }
+    };
+    match zip::ZipArchive::new(file) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(LauncherLibError::ZipError(err)),
     }
+}
 
-    let mods_dir = mc_dir.join("mods");
-
-    let mods_backup = mc_dir.join("mods.backup");
-
-    if let Err(_) = dircpy::copy_dir(mods_dir.clone(), mods_backup) {
-        std::fs::create_dir(&mods_dir).expect("Failed to create .minecraft/mods folder");
-    }
+/// Brings the staged mods and shaderpacks up to date with the mods list
+async fn stage_mods(
+    update: &StagedUpdate,
+    mods_list: &str,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
+) -> LibResult<()> {
+    let mods_dir = update.get_staged_dir(MODS_DIR);
 
     let mut to_install_mod_jars = mods_list.lines().fold(HashMap::new(), |mut acc, file_url| {
         *acc.entry(get_jar_name(file_url)).or_insert(file_url) = file_url;
@@ -60,22 +68,34 @@ pub async fn install_mods(mc_dir: PathBuf, callback: Callback) -> LibResult<()>
         .collect::<HashSet<String>>();
 
     // Iterate over the already installed jars to see if we can avoid installing it again
-    let installed_jars = fs::read_dir(&mods_dir).unwrap();
+    let installed_jars = match fs::read_dir(&mods_dir) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to read mods folder".into(),
+            })
+        }
+    };
     for jar_path in installed_jars.filter_map(|s| {
-        let file_path = s.as_ref().unwrap().path().display().to_string();
+        let file_path = s.ok()?.path().display().to_string();
         file_path.contains(".jar").then_some(file_path)
     }) {
         let jar = get_jar_name(&jar_path);
 
         if to_install_mod_jars.contains_key(jar) {
             to_install_mod_jars.remove(jar);
-            callback(Event::Status(format!("Skipping mod {}", jar)));
+            callback.report(Event::Status(format!("Skipping mod {}", jar)));
         } else if let Some(mod_id) = get_mod_name_id(jar) {
             if to_install_mod_ids.contains(&mod_id) {
                 let mod_file = mods_dir.join(jar);
-                callback(Event::Status(format!("Replacing out-dated mod {}", jar)));
-                fs::remove_file(mod_file)
-                    .expect(&format!("Error: couldn't remove out-dated mod {}", jar));
+                callback.report(Event::Status(format!("Replacing out-dated mod {}", jar)));
+                if let Err(err) = fs::remove_file(mod_file) {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: format!("Couldn't remove out-dated mod {}", jar),
+                    });
+                }
             }
         }
     }
@@ -87,6 +107,7 @@ pub async fn install_mods(mc_dir: PathBuf, callback: Callback) -> LibResult<()>
             download_url.to_string(),
             mod_file.clone(),
             callback,
+            cancel,
             None,
             false,
         )
@@ -94,58 +115,87 @@ pub async fn install_mods(mc_dir: PathBuf, callback: Callback) -> LibResult<()>
         {
             return Err(err);
         }
+        verify_archive(&mod_file)?;
 
-        callback(Event::Status(format!(
-            "Installed mod {}",
-            mod_file.display()
-        )));
+        callback.report(Event::Status(format!("Installed mod {}", jar)));
     }
 
     // Install shaderpack
-    let shaderpacks_dir = mc_dir.join("shaderpacks");
-
-    let shaderpacks_backup = mc_dir.join("shaderpacks.backup");
-
-    if let Err(_) = dircpy::copy_dir(&shaderpacks_dir, shaderpacks_backup) {
-        std::fs::create_dir(&shaderpacks_dir)
-            .expect("Failed to create .minecraft/shaderpacks folder");
-    }
+    let shaderpacks_dir = update.get_staged_dir(SHADERPACKS_DIR);
     let shader_url =
         "https://mediafiles.forgecdn.net/files/3928/682/ComplementaryReimagined_r1.2.2.zip";
     let url_tree: Vec<&str> = shader_url.split(&['/', '='][..]).collect();
     let shaderpack = *url_tree.last().unwrap();
     let shaderpack_file = shaderpacks_dir.join(shaderpack);
-    if let Err(err) = download_file(
-        shader_url.to_string(),
-        shaderpack_file.clone(),
-        callback,
-        None,
-        false,
-    )
-    .await
-    {
-        return Err(err);
+    // the staged shaderpack links the installed one, which has no checksum to check it against
+    if shaderpack_file.is_file() && verify_archive(&shaderpack_file).is_ok() {
+        callback.report(Event::Status(format!("Skipping shader {}", shaderpack)));
+    } else {
+        download_file(
+            shader_url.to_string(),
+            shaderpack_file.clone(),
+            callback,
+            cancel,
+            None,
+            false,
+        )
+        .await?;
+        verify_archive(&shaderpack_file)?;
+        callback.report(Event::Status(format!("Installed shader {}", shaderpack)));
     }
 
-    callback(Event::Status(format!(
-        "Installed shader {}",
-        shaderpack_file.display()
-    )));
-
     // Install shader settings
     let shader_settings_txt = include_str!("../../assets/ComplementaryReimagined_r1.2.2.zip.txt");
 
+    // the staged file can be a hard link to the live one, so replace it instead of writing into it
     let shaderpack_settings_txt_path =
         shaderpacks_dir.join("ComplementaryReimagined_r1.2.2.zip.txt");
-    let mut shaderpack_settings_txt_file = File::create(shaderpack_settings_txt_path).unwrap();
-    // writeln!(&mut shaderpack_settings_txt_file, "{}", shader_settings_txt).unwrap();
-    shaderpack_settings_txt_file
-        .write_fmt(format_args!("{}", shader_settings_txt))
-        .expect("Failed to write shaderpack settings .txt");
+    let _ = fs::remove_file(&shaderpack_settings_txt_path);
+    if let Err(err) = fs::write(shaderpack_settings_txt_path, shader_settings_txt) {
+        return Err(LauncherLibError::OS {
+            source: err,
+            msg: "Failed to write shaderpack settings .txt".into(),
+        });
+    }
 
     Ok(())
 }
 
+/// Updates the mods and shaderpacks. Everything is downloaded and checked in a
+/// staging directory first and only then swapped in, so a failed update leaves
+/// the previous mods, config and shaderpacks in place.
+pub async fn install_mods(mc_dir: PathBuf, callback: Callback<'_>, cancel: &CancellationToken) -> LibResult<()> {
+    callback.report(Event::phase(PHASE_MODS, 0));
+
+    match recover_update(&mc_dir) {
+        Ok(true) => callback.report(Event::Status("Recovered from an interrupted mod update".into())),
+        Ok(false) => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut mods_list = String::new();
+    download_file_to_string(
+        get_context().endpoints.mods_list.clone(),
+        &mut mods_list,
+        callback,
+        cancel,
+    )
+    .await?;
+
+    let update = match StagedUpdate::begin(&mc_dir, &[MODS_DIR, CONFIG_DIR, SHADERPACKS_DIR]) {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    if let Err(err) = stage_mods(&update, &mods_list, callback, cancel).await {
+        let _ = update.abort();
+        return Err(err);
+    }
+
+    callback.report(Event::Status("Applying mod update".into()));
+    update.commit()
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Version {
     #[serde(rename = "$value")]
@@ -171,26 +221,118 @@ pub struct MavenMetadata {
 }
 
 pub async fn get_metadata(root_url: &str) -> LibResult<MavenMetadata> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err),
-    };
-
-    match client
-        .get(format!("{}maven-metadata.xml", root_url).as_str())
-        .send()
-        .await
-    {
-        Ok(response) => match response.text().await {
-            Ok(value) => match serde_xml_rs::from_str::<MavenMetadata>(&value) {
-                Ok(res) => Ok(res),
-                Err(err) => Err(LauncherLibError::General(err.to_string())),
-            },
-            Err(err) => Err(LauncherLibError::PraseJsonReqwest(err)),
+    match get_text(format!("{}maven-metadata.xml", root_url).as_str()).await {
+        Ok(value) => match serde_xml_rs::from_str::<MavenMetadata>(&value) {
+            Ok(res) => Ok(res),
+            Err(err) => Err(LauncherLibError::General(err.to_string())),
         },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make http request".into(),
-        }),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{fixture_context, with_context, LauncherContext};
+    use crate::endpoints::Endpoints;
+    use crate::journal::open_journal;
+    use crate::retry::RetryPolicy;
+    use crate::transport::ReplayTransport;
+    use std::sync::Arc;
+
+    fn setup() -> PathBuf {
+        let mc_dir = std::env::temp_dir().join(format!("mc-mods-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(mc_dir.join(MODS_DIR)).unwrap();
+        fs::write(mc_dir.join(MODS_DIR).join("create-mc1.18.2_v0.4.0.jar"), "old").unwrap();
+        fs::write(mc_dir.join(MODS_DIR).join("unrelated.jar"), "kept").unwrap();
+        mc_dir
+    }
+
+    #[tokio::test]
+    async fn test_install_mods_from_fixture() {
+        let mc_dir = setup();
+
+        let result = with_context(
+            fixture_context("mods"),
+            install_mods(mc_dir.clone(), &|_: Event| {}, &CancellationToken::new()),
+        )
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        let mods = mc_dir.join(MODS_DIR);
+        assert!(!mods.join("create-mc1.18.2_v0.4.0.jar").exists());
+        assert!(mods.join("create-mc1.18.2_v0.5.0c.jar").is_file());
+        assert!(mods.join("flywheel-forge-1.18-0.6.4.jar").is_file());
+        assert!(mods.join("unrelated.jar").is_file());
+        assert!(mc_dir
+            .join(SHADERPACKS_DIR)
+            .join("ComplementaryReimagined_r1.2.2.zip.txt")
+            .is_file());
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_installed_shaderpack_is_not_downloaded_again() {
+        let mc_dir = setup();
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("mods");
+        let transport = Arc::new(ReplayTransport::from_dir(dir).unwrap());
+        let context = LauncherContext::new()
+            .retry_policy(RetryPolicy::none())
+            .transport(transport.clone());
+
+        with_context(context, async {
+            let result =
+                install_mods(mc_dir.clone(), &|_: Event| {}, &CancellationToken::new()).await;
+            assert!(result.is_ok(), "{:?}", result);
+
+            // the journal has no record of the shaderpack installed before it was opened
+            open_journal(&mc_dir);
+            let result =
+                install_mods(mc_dir.clone(), &|_: Event| {}, &CancellationToken::new()).await;
+            assert!(result.is_ok(), "{:?}", result);
+        })
+        .await;
+
+        let shader_downloads = transport
+            .get_requests()
+            .iter()
+            .filter(|request| request.ends_with("ComplementaryReimagined_r1.2.2.zip"))
+            .count();
+        assert_eq!(shader_downloads, 1);
+        assert!(mc_dir
+            .join(SHADERPACKS_DIR)
+            .join("ComplementaryReimagined_r1.2.2.zip")
+            .is_file());
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_mod_update_keeps_previous_mods() {
+        let mc_dir = setup();
+
+        let context = fixture_context("mods").endpoints(Endpoints {
+            mods_list: "https://example.com/broken/mods.md".into(),
+            ..Default::default()
+        });
+        let result = with_context(
+            context,
+            install_mods(mc_dir.clone(), &|_: Event| {}, &CancellationToken::new()),
+        )
+        .await;
+        assert!(result.is_err());
+
+        let mods = mc_dir.join(MODS_DIR);
+        assert!(mods.join("create-mc1.18.2_v0.4.0.jar").is_file());
+        assert!(!mods.join("create-mc1.18.2_v0.5.0c.jar").exists());
+        assert!(mods.join("unrelated.jar").is_file());
+        assert!(!mc_dir.join(SHADERPACKS_DIR).exists());
+        assert_eq!(fs::read_dir(&mc_dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(mc_dir).unwrap();
     }
 }
@@ -1,39 +1,52 @@
 use crate::expections::{LauncherLibError, LibResult };
+use crate::context::get_context;
+use crate::install::get_library_files;
+use crate::utils::parse_rule_list;
 use crate::json::{
+    game_settings::GameOptions,
     install::{
         ExtractFile,
         Library
     }
 };
 use std::env::consts;
-use std::fs::{ File, create_dir_all };
-use std::path::PathBuf;
-use std::io::Read;
+use std::fs::{ File, create_dir_all, remove_dir_all };
+use std::path::{ Path, PathBuf };
 use log::{ error };
 
+/// The natives classifier of an old style library for this platform, empty when it has none
 pub fn get_natives(library: &Library) -> String {
-    let arch = match consts::ARCH {
-        "x86" => "32",  
-        _ => "64"
+    get_natives_for(library, consts::OS, consts::ARCH)
+}
+
+/// The natives classifier of an old style library for `os` and `arch` as named by `std::env::consts`.
+/// `${arch}` in the classifier is the pointer width, ie `natives-windows-${arch}` becomes `natives-windows-64`.
+fn get_natives_for(library: &Library, os: &str, arch: &str) -> String {
+    let arch = match arch {
+        "x86" | "arm" => "32",
+        "x86_64" | "aarch64" => "64",
+        _ => if cfg!(target_pointer_width = "32") { "32" } else { "64" }
     };
 
     if let Some(native) = &library.natives {
-        let os = match consts::OS {
+        let os = match os {
             "macos" => "osx",
-            _ => consts::OS
+            _ => os
         };
 
         if let Some(value) = native.get(os) {
-            return value.clone().replace("{$arch}",arch);
+            return value.replace("${arch}",arch);
         }
     }
 
     String::default()
 }
 
-pub fn extract_natives_file(filename: PathBuf, extract_path: &PathBuf, extract_data: &ExtractFile) -> LibResult<()> {
+/// Extracts the natives jar into `extract_path`, skipping entries that start with
+/// one of the excluded prefixes and entries that would land outside of it
+pub fn extract_natives_file(filename: PathBuf, extract_path: &Path, extract_data: &ExtractFile) -> LibResult<()> {
 
-    if let Err(error) = create_dir_all(extract_path.clone()) {
+    if let Err(error) = create_dir_all(extract_path) {
         return Err(LauncherLibError::OS {
             msg: "Failed to create directory".into(),
             source: error
@@ -53,37 +66,53 @@ pub fn extract_natives_file(filename: PathBuf, extract_path: &PathBuf, extract_d
         Err(error) => return Err(LauncherLibError::ZipError(error))
     };
 
-    let mut ignores: Vec<String> = vec![];
-
-    for file in &extract_data.exclude {
-        ignores.push(file.clone());
-    }
-
     for i in 0..zip.len() {
         match zip.by_index(i) {
             Ok(mut item) => {
-               let mut skip = false;
-               for e in &ignores {
-                   if item.name().starts_with(e) {
-                       skip = true;
-                       break;
+               if extract_data.exclude.iter().any(|e| item.name().starts_with(e)) {
+                   continue;
+               }
+
+               // names like ../../evil.dll must not escape the natives directory
+               let output = match item.enclosed_name() {
+                   Some(value) => extract_path.join(value),
+                   None => {
+                       error!("Skipping unsafe natives entry {}",item.name());
+                       continue;
+                   }
+               };
+
+               if item.is_dir() {
+                   if let Err(err) = create_dir_all(&output) {
+                       return Err(LauncherLibError::OS {
+                           msg: "Failed to create directory".into(),
+                           source: err
+                       });
                    }
+                   continue;
                }
-               if skip { continue; }
 
-               let mut buffer: Vec<u8> = vec![];
-               if let Err(err) = item.read_to_end(&mut buffer) {
+               if let Some(parent) = output.parent() {
+                   if let Err(err) = create_dir_all(parent) {
+                       return Err(LauncherLibError::OS {
+                           msg: "Failed to create directory".into(),
+                           source: err
+                       });
+                   }
+               }
+
+               let mut file = match File::create(&output) {
+                   Ok(value) => value,
+                   Err(err) => return Err(LauncherLibError::OS {
+                       msg: "Failed to write file".into(),
+                       source: err
+                   })
+               };
+               if let Err(err) = std::io::copy(&mut item, &mut file) {
                    return Err(LauncherLibError::OS {
-                       msg: "Failed to write file to buffer".into(),
+                       msg: "Failed to write file".into(),
                        source: err
                    });
-               };
-
-               if let Err(err) = File::create(extract_path.join(item.name())) {
-                    return Err(LauncherLibError::OS {
-                        msg: "Failed to write file".into(),
-                        source: err
-                    });
                }
             }
             Err(err) => error!("{}",LauncherLibError::ZipError(err))
@@ -92,10 +121,163 @@ pub fn extract_natives_file(filename: PathBuf, extract_path: &PathBuf, extract_d
     Ok(())
 }
 
+/// The classifier of a library named `group:artifact:version:classifier`, without any `@extension`
+pub fn get_library_classifier(name: &str) -> Option<String> {
+    name.split(":").nth(3).map(|classifier| classifier.split("@").next().unwrap_or_default().to_string())
+}
+
+/// Whether the library is a natives jar of the 1.19+ kind, a library of its own
+/// with a `natives-<platform>` classifier instead of a `natives` map
+pub fn is_native_library(library: &Library) -> bool {
+    match get_library_classifier(&library.name) {
+        Some(classifier) => classifier.starts_with("natives-"),
+        None => false
+    }
+}
+
+/// Clears `natives_dir` of what earlier launches left and extracts the natives of the
+/// libraries that apply to this platform into it, in both the old and the 1.19+ format
+pub fn extract_natives(libraries: &[Library], mc_dir: &Path, natives_dir: &Path) -> LibResult<()> {
+    if natives_dir.exists() {
+        if let Err(err) = remove_dir_all(natives_dir) {
+            return Err(LauncherLibError::OS {
+                msg: "Failed to remove stale natives".into(),
+                source: err
+            });
+        }
+    }
+    if let Err(err) = create_dir_all(natives_dir) {
+        return Err(LauncherLibError::OS {
+            msg: "Failed to create directory".into(),
+            source: err
+        });
+    }
+
+    for library in libraries {
+        if let Some(rules) = &library.rules {
+            if !parse_rule_list(rules, &GameOptions::default()) {
+                continue;
+            }
+        }
+
+        let (parts,jar_filename,jar_filename_native) = match get_library_files(library) {
+            Ok(value) => value,
+            Err(err) => {
+                error!("{}",err);
+                continue;
+            }
+        };
+        let mut path = mc_dir.join("libraries");
+        for part in parts {
+            path = path.join(part);
+        }
+
+        let (jar, extract) = if !jar_filename_native.is_empty() {
+            (path.join(jar_filename_native), library.extract.clone().unwrap_or(ExtractFile { exclude: vec![] }))
+        } else if is_native_library(library) {
+            (path.join(jar_filename), library.extract.clone().unwrap_or(ExtractFile { exclude: vec!["META-INF/".into()] }))
+        } else {
+            continue;
+        };
+
+        if !jar.is_file() {
+            error!("Missing natives jar {}",jar.display());
+            continue;
+        }
+        extract_natives_file(jar, natives_dir, &extract)?;
+    }
+    Ok(())
+}
+
+/// LWJGL natives Mojang only lists for x86, by the os name rules use, and their ARM64 build
+const LWJGL_ARM64_NATIVES: [(&str,&str,&str);3] = [
+    ("linux","natives-linux","natives-linux-arm64"),
+    ("osx","natives-macos","natives-macos-arm64"),
+    ("windows","natives-windows","natives-windows-arm64")
+];
+
+/// First LWJGL release with ARM64 builds for linux, mac and windows
+const LWJGL_ARM64_VERSION: &str = "3.3.1";
+
+fn is_older_version(version: &str, than: &str) -> bool {
+    let parse = |value: &str| value.split(".").map(|part| part.parse::<u32>().unwrap_or(0)).collect::<Vec<u32>>();
+    parse(version) < parse(than)
+}
+
+/// On ARM64, swaps the x86 LWJGL natives of a version that has no ARM64 ones for the
+/// ARM64 builds, moving LWJGL to the first release that has them when the version's is older.
+/// Natives in the old `natives` map format become libraries of their own with the ARM64 classifier.
+pub fn substitute_arm64_natives(libraries: &mut Vec<Library>, os: &str, arch: &str) {
+    if arch != "aarch64" {
+        return;
+    }
+    let rule_os = match os {
+        "macos" => "osx",
+        _ => os
+    };
+    let (x86_classifier, arm_classifier) = match LWJGL_ARM64_NATIVES.iter().find(|(name,_,_)| *name == rule_os) {
+        Some((_,x86,arm)) => (*x86,*arm),
+        None => return
+    };
+
+    // 1.19+ lists the arm natives for mac and windows itself
+    if libraries.iter().any(|library| get_library_classifier(&library.name).as_deref() == Some(arm_classifier)) {
+        return;
+    }
+
+    let mut substituted: Vec<Library> = vec![];
+    for library in libraries.iter() {
+        if !library.name.starts_with("org.lwjgl:") {
+            substituted.push(library.clone());
+            continue;
+        }
+        let (group,artifact,version) = match get_library_data(library.name.clone()) {
+            Ok(value) => value,
+            Err(_) => {
+                substituted.push(library.clone());
+                continue;
+            }
+        };
+        let version = if is_older_version(&version, LWJGL_ARM64_VERSION) { LWJGL_ARM64_VERSION.to_string() } else { version };
+        let moved = !library.name.starts_with(&format!("{}:{}:{}",group,artifact,version));
+        let from_maven = |name: String| Library {
+            downloads: None,
+            name,
+            url: Some(get_context().endpoints.lwjgl_maven.clone()),
+            natives: None,
+            extract: None,
+            rules: library.rules.clone()
+        };
+
+        let has_old_natives = library.natives.as_ref().map(|natives| natives.contains_key(rule_os)).unwrap_or(false);
+        let classifier = get_library_classifier(&library.name);
+
+        if has_old_natives || classifier.as_deref() == Some(x86_classifier) {
+            if has_old_natives {
+                substituted.push(if moved {
+                    from_maven(format!("{}:{}:{}",group,artifact,version))
+                } else {
+                    Library { natives: None, extract: None, ..library.clone() }
+                });
+            }
+            substituted.push(from_maven(format!("{}:{}:{}:{}",group,artifact,version,arm_classifier)));
+        } else if moved && classifier.is_none() {
+            substituted.push(from_maven(format!("{}:{}:{}",group,artifact,version)));
+        } else {
+            substituted.push(library.clone());
+        }
+    }
+
+    // 1.14 to 1.18 list each LWJGL jar once plain and once with its natives
+    let mut seen = std::collections::HashSet::new();
+    substituted.retain(|library| seen.insert(library.name.clone()));
+    *libraries = substituted;
+}
+
 pub fn get_library_data(name: String) -> LibResult<(String,String,String)> {
     let data = name.split(":").collect::<Vec<&str>>();
 
-    if data.len() != 3 {
+    if data.len() < 3 {
         return Err(LauncherLibError::General("Library name does not content required params".into()))
     }
 
@@ -162,4 +344,110 @@ pub fn get_library_data(name: String) -> LibResult<(String,String,String)> {
     }
 
     Ok(())
-}*/
\ No newline at end of file
+}*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::write_jar;
+
+    #[test]
+    fn test_get_natives() {
+        let library: Library = serde_json::from_value(serde_json::json!({
+            "name": "tv.twitch:twitch-platform:5.16",
+            "natives": {
+                "linux": "natives-linux",
+                "osx": "natives-osx",
+                "windows": "natives-windows-${arch}"
+            }
+        })).unwrap();
+
+        assert_eq!(get_natives_for(&library, "windows", "x86"), "natives-windows-32");
+        assert_eq!(get_natives_for(&library, "windows", "x86_64"), "natives-windows-64");
+        assert_eq!(get_natives_for(&library, "windows", "aarch64"), "natives-windows-64");
+        assert_eq!(get_natives_for(&library, "macos", "aarch64"), "natives-osx");
+        assert_eq!(get_natives_for(&library, "linux", "x86_64"), "natives-linux");
+        assert_eq!(get_natives_for(&library, "freebsd", "x86_64"), "");
+    }
+
+    #[test]
+    fn test_extract_natives() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-natives-{}", uuid::Uuid::new_v4()));
+        let natives_dir = mc_dir.join("versions").join("1.19").join("natives");
+
+        let libraries: Vec<Library> = serde_json::from_value(serde_json::json!([
+            { "name": "org.lwjgl:lwjgl:3.3.1:natives-test" },
+            { "name": "org.lwjgl.lwjgl:lwjgl-platform:2.9.4", "natives": { consts::OS: "natives-old" }, "extract": { "exclude": ["META-INF/"] } },
+            { "name": "org.lwjgl:lwjgl:3.3.1" }
+        ])).unwrap();
+
+        write_jar(&mc_dir.join("libraries/org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1-natives-test.jar"), &[
+            ("META-INF/MANIFEST.MF", "Manifest-Version: 1.0"),
+            ("linux/x64/org/lwjgl/", ""),
+            ("linux/x64/org/lwjgl/liblwjgl.so", "new native"),
+            ("../evil.so", "escaped")
+        ]);
+        write_jar(&mc_dir.join("libraries/org/lwjgl/lwjgl/lwjgl-platform/2.9.4/lwjgl-platform-2.9.4-natives-old.jar"), &[
+            ("META-INF/MANIFEST.MF", "Manifest-Version: 1.0"),
+            ("liblwjgl.so", "old native")
+        ]);
+        write_jar(&mc_dir.join("libraries/org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1.jar"), &[("org/lwjgl/Version.class", "class")]);
+
+        create_dir_all(&natives_dir).unwrap();
+        std::fs::write(natives_dir.join("stale.so"), "stale").unwrap();
+
+        extract_natives(&libraries, &mc_dir, &natives_dir).unwrap();
+
+        assert_eq!(std::fs::read_to_string(natives_dir.join("linux/x64/org/lwjgl/liblwjgl.so")).unwrap(), "new native");
+        assert_eq!(std::fs::read_to_string(natives_dir.join("liblwjgl.so")).unwrap(), "old native");
+        assert!(!natives_dir.join("META-INF").exists());
+        assert!(!natives_dir.join("org").exists());
+        assert!(!natives_dir.join("stale.so").exists());
+        assert!(!natives_dir.parent().unwrap().join("evil.so").exists());
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[test]
+    fn test_substitute_arm64_natives() {
+        // 1.18 style: lwjgl 3.2.2 listed plain and with a natives map
+        let mut libraries: Vec<Library> = serde_json::from_value(serde_json::json!([
+            { "name": "org.lwjgl:lwjgl:3.2.2", "downloads": { "artifact": { "sha1": "a", "size": 1, "url": "https://libraries.minecraft.net/org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2.jar" } } },
+            { "name": "org.lwjgl:lwjgl:3.2.2", "natives": { "linux": "natives-linux", "osx": "natives-macos" } },
+            { "name": "com.mojang:brigadier:1.0.18" }
+        ])).unwrap();
+        substitute_arm64_natives(&mut libraries, "macos", "aarch64");
+        let names: Vec<&str> = libraries.iter().map(|library| library.name.as_str()).collect();
+        assert_eq!(names, vec!["org.lwjgl:lwjgl:3.3.1","org.lwjgl:lwjgl:3.3.1:natives-macos-arm64","com.mojang:brigadier:1.0.18"]);
+        assert!(libraries[0].downloads.is_none());
+        assert_eq!(libraries[1].url.as_deref(), Some("https://repo1.maven.org/maven2/"));
+
+        // 1.19 style: the arm natives are listed for mac, not for linux
+        let json = serde_json::json!([
+            { "name": "org.lwjgl:lwjgl:3.3.1" },
+            { "name": "org.lwjgl:lwjgl:3.3.1:natives-linux" },
+            { "name": "org.lwjgl:lwjgl:3.3.1:natives-macos" },
+            { "name": "org.lwjgl:lwjgl:3.3.1:natives-macos-arm64" }
+        ]);
+        let mut mac: Vec<Library> = serde_json::from_value(json.clone()).unwrap();
+        substitute_arm64_natives(&mut mac, "macos", "aarch64");
+        assert_eq!(mac.len(), 4);
+        assert_eq!(mac[2].name, "org.lwjgl:lwjgl:3.3.1:natives-macos");
+
+        let mut linux: Vec<Library> = serde_json::from_value(json.clone()).unwrap();
+        substitute_arm64_natives(&mut linux, "linux", "aarch64");
+        assert_eq!(linux[1].name, "org.lwjgl:lwjgl:3.3.1:natives-linux-arm64");
+        assert_eq!(linux[0].name, "org.lwjgl:lwjgl:3.3.1");
+
+        let mut x86: Vec<Library> = serde_json::from_value(json).unwrap();
+        substitute_arm64_natives(&mut x86, "linux", "x86_64");
+        assert_eq!(x86[1].name, "org.lwjgl:lwjgl:3.3.1:natives-linux");
+    }
+
+    #[test]
+    fn test_get_library_classifier() {
+        assert_eq!(get_library_classifier("org.lwjgl:lwjgl:3.3.1:natives-windows-arm64"), Some("natives-windows-arm64".into()));
+        assert_eq!(get_library_classifier("org.example:demo:1.0:natives-linux@zip"), Some("natives-linux".into()));
+        assert_eq!(get_library_classifier("org.lwjgl:lwjgl:3.3.1"), None);
+        assert_eq!(get_library_data("org.lwjgl:lwjgl:3.3.1:natives-linux".into()).unwrap().2, "3.3.1");
+    }
+}
@@ -1,7 +1,10 @@
-use crate::utils::{ get_http_client, download_file };
+use crate::cancel::{ run_command, CancellationToken };
+use crate::utils::{ get_text, download_file };
 use crate::expections::{ LauncherLibError,LibResult };
+use crate::context::get_context;
 use crate::runtime::get_exectable_path;
-use crate::install::install_minecraft_version;
+use crate::install::{ install_minecraft_version, is_version_installed };
+use crate::progress::PHASE_LOADER;
 use crate::json::{
     runtime::MinecraftJavaRuntime,
     install::{
@@ -15,9 +18,7 @@ use std::process::{ Stdio };
 use std::path::PathBuf;
 use log::{info};
 
-const OPTIFINE_HEADLESS: &str = "https://github.com/VisualSource/mc-installer-v2/raw/master/wellknowns/jars/optifineheadless.jar";
 //const OPTIFINE_HEADLESS_SHA1: &str = "";
-const OPTIFINE_DOWNLOADS_PAGE: &str = "https://optifine.net/downloads";
 
 #[derive(Debug, Default, Clone)]
 pub struct OptifineVersion {
@@ -27,90 +28,65 @@ pub struct OptifineVersion {
 }
 
 pub async fn get_optifine_versions() -> LibResult<Vec<OptifineVersion>> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
+    match get_text(&format!("{}downloads",get_context().endpoints.optifine)).await {
+        Ok(html) => {   
+            let document = Html::parse_document(&html);
+            let selector = Selector::parse("tr.downloadLine.downloadLineMain").expect("Failed to parse html query");
+            let slector_name = Selector::parse("td.colFile").expect("Failed to make selector");
+            let slector_url = Selector::parse("td.colMirror > a").expect("Failed to make selector");
+
+            let mut versions: Vec<OptifineVersion> = vec![];
+
+            for element in document.select(&selector) {
+                if let Some(name_raw) = element.select(&slector_name).collect::<Vec<scraper::element_ref::ElementRef>>().get(0) {
+                    let mut version = OptifineVersion::default();
+                    if let Some(name) = name_raw.text().collect::<Vec<&str>>().get(0) {
+                        version.name = name.to_string().replace("OptiFine ","").replace(" ", "_");
+                    } 
+                    if let Some(url_raw) = element.select(&slector_url).collect::<Vec<scraper::element_ref::ElementRef>>().get(0) {
+                        if let Some(url) = url_raw.value().attr("href") {
+                            version.url = url.to_string();
+                        }
+                    }
 
-    match client.get(OPTIFINE_DOWNLOADS_PAGE).send().await {
-        Ok(value) => {
-            match value.text().await {
-                Ok(html) => {   
-                    let document = Html::parse_document(&html);
-                    let selector = Selector::parse("tr.downloadLine.downloadLineMain").expect("Failed to parse html query");
-                    let slector_name = Selector::parse("td.colFile").expect("Failed to make selector");
-                    let slector_url = Selector::parse("td.colMirror > a").expect("Failed to make selector");
-
-                    let mut versions: Vec<OptifineVersion> = vec![];
-
-                    for element in document.select(&selector) {
-                        if let Some(name_raw) = element.select(&slector_name).collect::<Vec<scraper::element_ref::ElementRef>>().get(0) {
-                            let mut version = OptifineVersion::default();
-                            if let Some(name) = name_raw.text().collect::<Vec<&str>>().get(0) {
-                                version.name = name.to_string().replace("OptiFine ","").replace(" ", "_");
-                            } 
-                            if let Some(url_raw) = element.select(&slector_url).collect::<Vec<scraper::element_ref::ElementRef>>().get(0) {
-                                if let Some(url) = url_raw.value().attr("href") {
-                                    version.url = url.to_string();
-                                }
-                            }
-
-                            if let Some(mc_raw) = version.url.split("=").collect::<Vec<&str>>().get(1) {
-                                if let Some(mc) = mc_raw.replace("OptiFine_","").replace("_"," ").split(" ").collect::<Vec<&str>>().get(0) {
-                                    version.mc = mc.to_string();
-                                }
-                            }
-
-                            versions.push(version);
+                    if let Some(mc_raw) = version.url.split("=").collect::<Vec<&str>>().get(1) {
+                        if let Some(mc) = mc_raw.replace("OptiFine_","").replace("_"," ").split(" ").collect::<Vec<&str>>().get(0) {
+                            version.mc = mc.to_string();
                         }
                     }
 
-                    Ok(versions)
+                    versions.push(version);
                 }
-                Err(err) => Err(LauncherLibError::General(err.to_string()))
             }
+
+            Ok(versions)
         }
-        Err(err) => Err(LauncherLibError::HTTP { 
-            source: err,
-            msg: "Failed to make request".into()
-        })
+        Err(err) => Err(err)
     }
 }
 
 pub async fn get_optifine_download(url: String) -> LibResult<String> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-    match client.get(url).send().await {
-        Ok(value) => {
-            match value.text().await {
-                Ok(html) => {
-                    let document = Html::parse_document(&html);
-
-                    let selector = Selector::parse("span#Download > a").expect("Failed to parse html query");
+    match get_text(&url).await {
+        Ok(html) => {
+            let document = Html::parse_document(&html);
 
-                    let content = document.select(&selector).collect::<Vec<scraper::ElementRef>>();
+            let selector = Selector::parse("span#Download > a").expect("Failed to parse html query");
 
-                    if let Some(link) = content.get(0) {
-                       if let Some(a) = link.value().attr("href") {
-                           return Ok(format!("https://optifine.net/{}",a.to_string()).into());
-                       }
-                    }
+            let content = document.select(&selector).collect::<Vec<scraper::ElementRef>>();
 
-                    Err(LauncherLibError::General("Failed to get optifine download url".into()))
-                }
-                Err(err) => Err(LauncherLibError::General(err.to_string()))
+            if let Some(link) = content.get(0) {
+               if let Some(a) = link.value().attr("href") {
+                   return Ok(format!("{}{}",get_context().endpoints.optifine,a.to_string()).into());
+               }
             }
+
+            Err(LauncherLibError::General("Failed to get optifine download url".into()))
         }
-        Err(err) => Err(LauncherLibError::HTTP{
-            source: err,
-            msg: "Failed to get file jar".into()
-        })
+        Err(err) => Err(err)
     }
 }
 
-pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, callback: Callback, cache_path: Option<PathBuf>, loader: Option<String>, java: Option<PathBuf>, cache_headless: bool, cache_installer: bool) -> LibResult<()> {
+pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, callback: Callback<'_>, cancel: &CancellationToken, cache_path: Option<PathBuf>, loader: Option<String>, java: Option<PathBuf>, cache_headless: bool, cache_installer: bool) -> LibResult<()> {
     let versions: Vec<OptifineVersion> = match get_optifine_versions().await {
         Ok(value) => value,
         Err(err) => return Err(err)
@@ -166,30 +142,25 @@ pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, c
         temp_path.join(installer_jar.clone())
     };
 
-    callback(Event::Status("Checking for vanilla minecraft".into()));
-    if !mc_dir.join("versions").join(mc.clone()).join(format!("{}.json",mc.clone())).is_file() {
-        if let Err(err) = install_minecraft_version(mc.clone(),mc_dir.clone(),callback).await {
-            return Err(err);
-        }
+    callback.report(Event::Status("Checking for vanilla minecraft".into()));
+    if !is_version_installed(&mc, &mc_dir) {
+        install_minecraft_version(mc.clone(),mc_dir.clone(),callback, cancel).await?;
     }
 
-    callback(Event::Status("Downloading OptiFine Headless".into()));
-    callback(Event::progress(0, 2));
-    if let Err(err) = download_file(OPTIFINE_HEADLESS.into(), headless_path.clone(), callback, None, false).await {
-        return Err(err);
-    }   
-    callback(Event::progress(1, 2));
+    callback.report(Event::phase(PHASE_LOADER, 0));
+    callback.report(Event::Status("Downloading OptiFine Headless".into()));
+    callback.report(Event::progress(0, 2));
+    download_file(get_context().endpoints.optifine_headless.clone(), headless_path.clone(), callback, cancel, None, false).await?;
+    callback.report(Event::progress(1, 2));
 
     let download_url = match get_optifine_download(version.url.clone()).await {
         Ok(value) => value,
         Err(err) => return Err(err)
     };
 
-    callback(Event::Status("Downloading OptiFine".into()));
-    if let Err(err) = download_file(download_url, installer_path.clone(), callback, None, false).await {
-        return Err(err);
-    }
-    callback(Event::progress(2, 2));
+    callback.report(Event::Status("Downloading OptiFine".into()));
+    download_file(download_url, installer_path.clone(), callback, cancel, None, false).await?;
+    callback.report(Event::progress(2, 2));
 
     let exec: String = match java {
         Some(value) => value.to_str().expect("Failed to make string").into(),
@@ -213,14 +184,14 @@ pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, c
         mc_dir.to_str().expect("Failed to convert to str")
     ];
 
-    match Command::new(exec).args(args).stdout(Stdio::inherit()).output().await {
+    match run_command(Command::new(exec).args(args).stdout(Stdio::inherit()), cancel).await {
         Ok(output) => {
             info!("Stderr: {}",String::from_utf8_lossy(&output.stderr));
             info!("Stdout: {}",String::from_utf8_lossy(&output.stdout));
             info!("Status: {}",output.status);
 
-            callback(Event::Status("Starting cleanup".into()));
-            callback(Event::progress(0, 2));
+            callback.report(Event::Status("Starting cleanup".into()));
+            callback.report(Event::progress(0, 2));
             if !cache_headless {
                 if let Err(err) = remove_file(headless_path).await {
                     return Err(LauncherLibError::OS {
@@ -229,7 +200,7 @@ pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, c
                     });
                 }
             }
-            callback(Event::progress(1, 2));
+            callback.report(Event::progress(1, 2));
 
             if !cache_installer {
                 if let Err(err) = remove_file(installer_path).await {
@@ -239,12 +210,9 @@ pub async fn install_optifine(mc: String, mc_dir: PathBuf, temp_path: PathBuf, c
                     });
                 }
             }
-            callback(Event::progress(2, 2));
+            callback.report(Event::progress(2, 2));
         }
-        Err(err) => return Err(LauncherLibError::OS {
-            source: err,
-            msg: "Failed to run command".into()
-        }) 
+        Err(err) => return Err(err)
     };
 
     Ok(())
@@ -269,7 +237,7 @@ mod tests {
     async fn test_install_optifine() {
         let mc_dir = PathBuf::from("C:\\Users\\Collin\\AppData\\Roaming\\.minecraft");
         let temp_path = PathBuf::from("C:\\Users\\Collin\\Downloads\\");
-        if let Err(err) = install_optifine("1.18.1".into(), mc_dir, temp_path, |e|{ println!("{:#?}",e) }, None, None, None, false,false).await {
+        if let Err(err) = install_optifine("1.18.1".into(), mc_dir, temp_path, &|e: Event|{ println!("{:#?}",e) }, &CancellationToken::new(), None, None, None, false,false).await {
             eprintln!("{}",err);
         }
     }
@@ -0,0 +1,413 @@
+use crate::command::get_launch_command;
+use crate::expections::LibResult;
+use crate::install::get_library_files;
+use crate::json::{game_settings::GameOptions, install::VersionManifest};
+use crate::natives::{get_natives, is_native_library};
+use crate::utils::{get_classpath_separator, get_sha1, parse_rule_list, read_manifest_inherit};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Something that would stop the game from starting
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreflightProblem {
+    MissingClasspathEntry(PathBuf),
+    ClasspathHashMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// the version has natives but none were extracted
+    EmptyNatives(PathBuf),
+    /// java could not be run, or its version not read
+    JavaUnusable {
+        java: String,
+        reason: String,
+    },
+    JavaVersionMismatch {
+        java: String,
+        required: usize,
+        found: usize,
+    },
+    GameDirectoryNotWritable {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+/// Something that may stop the game from starting, but does not always
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreflightWarning {
+    /// launchwrapper versions often break on java newer than 8
+    LaunchwrapperOnNewerJava { java: String, found: usize },
+}
+
+#[derive(Debug, Default)]
+pub struct PreflightReport {
+    /// the java the game would be started with
+    pub java: String,
+    pub java_version: Option<usize>,
+    pub problems: Vec<PreflightProblem>,
+    pub warnings: Vec<PreflightWarning>,
+}
+impl PreflightReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Major version from the output of `java -version`, ie 8 for `"1.8.0_292"` and 17 for `"17.0.3"`
+pub fn parse_java_version(output: &str) -> Option<usize> {
+    let start = output.find("version \"")? + "version \"".len();
+    let version = &output[start..];
+    let version = &version[..version.find('"')?];
+    let mut parts = version.split(['.', '_', '-', '+']);
+    let major = match parts.next()? {
+        "1" => parts.next()?,
+        major => major,
+    };
+    major.parse().ok()
+}
+
+/// Whether java `found` can run a version that asks for `required`
+pub fn is_java_compatible(required: usize, found: usize) -> bool {
+    found >= required
+}
+
+const LAUNCHWRAPPER_MAIN_CLASS: &str = "net.minecraft.launchwrapper.Launch";
+
+/// What `java -version` prints
+pub(crate) async fn get_java_version_output(java: &str) -> Result<String, String> {
+    let output = match Command::new(java).arg("-version").output().await {
+        Ok(value) => value,
+        Err(err) => return Err(err.to_string()),
+    };
+    // java prints its version to stderr
+    Ok(format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stderr),
+        String::from_utf8_lossy(&output.stdout)
+    ))
+}
+
+async fn get_java_version(java: &str) -> Result<usize, String> {
+    let text = match get_java_version_output(java).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+    match parse_java_version(&text) {
+        Some(value) => Ok(value),
+        None => Err(format!("Unknown java version output: {}", text.trim())),
+    }
+}
+
+/// Expected sha1 of the library jars, by where they are on disk
+fn get_library_hashes(manifest: &VersionManifest, mc_dir: &Path) -> HashMap<PathBuf, String> {
+    let mut hashes = HashMap::new();
+    for library in &manifest.libraries {
+        let downloads = match &library.downloads {
+            Some(value) => value,
+            None => continue,
+        };
+        let (parts, jar_filename, jar_filename_native) = match get_library_files(library) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let dir = parts
+            .iter()
+            .fold(mc_dir.join("libraries"), |path, part| path.join(part));
+        hashes.insert(dir.join(jar_filename), downloads.artifact.sha1.clone());
+        if let Some(native) = downloads
+            .classifiers
+            .as_ref()
+            .and_then(|classifiers| classifiers.get(&get_natives(library)))
+        {
+            hashes.insert(dir.join(jar_filename_native), native.sha1.clone());
+        }
+    }
+    hashes
+}
+
+fn has_natives(manifest: &VersionManifest) -> bool {
+    manifest.libraries.iter().any(|library| {
+        let applies = match &library.rules {
+            Some(rules) => parse_rule_list(rules, &GameOptions::default()),
+            None => true,
+        };
+        applies && (!get_natives(library).is_empty() || is_native_library(library))
+    })
+}
+
+fn check_writable(dir: &Path) -> Result<(), String> {
+    if let Err(err) = std::fs::create_dir_all(dir) {
+        return Err(err.to_string());
+    }
+    let probe = dir.join(format!(".preflight-{}", uuid::Uuid::new_v4()));
+    match std::fs::write(&probe, b"") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(probe);
+            Ok(())
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Resolves the launch command of the version the way `Client::start` does and checks
+/// everything it needs is in place: the classpath, with hashes when `check_hashes` is set,
+/// the natives, the java version and a writable game directory.
+pub async fn preflight(
+    version: String,
+    mc_dir: PathBuf,
+    options: &mut GameOptions,
+    check_hashes: bool,
+) -> LibResult<PreflightReport> {
+    let version_json = mc_dir
+        .join("versions")
+        .join(&version)
+        .join(format!("{}.json", version));
+    let (java, _) = match get_launch_command(version, mc_dir.clone(), options).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+    let manifest = match read_manifest_inherit(version_json, &mc_dir).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    let mut report = PreflightReport {
+        java: java.clone(),
+        ..Default::default()
+    };
+
+    let hashes = if check_hashes {
+        get_library_hashes(&manifest, &mc_dir)
+    } else {
+        HashMap::new()
+    };
+    let classpath = options.classpath.clone().unwrap_or_default();
+    for entry in classpath.split(get_classpath_separator().as_str()) {
+        let path = PathBuf::from(entry);
+        if !path.is_file() {
+            report
+                .problems
+                .push(PreflightProblem::MissingClasspathEntry(path));
+            continue;
+        }
+        if let Some(expected) = hashes.get(&path) {
+            match get_sha1(path.clone()).await {
+                Ok(actual) if actual != *expected => {
+                    report
+                        .problems
+                        .push(PreflightProblem::ClasspathHashMismatch {
+                            path,
+                            expected: expected.clone(),
+                            actual,
+                        })
+                }
+                Ok(_) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    if let Some(natives) = &options.navtives_directory {
+        let is_empty = match std::fs::read_dir(natives) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(_) => true,
+        };
+        if is_empty && has_natives(&manifest) {
+            report
+                .problems
+                .push(PreflightProblem::EmptyNatives(natives.clone()));
+        }
+    }
+
+    match get_java_version(&java).await {
+        Ok(found) => {
+            report.java_version = Some(found);
+            if let Some(required) = manifest
+                .java_version
+                .as_ref()
+                .map(|java| java.major_version)
+            {
+                if !is_java_compatible(required, found) {
+                    report.problems.push(PreflightProblem::JavaVersionMismatch {
+                        java: java.clone(),
+                        required,
+                        found,
+                    });
+                }
+            }
+            if manifest.main_class == LAUNCHWRAPPER_MAIN_CLASS && found > 8 {
+                report
+                    .warnings
+                    .push(PreflightWarning::LaunchwrapperOnNewerJava {
+                        java: java.clone(),
+                        found,
+                    });
+            }
+        }
+        Err(reason) => report
+            .problems
+            .push(PreflightProblem::JavaUnusable { java, reason }),
+    }
+
+    let game_dir = options.game_directory.clone().unwrap_or(mc_dir);
+    if let Err(reason) = check_writable(&game_dir) {
+        report
+            .problems
+            .push(PreflightProblem::GameDirectoryNotWritable {
+                path: game_dir,
+                reason,
+            });
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancellationToken;
+    use crate::context::{fixture_context, with_context};
+    use crate::install::install_minecraft_version;
+    use crate::json::install::Event;
+
+    #[test]
+    fn test_parse_java_version() {
+        assert_eq!(
+            parse_java_version(
+                "openjdk version \"17.0.3\" 2022-04-19\nOpenJDK Runtime Environment"
+            ),
+            Some(17)
+        );
+        assert_eq!(parse_java_version("java version \"1.8.0_292\""), Some(8));
+        assert_eq!(
+            parse_java_version("openjdk version \"21\" 2023-09-19"),
+            Some(21)
+        );
+        assert_eq!(parse_java_version("command not found"), None);
+        assert!(is_java_compatible(17, 21));
+        assert!(!is_java_compatible(17, 8));
+        assert!(is_java_compatible(8, 17));
+        assert!(is_java_compatible(17, 17));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_preflight_finds_problems() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mc_dir = std::env::temp_dir().join(format!("mc-preflight-{}", uuid::Uuid::new_v4()));
+        with_context(
+            fixture_context("install"),
+            install_minecraft_version(
+                "fixture-1.0".into(),
+                mc_dir.clone(),
+                &|_: Event| {},
+                &CancellationToken::new(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let java = mc_dir.join("java");
+        std::fs::write(
+            &java,
+            "#!/bin/sh\necho 'openjdk version \"1.8.0_292\"' >&2\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let options = GameOptions {
+            executable_path: Some(java.clone()),
+            username: Some("Steve".into()),
+            uuid: Some("uuid".into()),
+            token: Some("token".into()),
+            ..Default::default()
+        };
+
+        let report = preflight(
+            "fixture-1.0".into(),
+            mc_dir.clone(),
+            &mut options.clone(),
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(report.java_version, Some(8));
+
+        let json = mc_dir.join("versions/fixture-1.0/fixture-1.0.json");
+        let mut manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        manifest["javaVersion"] =
+            serde_json::json!({ "component": "java-runtime-gamma", "majorVersion": 17 });
+        std::fs::write(&json, manifest.to_string()).unwrap();
+
+        let library = mc_dir.join("libraries/org/example/demo/1.0/demo-1.0.jar");
+        let mut bad = std::fs::read(&library).unwrap();
+        bad[0] ^= 0xff;
+        std::fs::write(&library, &bad).unwrap();
+        std::fs::remove_file(mc_dir.join("versions/fixture-1.0/fixture-1.0.jar")).unwrap();
+
+        let blocker = mc_dir.join("not-a-dir");
+        std::fs::write(&blocker, "").unwrap();
+        let mut options = GameOptions {
+            game_directory: Some(blocker.join("game")),
+            ..options
+        };
+
+        let report = preflight("fixture-1.0".into(), mc_dir.clone(), &mut options, true)
+            .await
+            .unwrap();
+        assert_eq!(report.problems.len(), 4, "{:?}", report.problems);
+        assert!(report
+            .problems
+            .contains(&PreflightProblem::MissingClasspathEntry(
+                mc_dir.join("versions/fixture-1.0/fixture-1.0.jar")
+            )));
+        assert!(report.problems.iter().any(|problem| matches!(
+            problem,
+            PreflightProblem::ClasspathHashMismatch { path, .. } if *path == library
+        )));
+        assert!(report
+            .problems
+            .contains(&PreflightProblem::JavaVersionMismatch {
+                java: java.to_string_lossy().to_string(),
+                required: 17,
+                found: 8
+            }));
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| matches!(problem, PreflightProblem::GameDirectoryNotWritable { .. })));
+
+        // newer java runs a launchwrapper version, but is worth a warning
+        std::fs::write(
+            &java,
+            "#!/bin/sh\necho 'openjdk version \"17.0.3\" 2022-04-19' >&2\n",
+        )
+        .unwrap();
+        manifest["mainClass"] = LAUNCHWRAPPER_MAIN_CLASS.into();
+        manifest["javaVersion"] =
+            serde_json::json!({ "component": "jre-legacy", "majorVersion": 8 });
+        std::fs::write(&json, manifest.to_string()).unwrap();
+        let report = preflight("fixture-1.0".into(), mc_dir.clone(), &mut options, true)
+            .await
+            .unwrap();
+        assert!(!report
+            .problems
+            .iter()
+            .any(|problem| matches!(problem, PreflightProblem::JavaVersionMismatch { .. })));
+        assert_eq!(
+            report.warnings,
+            vec![PreflightWarning::LaunchwrapperOnNewerJava {
+                java: java.to_string_lossy().to_string(),
+                found: 17
+            }]
+        );
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
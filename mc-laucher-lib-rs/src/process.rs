@@ -0,0 +1,386 @@
+use crate::expections::{LauncherLibError, LibResult};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// How long `Client::exit` gives the game to close before killing it
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One `<log4j:Event>` written by the game when the log config is enabled
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LogRecord {
+    pub level: String,
+    pub logger: String,
+    pub thread: String,
+    /// milliseconds since the unix epoch
+    pub timestamp: Option<u64>,
+    pub message: String,
+    /// stack trace logged with the message
+    pub throwable: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    Stdout(String),
+    Stderr(String),
+    Log(LogRecord),
+    /// last event of a process. `None` when it was ended by a signal.
+    Exited(Option<i32>),
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
+
+fn get_attribute(tag: &str, name: &str) -> Option<String> {
+    let key = format!(" {}=\"", name);
+    let start = tag.find(&key)? + key.len();
+    let end = tag[start..].find('"')?;
+    Some(unescape_xml(&tag[start..start + end]))
+}
+
+/// Text of `<name>...</name>`, which log4j writes as CDATA
+fn get_element(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)?;
+    let text = xml[start..start + end].trim();
+    match text
+        .strip_prefix("<![CDATA[")
+        .and_then(|text| text.strip_suffix("]]>"))
+    {
+        Some(value) => Some(value.to_string()),
+        None => Some(unescape_xml(text)),
+    }
+}
+
+/// Parses one complete `<log4j:Event ...>...</log4j:Event>` record
+pub fn parse_log4j_event(xml: &str) -> Option<LogRecord> {
+    let start = xml.find("<log4j:Event")?;
+    let end = start + xml[start..].find('>')?;
+    let tag = &xml[start..end];
+    Some(LogRecord {
+        level: get_attribute(tag, "level")?,
+        logger: get_attribute(tag, "logger").unwrap_or_default(),
+        thread: get_attribute(tag, "thread").unwrap_or_default(),
+        timestamp: get_attribute(tag, "timestamp").and_then(|value| value.parse().ok()),
+        message: get_element(xml, "log4j:Message").unwrap_or_default(),
+        throwable: get_element(xml, "log4j:Throwable"),
+    })
+}
+
+/// Turns stdout lines into events, gathering the lines of log4j records.
+/// Anything outside a record, like output from mods printing directly, stays a line.
+#[derive(Debug, Default)]
+struct Log4jParser {
+    record: Option<Vec<String>>,
+}
+impl Log4jParser {
+    fn push(&mut self, line: String) -> Vec<GameEvent> {
+        let mut lines = match self.record.take() {
+            Some(lines) => lines,
+            None if line.trim_start().starts_with("<log4j:Event") => vec![],
+            None => return vec![GameEvent::Stdout(line)],
+        };
+        lines.push(line);
+        if !lines
+            .last()
+            .map(|line| line.contains("</log4j:Event>"))
+            .unwrap_or(false)
+        {
+            self.record = Some(lines);
+            return vec![];
+        }
+        match parse_log4j_event(&lines.join("\n")) {
+            Some(record) => vec![GameEvent::Log(record)],
+            None => lines.into_iter().map(GameEvent::Stdout).collect(),
+        }
+    }
+
+    /// Lines of a record the stream ended in
+    fn finish(&mut self) -> Vec<GameEvent> {
+        match self.record.take() {
+            Some(lines) => lines.into_iter().map(GameEvent::Stdout).collect(),
+            None => vec![],
+        }
+    }
+}
+
+fn read_lines<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    sender: UnboundedSender<GameEvent>,
+    parser: Option<Log4jParser>,
+    stderr: bool,
+) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        let mut parser = parser;
+        while let Ok(Some(line)) = lines.next_line().await {
+            let events = match (&mut parser, stderr) {
+                (Some(parser), _) => parser.push(line),
+                (None, true) => vec![GameEvent::Stderr(line)],
+                (None, false) => vec![GameEvent::Stdout(line)],
+            };
+            for event in events {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+        if let Some(parser) = &mut parser {
+            for event in parser.finish() {
+                let _ = sender.send(event);
+            }
+        }
+    });
+}
+
+/// A running game whose output is read in the background
+#[derive(Debug)]
+pub struct GameProcess {
+    child: Child,
+    events: UnboundedReceiver<GameEvent>,
+    exit_code: Option<Option<i32>>,
+    exit_reported: bool,
+}
+impl GameProcess {
+    /// Starts the program with its output piped. With `log4j_xml` stdout is read as the
+    /// xml layout of the game's log config and records come as [`GameEvent::Log`].
+    pub fn spawn(program: &str, args: &[String], log4j_xml: bool) -> LibResult<Self> {
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to launch minecraft".into(),
+                })
+            }
+        };
+
+        let (sender, events) = unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            let parser = if log4j_xml {
+                Some(Log4jParser::default())
+            } else {
+                None
+            };
+            read_lines(stdout, sender.clone(), parser, false);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, sender, None, true);
+        }
+
+        Ok(Self {
+            child,
+            events,
+            exit_code: None,
+            exit_reported: false,
+        })
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// The next line or log record. Once all output is read this waits for the
+    /// process and gives [`GameEvent::Exited`], then `None`.
+    pub async fn next_event(&mut self) -> Option<GameEvent> {
+        if let Some(event) = self.events.recv().await {
+            return Some(event);
+        }
+        if self.exit_reported {
+            return None;
+        }
+        self.exit_reported = true;
+        match self.wait().await {
+            Ok(code) => Some(GameEvent::Exited(code)),
+            Err(_) => None,
+        }
+    }
+
+    /// The exit code if the process has exited, without waiting
+    pub fn try_wait(&mut self) -> LibResult<Option<Option<i32>>> {
+        if self.exit_code.is_some() {
+            return Ok(self.exit_code);
+        }
+        match self.child.try_wait() {
+            Ok(status) => {
+                self.exit_code = status.map(|status| status.code());
+                Ok(self.exit_code)
+            }
+            Err(err) => Err(LauncherLibError::General(err.to_string())),
+        }
+    }
+
+    /// Waits for the process to exit, `None` when it was ended by a signal
+    pub async fn wait(&mut self) -> LibResult<Option<i32>> {
+        if let Some(code) = self.exit_code {
+            return Ok(code);
+        }
+        match self.child.wait().await {
+            Ok(status) => {
+                self.exit_code = Some(status.code());
+                Ok(status.code())
+            }
+            Err(err) => Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to wait for minecraft".into(),
+            }),
+        }
+    }
+
+    /// Ends the process at once
+    pub async fn kill(&mut self) -> LibResult<Option<i32>> {
+        if let Ok(Some(code)) = self.try_wait() {
+            return Ok(code);
+        }
+        if let Err(err) = self.child.kill().await {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to kill minecraft".into(),
+            });
+        }
+        self.wait().await
+    }
+
+    /// Asks the game to close, SIGTERM on unix and a window close on windows,
+    /// and kills it when it hasn't exited within the timeout.
+    pub async fn stop(&mut self, timeout: Duration) -> LibResult<Option<i32>> {
+        if let Ok(Some(code)) = self.try_wait() {
+            return Ok(code);
+        }
+        let id = match self.id() {
+            Some(value) => value.to_string(),
+            None => return self.wait().await,
+        };
+        let request = if cfg!(windows) {
+            Command::new("taskkill").args(["/PID", &id]).output().await
+        } else {
+            Command::new("kill").args(["-TERM", &id]).output().await
+        };
+        if request.is_err() {
+            return self.kill().await;
+        }
+        match tokio::time::timeout(timeout, self.wait()).await {
+            Ok(result) => result,
+            Err(_) => self.kill().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log4j_parser() {
+        let mut parser = Log4jParser::default();
+        let lines = [
+            "[STDOUT]: printed by a mod",
+            r#"<log4j:Event logger="net.minecraft.client.Minecraft" timestamp="1650000000000" level="INFO" thread="Render thread">"#,
+            "  <log4j:Message><![CDATA[Setting user: <Steve> & co]]></log4j:Message>",
+            "</log4j:Event>",
+            r#"<log4j:Event logger="fml" timestamp="1" level="ERROR" thread="&quot;main&quot;">"#,
+            "  <log4j:Message>a &lt; b</log4j:Message>",
+            "  <log4j:Throwable><![CDATA[java.lang.RuntimeException: boom",
+            "\tat Main.main(Main.java:1)",
+            "]]></log4j:Throwable>",
+            "</log4j:Event>",
+            r#"<log4j:Event logger="cut" level="WARN" thread="main">"#,
+        ];
+        let mut events = vec![];
+        for line in lines {
+            events.extend(parser.push(line.to_string()));
+        }
+        events.extend(parser.finish());
+
+        assert_eq!(
+            events,
+            vec![
+                GameEvent::Stdout("[STDOUT]: printed by a mod".into()),
+                GameEvent::Log(LogRecord {
+                    level: "INFO".into(),
+                    logger: "net.minecraft.client.Minecraft".into(),
+                    thread: "Render thread".into(),
+                    timestamp: Some(1650000000000),
+                    message: "Setting user: <Steve> & co".into(),
+                    throwable: None,
+                }),
+                GameEvent::Log(LogRecord {
+                    level: "ERROR".into(),
+                    logger: "fml".into(),
+                    thread: "\"main\"".into(),
+                    timestamp: Some(1),
+                    message: "a < b".into(),
+                    throwable: Some(
+                        "java.lang.RuntimeException: boom\n\tat Main.main(Main.java:1)\n".into()
+                    ),
+                }),
+                GameEvent::Stdout(lines[10].into()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_game_process_events() {
+        let script = r#"echo plain
+echo '<log4j:Event logger="a" level="INFO" thread="main"><log4j:Message>hi</log4j:Message></log4j:Event>'
+echo oops >&2
+exit 3"#;
+        let mut process =
+            GameProcess::spawn("sh", &["-c".to_string(), script.to_string()], true).unwrap();
+        let mut events = vec![];
+        while let Some(event) = process.next_event().await {
+            events.push(event);
+        }
+
+        assert_eq!(events.last(), Some(&GameEvent::Exited(Some(3))));
+        assert!(events.contains(&GameEvent::Stdout("plain".into())));
+        assert!(events.contains(&GameEvent::Stderr("oops".into())));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GameEvent::Log(record) if record.message == "hi" && record.level == "INFO"
+        )));
+        assert_eq!(process.next_event().await, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_game_process_stop() {
+        let graceful = "trap 'exit 7' TERM; echo ready; while true; do sleep 0.1; done";
+        let mut process =
+            GameProcess::spawn("sh", &["-c".to_string(), graceful.to_string()], false).unwrap();
+        assert_eq!(
+            process.next_event().await,
+            Some(GameEvent::Stdout("ready".into()))
+        );
+        assert_eq!(process.stop(Duration::from_secs(5)).await.unwrap(), Some(7));
+
+        let stubborn = "trap '' TERM; echo ready; while true; do sleep 0.1; done";
+        let mut process =
+            GameProcess::spawn("sh", &["-c".to_string(), stubborn.to_string()], false).unwrap();
+        process.next_event().await;
+        assert_eq!(process.try_wait().unwrap(), None);
+        assert_eq!(
+            process.stop(Duration::from_millis(300)).await.unwrap(),
+            None
+        );
+    }
+}
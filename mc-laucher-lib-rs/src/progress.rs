@@ -0,0 +1,339 @@
+use crate::json::{
+    client::Loader,
+    install::{Event, Reporter},
+};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const PHASE_LIBRARIES: &str = "libraries";
+pub const PHASE_ASSETS: &str = "assets";
+pub const PHASE_CLIENT: &str = "client";
+pub const PHASE_RUNTIME: &str = "runtime";
+pub const PHASE_LOADER: &str = "loader";
+pub const PHASE_MODS: &str = "mods";
+
+/// Bytes a phase is assumed to fetch until it announces the real amount
+fn get_estimated_bytes(phase: &str) -> u64 {
+    const MIB: u64 = 1024 * 1024;
+    match phase {
+        PHASE_LIBRARIES => 40 * MIB,
+        PHASE_ASSETS => 350 * MIB,
+        PHASE_CLIENT => 20 * MIB,
+        PHASE_RUNTIME => 180 * MIB,
+        PHASE_LOADER => 10 * MIB,
+        PHASE_MODS => 60 * MIB,
+        _ => 10 * MIB,
+    }
+}
+
+/// How far back transfers are looked at when computing the rate
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+struct PhaseProgress {
+    name: String,
+    expected: u64,
+    done: u64,
+    started: bool,
+}
+
+/// Combines the [`Event::Phase`] and [`Event::Bytes`] events of an install
+/// into one overall percentage weighted by the bytes each phase fetches,
+/// along with the transfer rate and an ETA. The percentage never goes back.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    phases: Vec<PhaseProgress>,
+    current: Option<usize>,
+    /// bytes seen for each file of the current phase
+    files: HashMap<String, u64>,
+    /// (time, bytes transferred so far) within the rate window
+    samples: VecDeque<(Instant, u64)>,
+    transferred: u64,
+    percent: f64,
+    status: String,
+}
+impl ProgressTracker {
+    /// Tracker for phases that are expected to run in the given order
+    pub fn new(phases: &[&str]) -> Self {
+        Self {
+            phases: phases
+                .iter()
+                .map(|name| PhaseProgress {
+                    name: name.to_string(),
+                    expected: get_estimated_bytes(name),
+                    done: 0,
+                    started: false,
+                })
+                .collect(),
+            current: None,
+            files: HashMap::new(),
+            samples: VecDeque::new(),
+            transferred: 0,
+            percent: 0.0,
+            status: String::default(),
+        }
+    }
+
+    /// Tracker for the phases `ClientBuilder::install` goes through for the loader
+    pub fn for_loader(loader: &Loader) -> Self {
+        match loader {
+            Loader::Vanilla => Self::new(&[PHASE_LIBRARIES, PHASE_ASSETS, PHASE_CLIENT, PHASE_RUNTIME]),
+            Loader::Forge => Self::new(&[
+                PHASE_LIBRARIES,
+                PHASE_ASSETS,
+                PHASE_CLIENT,
+                PHASE_RUNTIME,
+                PHASE_LOADER,
+                PHASE_MODS,
+            ]),
+            Loader::Fabric | Loader::Optifine => Self::new(&[
+                PHASE_LIBRARIES,
+                PHASE_ASSETS,
+                PHASE_CLIENT,
+                PHASE_RUNTIME,
+                PHASE_LOADER,
+            ]),
+        }
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        self.update_at(event, Instant::now());
+    }
+
+    fn update_at(&mut self, event: &Event, now: Instant) {
+        match event {
+            Event::Phase { name, total } => self.start_phase(name, *total),
+            Event::Bytes {
+                url,
+                current,
+                total: _,
+            } => {
+                let previous = self.files.insert(url.clone(), *current);
+                let phase = match self.current {
+                    Some(index) => &mut self.phases[index],
+                    None => return,
+                };
+                match previous {
+                    Some(previous) if *current >= previous => {
+                        phase.done += current - previous;
+                        self.transferred += current - previous;
+                        self.samples.push_back((now, self.transferred));
+                    }
+                    // restarted after a failed attempt
+                    Some(previous) => phase.done = phase.done.saturating_sub(previous - current),
+                    // already on disk or resumed, so it doesn't count towards the rate
+                    None => phase.done += current,
+                }
+            }
+            Event::Status(status) => self.status = status.clone(),
+            _ => {}
+        }
+
+        while let Some((time, _)) = self.samples.front() {
+            if now.duration_since(*time) > RATE_WINDOW && self.samples.len() > 2 {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let expected: u64 = self.phases.iter().map(|phase| phase.expected).sum();
+        let done: u64 = self
+            .phases
+            .iter()
+            .map(|phase| phase.done.min(phase.expected))
+            .sum();
+        if expected > 0 {
+            self.percent = self
+                .percent
+                .max(done as f64 / expected as f64 * 100.0)
+                .min(100.0);
+        }
+    }
+
+    fn start_phase(&mut self, name: &str, total: u64) {
+        let index = match self.phases.iter().position(|phase| phase.name == name) {
+            Some(value) => value,
+            None => {
+                self.phases.push(PhaseProgress {
+                    name: name.to_string(),
+                    expected: get_estimated_bytes(name),
+                    done: 0,
+                    started: false,
+                });
+                self.phases.len() - 1
+            }
+        };
+
+        // the phase that was running is over, and earlier phases that never
+        // started were skipped, ie the runtime was already installed
+        if let Some(current) = self.current {
+            self.complete_phase(current);
+        }
+        for previous in 0..index {
+            self.complete_phase(previous);
+        }
+
+        let phase = &mut self.phases[index];
+        if phase.started {
+            // a phase can run again, ie the libraries of a mod loader after the vanilla ones
+            phase.done = phase.done.min(phase.expected);
+            phase.expected = phase.done + total;
+        } else if total > 0 {
+            phase.expected = total;
+        }
+        phase.started = true;
+
+        self.current = Some(index);
+        self.files.clear();
+    }
+
+    fn complete_phase(&mut self, index: usize) {
+        let phase = &mut self.phases[index];
+        phase.started = true;
+        phase.done = phase.expected;
+    }
+
+    /// Marks the install as complete
+    pub fn finish(&mut self) {
+        for index in 0..self.phases.len() {
+            self.complete_phase(index);
+        }
+        self.percent = 100.0;
+    }
+
+    /// Overall progress between 0 and 100, never decreases
+    pub fn get_percent(&self) -> f64 {
+        self.percent
+    }
+
+    /// Bytes per second over the last few seconds
+    pub fn get_rate(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((start, start_bytes)), Some((end, end_bytes))) => {
+                let elapsed = end.duration_since(*start).as_secs_f64();
+                if elapsed <= 0.0 {
+                    return 0.0;
+                }
+                (end_bytes - start_bytes) as f64 / elapsed
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Time left at the current rate, `None` while no rate is known
+    pub fn get_eta(&self) -> Option<Duration> {
+        let rate = self.get_rate();
+        if rate <= 0.0 {
+            return None;
+        }
+        let remaining: u64 = self
+            .phases
+            .iter()
+            .map(|phase| phase.expected.saturating_sub(phase.done))
+            .sum();
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// The last status message
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+}
+impl Display for ProgressTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:5.1}%", self.percent)?;
+        let rate = self.get_rate();
+        if rate > 0.0 {
+            write!(f, " | {}/s", format_bytes(rate as u64))?;
+        }
+        if let Some(eta) = self.get_eta() {
+            let secs = eta.as_secs();
+            write!(f, " | ETA {}m {:02}s", secs / 60, secs % 60)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lets a shared tracker be handed to the install functions as a reporter
+impl Reporter for Mutex<ProgressTracker> {
+    fn report(&self, event: Event) {
+        match self.lock() {
+            Ok(mut value) => value.update(&event),
+            Err(poisoned) => poisoned.into_inner().update(&event),
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_is_weighted_and_monotonic() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new(&[PHASE_LIBRARIES, PHASE_ASSETS]);
+
+        tracker.update_at(&Event::phase(PHASE_LIBRARIES, 100), start);
+        tracker.update_at(&Event::bytes("a".into(), 0, Some(100)), start);
+        tracker.update_at(&Event::bytes("a".into(), 50, Some(100)), start);
+        // assets are still estimated, so libraries are a small share of the total
+        assert!(tracker.get_percent() > 0.0 && tracker.get_percent() < 1.0);
+
+        tracker.update_at(&Event::phase(PHASE_ASSETS, 300), start);
+        // libraries count as done once the next phase starts
+        assert_eq!(tracker.get_percent(), 25.0);
+
+        tracker.update_at(&Event::bytes("b".into(), 0, Some(300)), start);
+        tracker.update_at(&Event::bytes("b".into(), 150, Some(300)), start);
+        assert_eq!(tracker.get_percent(), 62.5);
+
+        // a failed attempt starting over does not move the progress back
+        tracker.update_at(&Event::bytes("b".into(), 0, Some(300)), start);
+        assert_eq!(tracker.get_percent(), 62.5);
+
+        tracker.finish();
+        assert_eq!(tracker.get_percent(), 100.0);
+    }
+
+    #[test]
+    fn test_rate_and_eta() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new(&[PHASE_CLIENT]);
+        tracker.update_at(&Event::phase(PHASE_CLIENT, 3000), start);
+        tracker.update_at(&Event::bytes("jar".into(), 0, Some(3000)), start);
+        tracker.update_at(&Event::bytes("jar".into(), 1000, Some(3000)), start);
+        tracker.update_at(
+            &Event::bytes("jar".into(), 2000, Some(3000)),
+            start + Duration::from_secs(1),
+        );
+
+        assert_eq!(tracker.get_rate(), 1000.0);
+        assert_eq!(tracker.get_eta(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_existing_files_do_not_count_towards_rate() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new(&[PHASE_LIBRARIES]);
+        tracker.update_at(&Event::phase(PHASE_LIBRARIES, 200), start);
+        tracker.update_at(&Event::bytes("cached".into(), 200, Some(200)), start);
+
+        assert_eq!(tracker.get_percent(), 100.0);
+        assert_eq!(tracker.get_rate(), 0.0);
+        assert_eq!(tracker.get_eta(), None);
+    }
+}
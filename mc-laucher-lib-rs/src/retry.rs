@@ -0,0 +1,342 @@
+use crate::context::get_context;
+use crate::expections::{LauncherLibError, LibResult};
+use crate::transport::HttpResponse;
+use log::warn;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often, and how long to wait, before a failed request is tried again
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+impl RetryPolicy {
+    /// Only try each url once
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Exponential backoff with jitter for the given (zero based) attempt.
+    /// A `Retry-After` from the server is waited out if it is within `max_delay`,
+    /// otherwise returns `None` and the url is given up on.
+    pub fn get_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let jittered = backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+
+        match retry_after {
+            Some(value) if value > self.max_delay => None,
+            Some(value) => Some(value.max(jittered)),
+            None => Some(jittered),
+        }
+    }
+}
+
+/// Rewrites urls starting with `prefix` to start with `replacement` instead
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    pub prefix: String,
+    pub replacement: String,
+}
+impl Mirror {
+    pub fn new(prefix: &str, replacement: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        url.strip_prefix(self.prefix.as_str())
+            .map(|rest| format!("{}{}", self.replacement, rest))
+    }
+}
+
+/// Mirror rewrites for the BMCLAPI mirror of the Mojang, Forge and Fabric servers
+/// See <https://bmclapidoc.bangbang93.com/>
+pub fn bmclapi_mirrors() -> Vec<Mirror> {
+    const ROOT: &str = "https://bmclapi2.bangbang93.com/";
+    vec![
+        Mirror::new("https://launchermeta.mojang.com/", ROOT),
+        Mirror::new("https://launcher.mojang.com/", ROOT),
+        Mirror::new("https://piston-meta.mojang.com/", ROOT),
+        Mirror::new("https://piston-data.mojang.com/", ROOT),
+        Mirror::new(
+            "https://resources.download.minecraft.net/",
+            "https://bmclapi2.bangbang93.com/assets/",
+        ),
+        Mirror::new(
+            "https://libraries.minecraft.net/",
+            "https://bmclapi2.bangbang93.com/maven/",
+        ),
+        Mirror::new(
+            "https://maven.minecraftforge.net/",
+            "https://bmclapi2.bangbang93.com/maven/",
+        ),
+        Mirror::new(
+            "https://files.minecraftforge.net/maven/",
+            "https://bmclapi2.bangbang93.com/maven/",
+        ),
+        Mirror::new(
+            "https://meta.fabricmc.net/",
+            "https://bmclapi2.bangbang93.com/fabric-meta/",
+        ),
+        Mirror::new(
+            "https://maven.fabricmc.net/",
+            "https://bmclapi2.bangbang93.com/maven/",
+        ),
+    ]
+}
+
+/// The url itself followed by every mirror rewrite of it, in order
+pub fn get_candidate_urls(url: &str, mirrors: &[Mirror]) -> Vec<String> {
+    let mut urls = vec![url.to_string()];
+    for mirror in mirrors {
+        if let Some(value) = mirror.rewrite(url) {
+            if !urls.contains(&value) {
+                urls.push(value);
+            }
+        }
+    }
+    urls
+}
+
+/// Turns a non success http status into a `HttpStatus` error
+pub fn check_status(url: &str, response: HttpResponse) -> LibResult<HttpResponse> {
+    if response.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .get_header("retry-after")
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    Err(LauncherLibError::HttpStatus {
+        url: url.to_string(),
+        status: response.status,
+        retry_after,
+    })
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 425 | 429) || status >= 500
+}
+
+/// If trying the same url again could succeed
+pub fn is_retryable(err: &LauncherLibError) -> bool {
+    match err {
+        LauncherLibError::HTTP { source, .. } | LauncherLibError::PraseJsonReqwest(source) => {
+            source.is_timeout()
+                || source.is_connect()
+                || source.is_request()
+                || source.is_body()
+                || source
+                    .status()
+                    .map(|status| is_retryable_status(status.as_u16()))
+                    .unwrap_or(false)
+        }
+        LauncherLibError::HttpStatus { status, .. } => is_retryable_status(*status),
+        LauncherLibError::HashMismatch { .. } | LauncherLibError::Timeout(_) => true,
+        _ => false,
+    }
+}
+
+/// If the error came from the remote end, in which case a mirror is worth a try
+fn is_network_error(err: &LauncherLibError) -> bool {
+    matches!(
+        err,
+        LauncherLibError::HTTP { .. }
+            | LauncherLibError::PraseJsonReqwest(_)
+            | LauncherLibError::HttpStatus { .. }
+            | LauncherLibError::HashMismatch { .. }
+            | LauncherLibError::Timeout(_)
+    )
+}
+
+/// Runs `request` against `url` using the retry policy of the current context,
+/// falling back to each of the context's mirrors in turn.
+pub async fn with_retry<T, F, Fut>(url: &str, request: F) -> LibResult<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = LibResult<T>>,
+{
+    let context = get_context();
+    with_retry_policy(url, &context.retry, &context.mirrors, request).await
+}
+
+/// Same as `with_retry` but with an explicit policy and mirror list
+pub async fn with_retry_policy<T, F, Fut>(
+    url: &str,
+    policy: &RetryPolicy,
+    mirrors: &[Mirror],
+    mut request: F,
+) -> LibResult<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = LibResult<T>>,
+{
+    let mut last_error: Option<LauncherLibError> = None;
+
+    for candidate in get_candidate_urls(url, mirrors) {
+        let mut attempt: u32 = 0;
+        loop {
+            let err = match request(candidate.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            if !is_network_error(&err) {
+                return Err(err);
+            }
+
+            attempt += 1;
+            let delay = if is_retryable(&err) && attempt < policy.max_attempts {
+                let retry_after = match &err {
+                    LauncherLibError::HttpStatus { retry_after, .. } => {
+                        retry_after.map(Duration::from_secs)
+                    }
+                    _ => None,
+                };
+                policy.get_delay(attempt - 1, retry_after)
+            } else {
+                None
+            };
+
+            warn!("Request failed ({}/{}) | {}", attempt, policy.max_attempts, err);
+            last_error = Some(err);
+
+            match delay {
+                Some(value) => tokio::time::sleep(value).await,
+                None => break,
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| LauncherLibError::General("No url to request".into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_candidate_urls() {
+        let urls = get_candidate_urls(
+            "https://libraries.minecraft.net/org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2.jar",
+            &bmclapi_mirrors(),
+        );
+        assert_eq!(
+            urls,
+            vec![
+                "https://libraries.minecraft.net/org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2.jar",
+                "https://bmclapi2.bangbang93.com/maven/org/lwjgl/lwjgl/3.2.2/lwjgl-3.2.2.jar"
+            ]
+        );
+
+        let urls = get_candidate_urls("https://example.com/file.jar", &bmclapi_mirrors());
+        assert_eq!(urls, vec!["https://example.com/file.jar"]);
+    }
+
+    #[test]
+    fn test_get_delay() {
+        let policy = RetryPolicy::default();
+        for attempt in 0..10 {
+            let delay = policy.get_delay(attempt, None).unwrap();
+            assert!(delay <= policy.max_delay);
+            assert!(delay >= policy.base_delay / 2);
+        }
+        assert_eq!(
+            policy.get_delay(0, Some(Duration::from_secs(10))),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(policy.get_delay(0, Some(Duration::from_secs(3600))), None);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let status = |status: u16| LauncherLibError::HttpStatus {
+            url: String::default(),
+            status,
+            retry_after: None,
+        };
+        assert!(is_retryable(&status(503)));
+        assert!(is_retryable(&status(429)));
+        assert!(!is_retryable(&status(404)));
+        assert!(is_retryable(&LauncherLibError::HashMismatch {
+            url: String::default(),
+            expected: "a".into(),
+            actual: "b".into()
+        }));
+        assert!(!is_retryable(&LauncherLibError::General("".into())));
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mirrors = vec![Mirror::new(
+            "https://libraries.minecraft.net/",
+            "https://mirror.example/maven/",
+        )];
+
+        let mut tried: Vec<String> = vec![];
+        let result = with_retry_policy(
+            "https://libraries.minecraft.net/a.jar",
+            &policy,
+            &mirrors,
+            |url| {
+                tried.push(url.clone());
+                let status = if url.starts_with("https://libraries.minecraft.net/") {
+                    503
+                } else if tried.len() < 5 {
+                    500
+                } else {
+                    200
+                };
+                async move {
+                    if status == 200 {
+                        return Ok(url);
+                    }
+                    Err(LauncherLibError::HttpStatus {
+                        url,
+                        status,
+                        retry_after: None,
+                    })
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "https://mirror.example/maven/a.jar");
+        assert_eq!(tried.len(), 5);
+
+        // errors not caused by the remote end are returned right away
+        let mut count = 0;
+        let result: LibResult<()> = with_retry_policy("https://a.example/", &policy, &mirrors, |_| {
+            count += 1;
+            async { Err(LauncherLibError::General("disk full".into())) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,293 @@
+use crate::json::{
+    game_settings::GameOptions,
+    install::{Argument, Rule},
+};
+use crate::utils::{get_os_version, is_arch};
+use log::error;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::env::consts;
+use std::sync::{Mutex, OnceLock};
+
+pub const FEATURE_DEMO_USER: &str = "is_demo_user";
+pub const FEATURE_CUSTOM_RESOLUTION: &str = "has_custom_resolution";
+pub const FEATURE_QUICK_PLAYS_SUPPORT: &str = "has_quick_plays_support";
+pub const FEATURE_QUICK_PLAY_SINGLEPLAYER: &str = "is_quick_play_singleplayer";
+pub const FEATURE_QUICK_PLAY_MULTIPLAYER: &str = "is_quick_play_multiplayer";
+pub const FEATURE_QUICK_PLAY_REALMS: &str = "is_quick_play_realms";
+
+/// What the rules of a version json are evaluated against
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleEnvironment {
+    /// os name as the rules spell it: `windows`, `osx` or `linux`
+    pub os: String,
+    /// rust target arch, ie `x86_64` or `aarch64`
+    pub arch: String,
+    /// matched against the `os.version` regex
+    pub os_version: String,
+    /// features that are enabled, any other feature is off
+    pub features: HashSet<String>,
+}
+impl RuleEnvironment {
+    /// The machine this runs on, with no features enabled
+    pub fn current() -> Self {
+        static OS_VERSION: OnceLock<String> = OnceLock::new();
+        Self {
+            os: match consts::OS {
+                "macos" => "osx".into(),
+                os => os.into(),
+            },
+            arch: consts::ARCH.into(),
+            os_version: OS_VERSION.get_or_init(get_os_version).clone(),
+            features: HashSet::new(),
+        }
+    }
+
+    /// The machine this runs on, with the features the launch options ask for
+    pub fn from_options(options: &GameOptions) -> Self {
+        let mut environment = Self::current();
+        if options.demo {
+            environment.features.insert(FEATURE_DEMO_USER.into());
+        }
+        if options.custom_resolution.is_some() {
+            environment
+                .features
+                .insert(FEATURE_CUSTOM_RESOLUTION.into());
+        }
+        if options.quick_play_path.is_some() {
+            environment
+                .features
+                .insert(FEATURE_QUICK_PLAYS_SUPPORT.into());
+        }
+        if options.server.is_some() {
+            environment
+                .features
+                .insert(FEATURE_QUICK_PLAY_MULTIPLAYER.into());
+        }
+        if options.quick_play_singleplayer.is_some() {
+            environment
+                .features
+                .insert(FEATURE_QUICK_PLAY_SINGLEPLAYER.into());
+        }
+        if options.quick_play_realms.is_some() {
+            environment
+                .features
+                .insert(FEATURE_QUICK_PLAY_REALMS.into());
+        }
+        environment
+    }
+
+    pub fn with_feature(mut self, feature: &str) -> Self {
+        self.features.insert(feature.into());
+        self
+    }
+}
+
+/// Whether the arguments have a rule on the feature, ie the version knows about it
+pub fn has_feature_rule(args: &[Argument], feature: &str) -> bool {
+    args.iter().any(|arg| match arg {
+        Argument::RuleMulti { rules, .. } | Argument::RuleSingle { rules, .. } => rules
+            .iter()
+            .filter_map(|rule| rule.features.as_ref())
+            .any(|features| features.contains_key(feature)),
+        Argument::Arg(_) => false,
+    })
+}
+
+/// Compiled `os.version` patterns. `None` for patterns that don't compile, which never match.
+fn is_version_match(pattern: &str, version: &str) -> bool {
+    static CACHE: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+    let mut cache = CACHE
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .expect("Rule regex cache poisoned");
+    let regex = cache
+        .entry(pattern.to_string())
+        .or_insert_with(|| match Regex::new(pattern) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                error!("Invalid os version rule {}: {}", pattern, err);
+                None
+            }
+        });
+    match regex {
+        Some(regex) => regex.is_match(version),
+        None => false,
+    }
+}
+
+/// Whether every condition of the rule holds in the environment. A condition on
+/// an unknown `os` key never holds, so rules written for newer launchers don't apply.
+pub fn is_rule_match(rule: &Rule, environment: &RuleEnvironment) -> bool {
+    if let Some(os) = &rule.os {
+        for (key, value) in os {
+            let matches = match key.as_str() {
+                "name" => *value == environment.os,
+                "arch" => is_arch(value, &environment.arch),
+                "version" => is_version_match(value, &environment.os_version),
+                _ => false,
+            };
+            if !matches {
+                return false;
+            }
+        }
+    }
+    if let Some(features) = &rule.features {
+        for (feature, wanted) in features {
+            if environment.features.contains(feature) != *wanted {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Evaluates rules the way the launcher does: nothing is allowed until a rule says so,
+/// and the last rule that matches decides. An empty list allows.
+pub fn is_allowed(rules: &[Rule], environment: &RuleEnvironment) -> bool {
+    if rules.is_empty() {
+        return true;
+    }
+    let mut allowed = false;
+    for rule in rules {
+        if is_rule_match(rule, environment) {
+            allowed = rule.action == "allow";
+        }
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::install::VersionManifest;
+
+    fn read_fixture(name: &str) -> VersionManifest {
+        let raw = std::fs::read_to_string(format!("tests/fixtures/rules/{}", name)).unwrap();
+        serde_json::from_str(&raw).unwrap()
+    }
+
+    fn environment(os: &str, arch: &str, os_version: &str) -> RuleEnvironment {
+        RuleEnvironment {
+            os: os.into(),
+            arch: arch.into(),
+            os_version: os_version.into(),
+            features: HashSet::new(),
+        }
+    }
+
+    fn get_libraries(manifest: &VersionManifest, environment: &RuleEnvironment) -> Vec<String> {
+        manifest
+            .libraries
+            .iter()
+            .filter(|library| match &library.rules {
+                Some(rules) => is_allowed(rules, environment),
+                None => true,
+            })
+            .map(|library| library.name.clone())
+            .collect()
+    }
+
+    fn get_arguments(args: &[Argument], environment: &RuleEnvironment) -> Vec<String> {
+        let mut list = vec![];
+        for arg in args {
+            match arg {
+                Argument::Arg(value) => list.push(value.clone()),
+                Argument::RuleMulti { rules, value } if is_allowed(rules, environment) => {
+                    list.extend(value.iter().cloned())
+                }
+                Argument::RuleSingle { rules, value } if is_allowed(rules, environment) => {
+                    list.push(value.clone())
+                }
+                _ => {}
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn test_last_matching_rule_wins() {
+        // lwjgl 2.9.4 is allowed everywhere but mac, 2.9.2 only on mac
+        let manifest = read_fixture("1.12.2.json");
+        let mac = get_libraries(&manifest, &environment("osx", "x86_64", "12.6"));
+        let linux = get_libraries(&manifest, &environment("linux", "x86_64", "5.15"));
+        assert!(mac.contains(&"org.lwjgl.lwjgl:lwjgl:2.9.2-nightly-20140822".to_string()));
+        assert!(!mac.contains(&"org.lwjgl.lwjgl:lwjgl:2.9.4-nightly-20150209".to_string()));
+        assert!(linux.contains(&"org.lwjgl.lwjgl:lwjgl:2.9.4-nightly-20150209".to_string()));
+        assert!(!linux.contains(&"org.lwjgl.lwjgl:lwjgl:2.9.2-nightly-20140822".to_string()));
+        assert!(linux.contains(&"com.mojang:patchy:1.3.9".to_string()));
+    }
+
+    #[test]
+    fn test_os_and_arch_rules() {
+        let manifest = read_fixture("1.20.1.json");
+        let jvm = &manifest.arguments.jvm;
+
+        let windows = get_arguments(jvm, &environment("windows", "x86", "10.0"));
+        assert!(windows.contains(&"-Dos.name=Windows 10".to_string()));
+        assert!(windows.contains(&"-Xss1M".to_string()));
+        assert!(!windows.contains(&"-XstartOnFirstThread".to_string()));
+
+        let windows_7 = get_arguments(jvm, &environment("windows", "x86_64", "6.1"));
+        assert!(!windows_7.contains(&"-Dos.name=Windows 10".to_string()));
+        assert!(!windows_7.contains(&"-Xss1M".to_string()));
+
+        let mac = get_arguments(jvm, &environment("osx", "aarch64", "13.4"));
+        assert!(mac.contains(&"-XstartOnFirstThread".to_string()));
+
+        let libraries = get_libraries(&manifest, &environment("osx", "aarch64", "13.4"));
+        assert!(libraries.contains(&"org.lwjgl:lwjgl:3.3.1:natives-macos-arm64".to_string()));
+        assert!(!libraries.contains(&"org.lwjgl:lwjgl:3.3.1:natives-linux".to_string()));
+    }
+
+    #[test]
+    fn test_feature_rules() {
+        let manifest = read_fixture("1.20.1.json");
+        let game = &manifest.arguments.game;
+        let base = environment("linux", "x86_64", "5.15");
+
+        let plain = get_arguments(game, &base);
+        assert!(!plain
+            .iter()
+            .any(|arg| arg.starts_with("--quickPlay") || arg == "--demo"));
+
+        let multiplayer = get_arguments(
+            game,
+            &base
+                .clone()
+                .with_feature(FEATURE_QUICK_PLAYS_SUPPORT)
+                .with_feature(FEATURE_QUICK_PLAY_MULTIPLAYER),
+        );
+        assert!(multiplayer.contains(&"--quickPlayPath".to_string()));
+        assert!(multiplayer.contains(&"--quickPlayMultiplayer".to_string()));
+        assert!(!multiplayer.contains(&"--quickPlaySingleplayer".to_string()));
+
+        let demo = get_arguments(game, &base.clone().with_feature(FEATURE_DEMO_USER));
+        assert!(demo.contains(&"--demo".to_string()));
+
+        // a feature the launcher doesn't know is off
+        let unknown = Rule {
+            action: "allow".into(),
+            os: None,
+            features: Some(HashMap::from([("is_future_feature".to_string(), true)])),
+        };
+        assert!(!is_allowed(&[unknown], &base));
+        assert!(is_allowed(&[], &base));
+    }
+
+    #[test]
+    fn test_invalid_version_pattern() {
+        let rule = Rule {
+            action: "allow".into(),
+            os: Some(HashMap::from([(
+                "version".to_string(),
+                "^10\\.(".to_string(),
+            )])),
+            features: None,
+        };
+        assert!(!is_allowed(
+            &[rule],
+            &environment("windows", "x86_64", "10.0")
+        ));
+    }
+}
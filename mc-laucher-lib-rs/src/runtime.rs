@@ -1,3 +1,4 @@
+use crate::cancel::CancellationToken;
 
 use crate::json::{
     runtime::{
@@ -6,59 +7,41 @@ use crate::json::{
         RuntimeData,
         JvmManifest
     },
-    install::{Callback,Event}
+    install::{Callback,Event,DownloadableFile}
 };
 use crate::expections::{ LibResult, LauncherLibError};
-use crate::utils::{ get_http_client, download_file };
+use crate::context::get_context;
+use crate::journal::open_journal;
+use crate::progress::PHASE_RUNTIME;
+use crate::utils::{ get_json, download_file };
 use tokio::fs::{ write, create_dir_all };
 use std::env::{ consts };
 use std::path::PathBuf;
 
-const JVM_MANIFEST_URL: &str = "https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json";
-
 
 /// Get the name that is used to identify the platform
-fn get_jvm_platform_string() -> LibResult<String> {
-    match consts::OS {
-        "windows" => {
-            if consts::ARCH == "x86" {
-                return Ok("windows-x86".into());
-            }
+pub(crate) fn get_jvm_platform_string() -> LibResult<String> {
+    get_jvm_platform(consts::OS, consts::ARCH)
+}
 
-            Ok("windows-x64".into())
-        }
-        "macos" => Ok("mac-os".into()),
-        "linux" => {
-            if consts::ARCH == "x86" {
-                return Ok("linux-i386".into());
-            }
-            Ok("linux".into())
-        }
-        _ => Err(LauncherLibError::Unsupported(format!("Platform ({}) is unspported",consts::OS)))
+/// The name Mojang's runtime manifest uses for the os and arch
+fn get_jvm_platform(os: &str, arch: &str) -> LibResult<String> {
+    match (os, arch) {
+        ("windows", "x86") => Ok("windows-x86".into()),
+        ("windows", "aarch64") => Ok("windows-arm64".into()),
+        ("windows", _) => Ok("windows-x64".into()),
+        ("macos", "aarch64") => Ok("mac-os-arm64".into()),
+        ("macos", _) => Ok("mac-os".into()),
+        ("linux", "x86") => Ok("linux-i386".into()),
+        ("linux", "aarch64") => Ok("linux-aarch64".into()),
+        ("linux", _) => Ok("linux".into()),
+        _ => Err(LauncherLibError::Unsupported(format!("Platform ({}) is unspported",os)))
     }
 }
 
 /// returns a list of all mc jvm runtimes
 async fn get_jvm_runtimes() -> LibResult<JvmManifest> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get(JVM_MANIFEST_URL).send().await {
-        Ok(res) => {
-            match res.json::<JvmManifest>().await {
-                Err(error) => Err(LauncherLibError::PraseJsonReqwest(error)),
-                Ok(value) => Ok(value)
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            msg: "Failed to make http request".into(),
-            source: err
-        })
-        
-      
-    }
+    get_json::<JvmManifest>(&get_context().endpoints.jvm_manifest).await
 }
 
 fn get_manifest(arch: String, runtime: MinecraftJavaRuntime, runtimes: JvmManifest) -> LibResult<RuntimeData> {
@@ -78,7 +61,7 @@ fn get_manifest(arch: String, runtime: MinecraftJavaRuntime, runtimes: JvmManife
     }
 }
 
-pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_dir: PathBuf, callback: Callback) -> LibResult<()> {
+pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_dir: PathBuf, callback: Callback<'_>, cancel: &CancellationToken) -> LibResult<()> {
     let runtimes = match get_jvm_runtimes().await {
         Err(err) => return Err(err),
         Ok(value) => value
@@ -93,30 +76,32 @@ pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_di
         Err(err) => return Err(err)
     };
 
-    let client = match get_http_client().await {
-        Ok(value) => value,
+    let src_download = match get_json::<JVMFiles>(manifest.manifest.url.as_str()).await {
+        Ok(json) => json,
         Err(err) => return Err(err)
     };
 
-    let src_download = match client.get(manifest.manifest.url.as_str()).send().await {
-        Ok(value) => {
-            match value.json::<JVMFiles>().await {
-                Ok(json) => json,
-                Err(err) => return Err(LauncherLibError::PraseJsonReqwest(err))
-            }
-        },
-        Err(error) => return Err(LauncherLibError::HTTP {
-            source: error,
-            msg: "Failed to make http call".into()
-        })
-        
-    };
-
     let root = minecraft_dir.join("runtime").join(jvm_version.to_string()).join(arch.clone()).join(jvm_version.to_string());
 
+    // the java executable can exist before the rest of the runtime, so remember the install started
+    let journal = open_journal(&minecraft_dir);
+    let step = get_runtime_step(&jvm_version);
+    journal.start_step(&step)?;
+
     let file_count = src_download.files.len();
     let mut count = 0;
 
+    let mut size = 0;
+    for value in src_download.files.values() {
+        if let Some(download) = &value.downloads {
+            size += match &download.lzma {
+                Some(lzma) => lzma.size as u64,
+                None => download.raw.size as u64
+            };
+        }
+    }
+    callback.report(Event::phase(PHASE_RUNTIME, size));
+
     for (key, value) in &src_download.files {
         let cur = root.join(key.clone());
         match value.action.as_str() {
@@ -124,17 +109,13 @@ pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_di
                 if let Some(download) = &value.downloads {
                     if let Some(lzma) = download.lzma.clone() {
                         // if let Err(error) = download_file(lzma.url, cur.clone(), callback, Some(lzma.sha1), true).await {
-                        if let Err(error) = download_file(lzma.url, cur.clone(), callback, None, true).await {
-                             return Err(error);
-                        }
+                        download_file(lzma.url, cur.clone(), callback, cancel, None, true).await?;
                     } else {
                         // if let Err(error) = download_file(download.raw.url.clone(), cur.clone(), callback, Some(download.raw.sha1.clone()), false).await {
-                        if let Err(error) = download_file(download.raw.url.clone(), cur.clone(), callback, None, false).await {
-                            return Err(error);
-                        }
+                        download_file(download.raw.url.clone(), cur.clone(), callback, cancel, None, false).await?;
                     }
                     count += 1;
-                    callback(Event::progress(count,file_count));
+                    callback.report(Event::progress(count,file_count));
                 }
             }
             "directory" => {
@@ -147,7 +128,7 @@ pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_di
                     }
                 }
                 count += 1;
-                callback(Event::progress(count,file_count));
+                callback.report(Event::progress(count,file_count));
             }
             _ => {}
         }
@@ -160,13 +141,9 @@ pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_di
                 println!("Redownloading \033[48;5;57m {}",key);
                 if let Some(download) = &value.downloads {
                     if let Some(lzma) = download.lzma.clone() {
-                        if let Err(error) = download_file(lzma.url, cur.clone(), callback, Some(lzma.sha1), true).await {
-                             return Err(error);
-                        }
+                        download_file(lzma.url, cur.clone(), callback, cancel, Some(lzma.sha1), true).await?;
                     } else {
-                        if let Err(error) = download_file(download.raw.url.clone(), cur.clone(), callback, Some(download.raw.sha1.clone()), false).await {
-                            return Err(error);
-                        }
+                        download_file(download.raw.url.clone(), cur.clone(), callback, cancel, Some(download.raw.sha1.clone()), false).await?;
                     }
                 }
             }
@@ -183,7 +160,47 @@ pub async fn install_jvm_runtime(jvm_version: MinecraftJavaRuntime, minecraft_di
         });
     }
 
-    Ok(())
+    journal.complete_step(&step)
+}
+
+/// Name of the install journal step for the runtime
+fn get_runtime_step(jvm_version: &MinecraftJavaRuntime) -> String {
+    format!("runtime:{}",jvm_version.to_string())
+}
+
+/// The files a runtime is made of, with the uncompressed download each should match
+pub(crate) async fn get_runtime_files(jvm_version: MinecraftJavaRuntime, minecraft_dir: PathBuf) -> LibResult<Vec<(PathBuf,DownloadableFile)>> {
+    let runtimes = match get_jvm_runtimes().await {
+        Err(err) => return Err(err),
+        Ok(value) => value
+    };
+    let arch = match get_jvm_platform_string() {
+        Err(err) => return Err(err),
+        Ok(value) => value
+    };
+
+    let manifest: RuntimeData = match get_manifest(arch.clone(), jvm_version.clone(), runtimes) {
+        Ok(value) => value,
+        Err(err) => return Err(err)
+    };
+
+    let src_download = match get_json::<JVMFiles>(manifest.manifest.url.as_str()).await {
+        Ok(json) => json,
+        Err(err) => return Err(err)
+    };
+
+    let root = minecraft_dir.join("runtime").join(jvm_version.to_string()).join(arch).join(jvm_version.to_string());
+
+    let mut files = vec![];
+    for (key, value) in src_download.files {
+        if value.action != "file" {
+            continue;
+        }
+        if let Some(download) = value.downloads {
+            files.push((root.join(key),download.raw));
+        }
+    }
+    Ok(files)
 }
 
 /// Returns the path to the java executable. None if it does not exists
@@ -226,6 +243,11 @@ pub fn does_runtime_exist(jvm_version: MinecraftJavaRuntime, minecraft_dir: Path
         Ok(value) => value,
         Err(err) => return Err(err)
     };
+    // a runtime left behind by an interrupted install is not complete
+    if open_journal(&minecraft_dir).is_step_interrupted(&get_runtime_step(&jvm_version)) {
+        return Ok(false);
+    }
+
     let java_path = minecraft_dir.join("runtime").join(version.clone()).join(platform).join(version).join("bin").join("java");
 
     if java_path.is_file() {
@@ -244,15 +266,34 @@ pub fn does_runtime_exist(jvm_version: MinecraftJavaRuntime, minecraft_dir: Path
 #[cfg(test)]
 mod tests {
     use super::*;
-    fn test_callback(event: Event) {
-        println!("{:#?}",event);
+    use crate::context::{fixture_context, with_context};
+
+    fn get_temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("mc-runtime-{}", uuid::Uuid::new_v4()))
     }
+
     #[tokio::test]
     async fn test_install_jvm_runtime() {
-        if let Err(err) = install_jvm_runtime(MinecraftJavaRuntime::JavaRuntimeBeta, PathBuf::from("C:\\Users\\Collin\\AppData\\Roaming\\.minecraft"), test_callback).await {
-            eprintln!("{}",err);
-        }
-        
+        let mc_dir = get_temp_dir();
+        with_context(fixture_context("runtime"), async {
+            install_jvm_runtime(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir.clone(), &|_: Event| {}, &CancellationToken::new()).await.unwrap();
+
+            let platform = get_jvm_platform_string().unwrap();
+            let runtime = mc_dir.join("runtime").join("java-runtime-alpha").join(&platform);
+            assert_eq!(std::fs::read_to_string(runtime.join(".version")).unwrap(),"17.0.3");
+            // bin/java comes lzma compressed
+            let java = std::fs::read_to_string(runtime.join("java-runtime-alpha").join("bin").join("java")).unwrap();
+            assert!(java.contains("openjdk version"));
+
+            assert!(does_runtime_exist(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir.clone()).unwrap());
+            assert_eq!(
+                get_exectable_path(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir.clone()).unwrap(),
+                Some(runtime.join("java-runtime-alpha").join("bin").join("java"))
+            );
+            let files = get_runtime_files(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir.clone()).await.unwrap();
+            assert_eq!(files.len(),2);
+        }).await;
+        let _ = std::fs::remove_dir_all(mc_dir);
     }
     #[test]
     fn test_get_jvm_platform_string() {
@@ -261,36 +302,32 @@ mod tests {
             Err(err) => eprintln!("{}",err)
         }
     }
-    #[tokio::test]
-    async fn test_get_jvm_runtimes() {
-        match get_jvm_runtimes().await {
-            Ok(value) => println!("{:#?}",value),
-            Err(err) => eprintln!("{}",err)
-        }
-    }
     #[test]
-    fn test_get_exectable_path() {
-        match get_exectable_path(MinecraftJavaRuntime::JavaRuntimeAlpha,PathBuf::from("C:\\Users\\Collin\\AppData\\Roaming\\.minecraft")) {
-            Ok(value) => println!("{:#?}",value),
-            Err(err) => eprintln!("{}",err)
-        }
+    fn test_get_jvm_platform() {
+        assert_eq!(get_jvm_platform("linux","aarch64").unwrap(),"linux-aarch64");
+        assert_eq!(get_jvm_platform("macos","aarch64").unwrap(),"mac-os-arm64");
+        assert_eq!(get_jvm_platform("macos","x86_64").unwrap(),"mac-os");
+        assert_eq!(get_jvm_platform("windows","aarch64").unwrap(),"windows-arm64");
+        assert_eq!(get_jvm_platform("linux","x86").unwrap(),"linux-i386");
+        assert!(get_jvm_platform("freebsd","x86_64").is_err());
     }
-
     #[test]
-    fn test_does_runtime_exist() {
-        match does_runtime_exist(MinecraftJavaRuntime::JavaRuntimeAlpha,PathBuf::from("C:\\Users\\Collin\\AppData\\Roaming\\.minecraft")) {
-            Ok(value) => assert_eq!(value,true),
-            Err(err) => eprintln!("{}",err)
-        }
+    fn test_missing_runtime() {
+        let mc_dir = get_temp_dir();
+        assert_eq!(get_exectable_path(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir.clone()).unwrap(), None);
+        assert!(!does_runtime_exist(MinecraftJavaRuntime::JavaRuntimeAlpha, mc_dir).unwrap());
     }
 
     #[tokio::test]
     async fn test_get_manifest() {
-        if let Ok(value) = get_jvm_runtimes().await {
-            match get_manifest("windows-x64".into(), MinecraftJavaRuntime::JavaRuntimeAlpha, value) {
-                Ok(value) => println!("{:#?}",value),
-                Err(err) => eprintln!("{}",err)
-            }
-        }
+        with_context(fixture_context("runtime"), async {
+            let runtimes = get_jvm_runtimes().await.unwrap();
+            let manifest = get_manifest("windows-x64".into(), MinecraftJavaRuntime::JavaRuntimeAlpha, runtimes.clone()).unwrap();
+            assert_eq!(manifest.version.name,"17.0.3");
+            // listed without any build
+            assert!(get_manifest("windows-x64".into(), MinecraftJavaRuntime::JreLegacy, runtimes.clone()).is_err());
+            assert!(get_manifest("windows-x64".into(), MinecraftJavaRuntime::JavaRuntimeBeta, runtimes.clone()).is_err());
+            assert!(get_manifest("solaris".into(), MinecraftJavaRuntime::JavaRuntimeAlpha, runtimes).is_err());
+        }).await;
     }
-}
\ No newline at end of file
+}
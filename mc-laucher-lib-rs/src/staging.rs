@@ -0,0 +1,347 @@
+use crate::expections::{LauncherLibError, LibResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MODS_DIR: &str = "mods";
+pub const CONFIG_DIR: &str = "config";
+pub const SHADERPACKS_DIR: &str = "shaderpacks";
+
+const STAGING_DIR: &str = ".update-staging";
+const BACKUP_DIR: &str = ".update-backup";
+const JOURNAL_FILE: &str = ".update-journal.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum JournalState {
+    /// files are being prepared in the staging directory, the live directories are untouched
+    Staging,
+    /// live directories are being moved to the backup and replaced by the staged ones
+    Swapping,
+    /// every staged directory is live, only the backup is left to remove
+    Committed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalTarget {
+    name: String,
+    /// whether the live directory existed before the update
+    existed: bool,
+}
+
+/// Record of an update in progress, kept in the minecraft directory so
+/// an update interrupted by a crash can be finished or undone on the next run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Journal {
+    state: JournalState,
+    targets: Vec<JournalTarget>,
+}
+
+fn os_error(msg: String, source: std::io::Error) -> LauncherLibError {
+    LauncherLibError::OS { msg, source }
+}
+
+fn write_journal(mc_dir: &Path, journal: &Journal) -> LibResult<()> {
+    let raw = match serde_json::to_string_pretty(journal) {
+        Ok(value) => value,
+        Err(err) => return Err(LauncherLibError::ParseJsonSerde(err)),
+    };
+
+    // write then rename so a crash never leaves a half written journal
+    let path = mc_dir.join(JOURNAL_FILE);
+    let temp = mc_dir.join(format!("{}.tmp", JOURNAL_FILE));
+    if let Err(err) = fs::write(&temp, raw) {
+        return Err(os_error("Failed to write update journal".into(), err));
+    }
+    if let Err(err) = fs::rename(&temp, &path) {
+        return Err(os_error("Failed to write update journal".into(), err));
+    }
+    Ok(())
+}
+
+fn read_journal(mc_dir: &Path) -> LibResult<Option<Journal>> {
+    let path = mc_dir.join(JOURNAL_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match fs::read_to_string(&path) {
+        Ok(raw) => match serde_json::from_str::<Journal>(&raw) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
+        },
+        Err(err) => Err(os_error("Failed to read update journal".into(), err)),
+    }
+}
+
+fn remove_dir_if_exists(path: &Path) -> LibResult<()> {
+    if path.exists() {
+        if let Err(err) = fs::remove_dir_all(path) {
+            return Err(os_error(format!("Failed to remove {:?}", path), err));
+        }
+    }
+    Ok(())
+}
+
+/// Removes everything an update leaves behind once it is done with
+fn clean_up(mc_dir: &Path) -> LibResult<()> {
+    remove_dir_if_exists(&mc_dir.join(STAGING_DIR))?;
+    remove_dir_if_exists(&mc_dir.join(BACKUP_DIR))?;
+    let journal = mc_dir.join(JOURNAL_FILE);
+    if journal.is_file() {
+        if let Err(err) = fs::remove_file(&journal) {
+            return Err(os_error("Failed to remove update journal".into(), err));
+        }
+    }
+    Ok(())
+}
+
+/// Mirrors `source` into `target`, hard linking files where possible so
+/// staging a large mods folder does not copy every jar
+fn link_dir(source: &Path, target: &Path) -> LibResult<()> {
+    if let Err(err) = fs::create_dir_all(target) {
+        return Err(os_error(format!("Failed to create {:?}", target), err));
+    }
+    let entries = match fs::read_dir(source) {
+        Ok(value) => value,
+        Err(err) => return Err(os_error(format!("Failed to read {:?}", source), err)),
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(value) => value,
+            Err(err) => return Err(os_error(format!("Failed to read {:?}", source), err)),
+        };
+        let from = entry.path();
+        let to = target.join(entry.file_name());
+        if from.is_dir() {
+            link_dir(&from, &to)?;
+        } else if fs::hard_link(&from, &to).is_err() {
+            if let Err(err) = fs::copy(&from, &to) {
+                return Err(os_error(format!("Failed to stage {:?}", from), err));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Puts back the live directories of an interrupted swap
+fn roll_back(mc_dir: &Path, targets: &[JournalTarget]) -> LibResult<()> {
+    for target in targets.iter().rev() {
+        let live = mc_dir.join(&target.name);
+        let backup = mc_dir.join(BACKUP_DIR).join(&target.name);
+        let staged = mc_dir.join(STAGING_DIR).join(&target.name);
+
+        if backup.exists() {
+            remove_dir_if_exists(&live)?;
+            if let Err(err) = fs::rename(&backup, &live) {
+                return Err(os_error(format!("Failed to restore {:?}", live), err));
+            }
+        } else if !target.existed && !staged.exists() {
+            // the directory is new and was already moved into place
+            remove_dir_if_exists(&live)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finishes or undoes an update that was interrupted, ie by a crash.
+/// Returns whether there was anything to recover.
+pub fn recover_update(mc_dir: &Path) -> LibResult<bool> {
+    let journal = match read_journal(mc_dir) {
+        Ok(Some(value)) => value,
+        Ok(None) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    if journal.state == JournalState::Swapping {
+        roll_back(mc_dir, &journal.targets)?;
+    }
+
+    match clean_up(mc_dir) {
+        Ok(_) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+/// Update of some of the directories in the minecraft directory that is
+/// prepared next to them and swapped in once everything is in place.
+/// Staged files may be hard links to the live ones, so replace them instead of writing into them.
+#[derive(Debug)]
+pub struct StagedUpdate {
+    mc_dir: PathBuf,
+    targets: Vec<JournalTarget>,
+}
+impl StagedUpdate {
+    /// Starts an update of the given directories, staging their current contents
+    pub fn begin(mc_dir: &Path, targets: &[&str]) -> LibResult<Self> {
+        recover_update(mc_dir)?;
+
+        let journal = Journal {
+            state: JournalState::Staging,
+            targets: targets
+                .iter()
+                .map(|name| JournalTarget {
+                    name: name.to_string(),
+                    existed: mc_dir.join(name).is_dir(),
+                })
+                .collect(),
+        };
+        write_journal(mc_dir, &journal)?;
+
+        let update = Self {
+            mc_dir: mc_dir.to_path_buf(),
+            targets: journal.targets,
+        };
+        for target in &update.targets {
+            let staged = update.get_staged_dir(&target.name);
+            let result = if target.existed {
+                link_dir(&mc_dir.join(&target.name), &staged)
+            } else {
+                match fs::create_dir_all(&staged) {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(os_error(format!("Failed to create {:?}", staged), err)),
+                }
+            };
+            if let Err(err) = result {
+                let _ = clean_up(mc_dir);
+                return Err(err);
+            }
+        }
+
+        Ok(update)
+    }
+
+    /// Directory holding the new contents of `name`
+    pub fn get_staged_dir(&self, name: &str) -> PathBuf {
+        self.mc_dir.join(STAGING_DIR).join(name)
+    }
+
+    /// Swaps the staged directories in. If any step fails the previous
+    /// directories are put back before the error is returned.
+    pub fn commit(self) -> LibResult<()> {
+        let mut journal = Journal {
+            state: JournalState::Swapping,
+            targets: self.targets.clone(),
+        };
+        write_journal(&self.mc_dir, &journal)?;
+
+        if let Err(err) = self.swap() {
+            // a failed roll back leaves the journal so the next run can try again
+            roll_back(&self.mc_dir, &self.targets)?;
+            let _ = clean_up(&self.mc_dir);
+            return Err(err);
+        }
+
+        journal.state = JournalState::Committed;
+        write_journal(&self.mc_dir, &journal)?;
+        clean_up(&self.mc_dir)
+    }
+
+    fn swap(&self) -> LibResult<()> {
+        let backup = self.mc_dir.join(BACKUP_DIR);
+        if let Err(err) = fs::create_dir_all(&backup) {
+            return Err(os_error("Failed to create update backup".into(), err));
+        }
+
+        for target in &self.targets {
+            let live = self.mc_dir.join(&target.name);
+            if live.exists() {
+                if let Err(err) = fs::rename(&live, backup.join(&target.name)) {
+                    return Err(os_error(format!("Failed to back up {:?}", live), err));
+                }
+            }
+            if let Err(err) = fs::rename(self.get_staged_dir(&target.name), &live) {
+                return Err(os_error(format!("Failed to move {:?} into place", live), err));
+            }
+        }
+        Ok(())
+    }
+
+    /// Throws the staged contents away, leaving the live directories as they were
+    pub fn abort(self) -> LibResult<()> {
+        clean_up(&self.mc_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> PathBuf {
+        let mc_dir = std::env::temp_dir().join(format!("mc-staging-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(mc_dir.join(MODS_DIR)).unwrap();
+        fs::write(mc_dir.join(MODS_DIR).join("old.jar"), "old").unwrap();
+        mc_dir
+    }
+
+    #[test]
+    fn test_commit_swaps_staged_dirs() {
+        let mc_dir = setup();
+
+        let update = StagedUpdate::begin(&mc_dir, &[MODS_DIR, SHADERPACKS_DIR]).unwrap();
+        let staged = update.get_staged_dir(MODS_DIR);
+        assert!(staged.join("old.jar").is_file());
+        fs::remove_file(staged.join("old.jar")).unwrap();
+        fs::write(staged.join("new.jar"), "new").unwrap();
+        fs::write(update.get_staged_dir(SHADERPACKS_DIR).join("pack.zip"), "pack").unwrap();
+
+        // nothing changes until the commit
+        assert!(mc_dir.join(MODS_DIR).join("old.jar").is_file());
+        update.commit().unwrap();
+
+        assert!(!mc_dir.join(MODS_DIR).join("old.jar").exists());
+        assert!(mc_dir.join(MODS_DIR).join("new.jar").is_file());
+        assert!(mc_dir.join(SHADERPACKS_DIR).join("pack.zip").is_file());
+        assert!(!mc_dir.join(STAGING_DIR).exists());
+        assert!(!mc_dir.join(BACKUP_DIR).exists());
+        assert!(!mc_dir.join(JOURNAL_FILE).exists());
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_commit_rolls_back() {
+        let mc_dir = setup();
+
+        let update = StagedUpdate::begin(&mc_dir, &[MODS_DIR, SHADERPACKS_DIR]).unwrap();
+        fs::write(update.get_staged_dir(MODS_DIR).join("new.jar"), "new").unwrap();
+        // make the second rename fail after the first directory was swapped
+        fs::remove_dir_all(update.get_staged_dir(SHADERPACKS_DIR)).unwrap();
+
+        assert!(update.commit().is_err());
+        assert!(mc_dir.join(MODS_DIR).join("old.jar").is_file());
+        assert!(!mc_dir.join(MODS_DIR).join("new.jar").exists());
+        assert!(!mc_dir.join(SHADERPACKS_DIR).exists());
+        assert!(!mc_dir.join(JOURNAL_FILE).exists());
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_interrupted_swap() {
+        let mc_dir = setup();
+
+        let update = StagedUpdate::begin(&mc_dir, &[MODS_DIR, SHADERPACKS_DIR]).unwrap();
+        fs::write(update.get_staged_dir(MODS_DIR).join("new.jar"), "new").unwrap();
+
+        // simulate a crash right after the mods were swapped
+        write_journal(
+            &mc_dir,
+            &Journal {
+                state: JournalState::Swapping,
+                targets: update.targets.clone(),
+            },
+        )
+        .unwrap();
+        fs::create_dir_all(mc_dir.join(BACKUP_DIR)).unwrap();
+        fs::rename(mc_dir.join(MODS_DIR), mc_dir.join(BACKUP_DIR).join(MODS_DIR)).unwrap();
+        fs::rename(update.get_staged_dir(MODS_DIR), mc_dir.join(MODS_DIR)).unwrap();
+        drop(update);
+
+        assert!(recover_update(&mc_dir).unwrap());
+        assert!(mc_dir.join(MODS_DIR).join("old.jar").is_file());
+        assert!(!mc_dir.join(MODS_DIR).join("new.jar").exists());
+        assert!(!mc_dir.join(SHADERPACKS_DIR).exists());
+        assert!(!recover_update(&mc_dir).unwrap());
+
+        fs::remove_dir_all(mc_dir).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+use crate::crash::{analyze_crash, get_latest_crash_report, read_mod_jar, LATEST_LOG};
+use crate::expections::{LauncherLibError, LibResult};
+use crate::json::runtime::MinecraftJavaRuntime;
+use crate::preflight::get_java_version_output;
+use crate::runtime::{get_exectable_path, get_jvm_platform_string};
+use crate::staging::MODS_DIR;
+use crate::utils::{get_os_version, get_sha1};
+use regex::Regex;
+use std::env::{self, consts};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+
+const REDACTED: &str = "<redacted>";
+
+/// Hides access tokens, account names and the user's home directory in text
+/// that goes into a support bundle. `secrets` are replaced wherever they appear.
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_string();
+    // short values would replace parts of unrelated words
+    for secret in secrets.iter().filter(|secret| secret.len() >= 3) {
+        text = text.replace(secret.as_str(), REDACTED);
+    }
+
+    let patterns = [
+        // launch arguments, as a command line or as modlauncher logs them: `[--username, Steve, ...]`
+        r"(--(?:username|uuid|accessToken|session|xuid|clientId)[\s,=]+)[^\s,\]]+",
+        r"(Setting user: )\S+",
+        r#"("(?:accessToken|access_token|refresh_token|clientToken|xuid)"\s*:\s*")[^"]*"#,
+    ];
+    for pattern in patterns {
+        let regex = Regex::new(pattern).expect("Invalid redaction regex");
+        text = regex
+            .replace_all(&text, format!("${{1}}{}", REDACTED).as_str())
+            .to_string();
+    }
+    let jwt = Regex::new(r"eyJ[\w-]+\.[\w-]+\.[\w-]*").expect("Invalid redaction regex");
+    text = jwt.replace_all(&text, REDACTED).to_string();
+
+    // the home directory is usually named after the person
+    for var in ["HOME", "USERPROFILE"] {
+        if let Ok(home) = env::var(var) {
+            if home.len() > 1 {
+                text = text.replace(&home, "~");
+            }
+        }
+    }
+    text
+}
+
+/// Total physical memory in bytes
+fn get_total_memory() -> Option<u64> {
+    match consts::OS {
+        "linux" => {
+            let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+            let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+            let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+            Some(kb * 1024)
+        }
+        "macos" => {
+            let output = std::process::Command::new("sysctl")
+                .args(["-n", "hw.memsize"])
+                .output()
+                .ok()?;
+            String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+        }
+        "windows" => {
+            let output = std::process::Command::new("powershell")
+                .args([
+                    "-NoProfile",
+                    "-Command",
+                    "(Get-CimInstance Win32_ComputerSystem).TotalPhysicalMemory",
+                ])
+                .output()
+                .ok()?;
+            String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+        }
+        _ => None,
+    }
+}
+
+fn get_installed_versions(mc_dir: &Path) -> Vec<String> {
+    let mut versions: Vec<String> = match fs::read_dir(mc_dir.join("versions")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|id| {
+                mc_dir
+                    .join("versions")
+                    .join(id)
+                    .join(format!("{}.json", id))
+                    .is_file()
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    versions.sort();
+    versions
+}
+
+/// The java runtimes installed in the minecraft directory and the one on the PATH
+async fn get_java_runtimes(mc_dir: &Path) -> Vec<(String, String)> {
+    let mut components: Vec<String> = match fs::read_dir(mc_dir.join("runtime")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => vec![],
+    };
+    components.sort();
+
+    let mut javas = vec![];
+    for component in components {
+        let runtime: MinecraftJavaRuntime =
+            match serde_json::from_value(serde_json::Value::String(component.clone())) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+        if let Ok(Some(java)) = get_exectable_path(runtime, mc_dir.to_path_buf()) {
+            javas.push((component, java.to_string_lossy().to_string()));
+        }
+    }
+    javas.push(("PATH".into(), "java".into()));
+
+    let mut runtimes = vec![];
+    for (name, java) in javas {
+        let version = match get_java_version_output(&java).await {
+            Ok(output) => output.lines().next().unwrap_or_default().to_string(),
+            Err(err) => format!("not usable: {}", err),
+        };
+        runtimes.push((name, format!("{} | {}", java, version)));
+    }
+    runtimes
+}
+
+/// The profiles of the vanilla launcher with the version and java arguments they start
+fn get_launcher_profiles(path: &Path) -> Option<String> {
+    let raw = fs::read_to_string(path).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&raw).ok()?;
+    let profiles = json["profiles"].as_object()?;
+    let mut lines = vec![];
+    for (key, profile) in profiles {
+        lines.push(format!(
+            "{}: {} | version {} | java args {}",
+            key,
+            profile["name"].as_str().unwrap_or_default(),
+            profile["lastVersionId"].as_str().unwrap_or("-"),
+            profile["javaArgs"].as_str().unwrap_or("default"),
+        ));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Everything we ask for in a support ticket, written into one zip
+#[derive(Debug, Clone, Default)]
+pub struct SupportBundle {
+    mc_dir: PathBuf,
+    game_dir: Option<PathBuf>,
+    installer_log: Option<PathBuf>,
+    launcher_profiles: Option<PathBuf>,
+    secrets: Vec<String>,
+}
+impl SupportBundle {
+    pub fn new(mc_dir: PathBuf) -> Self {
+        Self {
+            launcher_profiles: Some(mc_dir.join("launcher_profiles.json")),
+            mc_dir,
+            ..Default::default()
+        }
+    }
+
+    /// Where the mods, logs and crash reports are, if not the minecraft directory
+    pub fn game_directory(mut self, dir: PathBuf) -> Self {
+        self.game_dir = Some(dir);
+        self
+    }
+
+    pub fn installer_log(mut self, path: PathBuf) -> Self {
+        self.installer_log = Some(path);
+        self
+    }
+
+    pub fn launcher_profiles(mut self, path: PathBuf) -> Self {
+        self.launcher_profiles = Some(path);
+        self
+    }
+
+    /// A value to hide wherever it shows up, like the player's name or token
+    pub fn redact<T: Into<String>>(mut self, secret: T) -> Self {
+        self.secrets.push(secret.into());
+        self
+    }
+
+    async fn get_system_info(&self) -> String {
+        let versions = get_installed_versions(&self.mc_dir);
+        let forge: Vec<&String> = versions.iter().filter(|id| id.contains("forge")).collect();
+        let mut lines = vec![
+            format!("Launcher library: {}", env!("CARGO_PKG_VERSION")),
+            format!("OS: {} {} ({})", consts::OS, get_os_version(), consts::ARCH),
+            format!(
+                "Java platform: {}",
+                get_jvm_platform_string().unwrap_or_else(|err| err.to_string())
+            ),
+            format!(
+                "Memory: {}",
+                match get_total_memory() {
+                    Some(bytes) => format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64),
+                    None => "unknown".into(),
+                }
+            ),
+            format!("Installed versions: {}", versions.join(", ")),
+            format!(
+                "Forge: {}",
+                forge
+                    .iter()
+                    .map(|id| id.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+            "Java runtimes:".into(),
+        ];
+        for (name, java) in get_java_runtimes(&self.mc_dir).await {
+            lines.push(format!("  {}: {}", name, java));
+        }
+        lines.join("\n")
+    }
+
+    /// `sha1  file  mod ids` for every jar in the mods directory
+    async fn get_mods_list(&self, game_dir: &Path) -> String {
+        let mut jars: Vec<PathBuf> = match fs::read_dir(game_dir.join(MODS_DIR)) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map(|ext| ext == "jar").unwrap_or(false))
+                .collect(),
+            Err(_) => return "No mods directory".into(),
+        };
+        jars.sort();
+
+        let mut lines = vec![];
+        for jar in jars {
+            let sha1 = match get_sha1(jar.clone()).await {
+                Ok(value) => value,
+                Err(_) => "unreadable".into(),
+            };
+            let mod_ids = match read_mod_jar(&jar) {
+                Ok(value) => value.mod_ids.join(","),
+                Err(_) => "not a valid jar".into(),
+            };
+            lines.push(format!(
+                "{}  {}  {}",
+                sha1,
+                jar.file_name().unwrap_or_default().to_string_lossy(),
+                mod_ids
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Collects the files and writes the zip to `output`, replacing it if it exists
+    pub async fn write(&self, output: &Path) -> LibResult<PathBuf> {
+        let game_dir = self.game_dir.clone().unwrap_or_else(|| self.mc_dir.clone());
+
+        let mut files: Vec<(String, String)> = vec![
+            ("system.txt".into(), self.get_system_info().await),
+            ("mods.txt".into(), self.get_mods_list(&game_dir).await),
+            ("diagnosis.txt".into(), analyze_crash(&game_dir).diagnosis()),
+        ];
+        if let Some(profiles) = self
+            .launcher_profiles
+            .as_deref()
+            .and_then(get_launcher_profiles)
+        {
+            files.push(("launcher-profiles.txt".into(), profiles));
+        }
+        let mut logs = vec![(LATEST_LOG.to_string(), game_dir.join(LATEST_LOG))];
+        if let Some(report) = get_latest_crash_report(&game_dir) {
+            let name = report.file_name().unwrap_or_default().to_string_lossy();
+            logs.push((format!("crash-reports/{}", name), report.clone()));
+        }
+        if let Some(log) = &self.installer_log {
+            logs.push(("installer.log".into(), log.clone()));
+        }
+        for (name, path) in logs {
+            if let Ok(bytes) = fs::read(&path) {
+                files.push((name, String::from_utf8_lossy(&bytes).to_string()));
+            }
+        }
+
+        if let Some(parent) = output.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: format!("Failed to create {}", parent.display()),
+                });
+            }
+        }
+        let file = match File::create(output) {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: format!("Failed to create {}", output.display()),
+                })
+            }
+        };
+        let mut zip = zip::ZipWriter::new(file);
+        for (name, contents) in files {
+            if let Err(err) = zip.start_file(name.as_str(), FileOptions::default()) {
+                return Err(LauncherLibError::ZipError(err));
+            }
+            if let Err(err) = zip.write_all(redact(&contents, &self.secrets).as_bytes()) {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: format!("Failed to write {} into the bundle", name),
+                });
+            }
+        }
+        if let Err(err) = zip.finish() {
+            return Err(LauncherLibError::ZipError(err));
+        }
+
+        Ok(output.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_redact() {
+        let secrets = vec!["Steve".to_string(), "0f".to_string()];
+        let log =
+            "args [--username, Steve, --version, 1.18.2, --accessToken, abc.def, --uuid, 0f3a]\n\
+                   Setting user: Alex\n\
+                   java -jar x.jar --accessToken eyJhbGciOi.eyJzdWIi.sig --width 854\n\
+                   {\"accessToken\": \"secret\", \"name\": \"Seabreyh Mods\"}\n\
+                   Steve joined the game, 0f left";
+        let redacted = redact(log, &secrets);
+        assert_eq!(
+            redacted,
+            "args [--username, <redacted>, --version, 1.18.2, --accessToken, <redacted>, --uuid, <redacted>]\n\
+             Setting user: <redacted>\n\
+             java -jar x.jar --accessToken <redacted> --width 854\n\
+             {\"accessToken\": \"<redacted>\", \"name\": \"Seabreyh Mods\"}\n\
+             <redacted> joined the game, 0f left"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_support_bundle() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-support-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(mc_dir.join(MODS_DIR)).unwrap();
+        fs::create_dir_all(mc_dir.join("logs")).unwrap();
+        fs::create_dir_all(mc_dir.join("crash-reports")).unwrap();
+        fs::create_dir_all(mc_dir.join("versions/1.18.2-forge-40.1.73")).unwrap();
+        fs::write(
+            mc_dir.join("versions/1.18.2-forge-40.1.73/1.18.2-forge-40.1.73.json"),
+            "{}",
+        )
+        .unwrap();
+        fs::copy(
+            "tests/fixtures/mods/create-mc1.18.2_v0.5.0c.jar",
+            mc_dir.join(MODS_DIR).join("create-mc1.18.2_v0.5.0c.jar"),
+        )
+        .unwrap();
+        fs::copy("tests/fixtures/crash/latest.log", mc_dir.join(LATEST_LOG)).unwrap();
+        fs::copy(
+            "tests/fixtures/crash/crash-2022-08-01_12.00.00-client.txt",
+            mc_dir.join("crash-reports/crash-2022-08-01_12.00.00-client.txt"),
+        )
+        .unwrap();
+        fs::write(
+            mc_dir.join("launcher_profiles.json"),
+            r#"{"profiles": {"forge": {"name": "Seabreyh Mods", "lastVersionId": "1.18.2-forge-40.1.73", "javaArgs": "-Xmx4G"}}}"#,
+        )
+        .unwrap();
+        let installer_log = mc_dir.join("installer.log");
+        fs::write(&installer_log, "Signed in as SecretSteve\n").unwrap();
+
+        let output = mc_dir.join("bundle").join("support.zip");
+        SupportBundle::new(mc_dir.clone())
+            .installer_log(installer_log)
+            .redact("SecretSteve")
+            .write(&output)
+            .await
+            .unwrap();
+
+        let mut zip = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let system = read("system.txt");
+        assert!(system.contains(&format!("OS: {}", consts::OS)));
+        assert!(system.contains("Forge: 1.18.2-forge-40.1.73"));
+        assert!(system.contains("  PATH: java | "));
+        assert!(read("mods.txt").ends_with("  create-mc1.18.2_v0.5.0c.jar  create"));
+        assert!(read("launcher-profiles.txt")
+            .contains("forge: Seabreyh Mods | version 1.18.2-forge-40.1.73 | java args -Xmx4G"));
+        assert!(read(LATEST_LOG).contains("--username, <redacted>, --version"));
+        assert!(read("crash-reports/crash-2022-08-01_12.00.00-client.txt")
+            .starts_with("---- Minecraft Crash Report ----"));
+        assert!(read("diagnosis.txt").starts_with("Mod loading error has occurred"));
+        assert_eq!(read("installer.log"), "Signed in as <redacted>\n");
+
+        let _ = fs::remove_dir_all(mc_dir);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::expections::{LauncherLibError, LibResult};
+use std::collections::HashMap;
+
+/// A `${...}` placeholder of the launch arguments in version jsons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variable {
+    AuthPlayerName,
+    AuthUuid,
+    AuthAccessToken,
+    /// the pre 1.7 name of the access token
+    AuthSession,
+    AuthXuid,
+    ClientId,
+    UserType,
+    UserProperties,
+    VersionName,
+    VersionType,
+    GameDirectory,
+    AssetsRoot,
+    GameAssets,
+    AssetsIndexName,
+    LibraryDirectory,
+    NativesDirectory,
+    Classpath,
+    ClasspathSeparator,
+    LauncherName,
+    LauncherVersion,
+    ResolutionWidth,
+    ResolutionHeight,
+    QuickPlayPath,
+    QuickPlaySingleplayer,
+    QuickPlayMultiplayer,
+    QuickPlayRealms,
+    /// the log config file in the `logging` argument
+    Path,
+}
+impl Variable {
+    const ALL: [Variable; 27] = [
+        Variable::AuthPlayerName,
+        Variable::AuthUuid,
+        Variable::AuthAccessToken,
+        Variable::AuthSession,
+        Variable::AuthXuid,
+        Variable::ClientId,
+        Variable::UserType,
+        Variable::UserProperties,
+        Variable::VersionName,
+        Variable::VersionType,
+        Variable::GameDirectory,
+        Variable::AssetsRoot,
+        Variable::GameAssets,
+        Variable::AssetsIndexName,
+        Variable::LibraryDirectory,
+        Variable::NativesDirectory,
+        Variable::Classpath,
+        Variable::ClasspathSeparator,
+        Variable::LauncherName,
+        Variable::LauncherVersion,
+        Variable::ResolutionWidth,
+        Variable::ResolutionHeight,
+        Variable::QuickPlayPath,
+        Variable::QuickPlaySingleplayer,
+        Variable::QuickPlayMultiplayer,
+        Variable::QuickPlayRealms,
+        Variable::Path,
+    ];
+
+    /// The name between `${` and `}`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variable::AuthPlayerName => "auth_player_name",
+            Variable::AuthUuid => "auth_uuid",
+            Variable::AuthAccessToken => "auth_access_token",
+            Variable::AuthSession => "auth_session",
+            Variable::AuthXuid => "auth_xuid",
+            Variable::ClientId => "clientid",
+            Variable::UserType => "user_type",
+            Variable::UserProperties => "user_properties",
+            Variable::VersionName => "version_name",
+            Variable::VersionType => "version_type",
+            Variable::GameDirectory => "game_directory",
+            Variable::AssetsRoot => "assets_root",
+            Variable::GameAssets => "game_assets",
+            Variable::AssetsIndexName => "assets_index_name",
+            Variable::LibraryDirectory => "library_directory",
+            Variable::NativesDirectory => "natives_directory",
+            Variable::Classpath => "classpath",
+            Variable::ClasspathSeparator => "classpath_separator",
+            Variable::LauncherName => "launcher_name",
+            Variable::LauncherVersion => "launcher_version",
+            Variable::ResolutionWidth => "resolution_width",
+            Variable::ResolutionHeight => "resolution_height",
+            Variable::QuickPlayPath => "quickPlayPath",
+            Variable::QuickPlaySingleplayer => "quickPlaySingleplayer",
+            Variable::QuickPlayMultiplayer => "quickPlayMultiplayer",
+            Variable::QuickPlayRealms => "quickPlayRealms",
+            Variable::Path => "path",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variable> {
+        Variable::ALL
+            .iter()
+            .find(|variable| variable.name() == name)
+            .copied()
+    }
+}
+
+/// Values of the placeholders known for a launch
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: HashMap<Variable, String>,
+}
+impl Variables {
+    pub fn set<T: Into<String>>(&mut self, variable: Variable, value: T) -> &mut Self {
+        self.values.insert(variable, value.into());
+        self
+    }
+
+    /// Sets the variable when there is a value, leaving it unresolved otherwise
+    pub fn set_some<T: Into<String>>(&mut self, variable: Variable, value: Option<T>) -> &mut Self {
+        if let Some(value) = value {
+            self.values.insert(variable, value.into());
+        }
+        self
+    }
+
+    pub fn get(&self, variable: Variable) -> Option<&str> {
+        self.values.get(&variable).map(|value| value.as_str())
+    }
+
+    /// Replaces every `${name}` in the template in one pass, values are not scanned again.
+    /// Fails on names that aren't known placeholders or have no value.
+    pub fn render(&self, template: &str) -> LibResult<String> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("${") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = match after.find('}') {
+                Some(value) => value,
+                None => {
+                    return Err(LauncherLibError::InvalidArguments(format!(
+                        "Unclosed placeholder in {}",
+                        template
+                    )))
+                }
+            };
+            let name = &after[..end];
+            match Variable::from_name(name).and_then(|variable| self.get(variable)) {
+                Some(value) => output.push_str(value),
+                None => {
+                    return Err(LauncherLibError::UnresolvedPlaceholder {
+                        name: name.to_string(),
+                        argument: template.to_string(),
+                    })
+                }
+            }
+            rest = &after[end + 1..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+/// Splits user supplied arguments like a shell does: on whitespace, keeping what is in
+/// single or double quotes together. A backslash escapes a quote, whitespace or another
+/// backslash and is kept as is before anything else, so windows paths need no escaping.
+pub fn split_args(input: &str) -> LibResult<Vec<String>> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => current.push(c),
+            (Some('"'), '"') => quote = None,
+            (_, '\\') => {
+                in_word = true;
+                match chars.peek() {
+                    Some(next)
+                        if *next == '"'
+                            || *next == '\\'
+                            || (quote.is_none() && (*next == '\'' || next.is_whitespace())) =>
+                    {
+                        current.push(*next);
+                        chars.next();
+                    }
+                    _ => current.push(c),
+                }
+            }
+            (Some(_), _) => current.push(c),
+            (None, '\'') | (None, '"') => {
+                in_word = true;
+                quote = Some(c);
+            }
+            (None, _) if c.is_whitespace() => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, _) => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if let Some(quote) = quote {
+        return Err(LauncherLibError::InvalidArguments(format!(
+            "Missing closing {} in {}",
+            quote, input
+        )));
+    }
+    if in_word {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut variables = Variables::default();
+        variables
+            .set(Variable::AuthPlayerName, "Steve")
+            .set(Variable::Classpath, "${auth_player_name}.jar")
+            .set_some(Variable::AuthXuid, None::<String>);
+
+        assert_eq!(
+            variables.render("--username=${auth_player_name}!").unwrap(),
+            "--username=Steve!"
+        );
+        // values are inserted as they are
+        assert_eq!(
+            variables.render("${classpath}").unwrap(),
+            "${auth_player_name}.jar"
+        );
+        assert_eq!(variables.render("-Xmx2G").unwrap(), "-Xmx2G");
+
+        match variables.render("--xuid ${auth_xuid}") {
+            Err(LauncherLibError::UnresolvedPlaceholder { name, .. }) => {
+                assert_eq!(name, "auth_xuid")
+            }
+            other => panic!("{:?}", other),
+        }
+        match variables.render("${made_up}") {
+            Err(LauncherLibError::UnresolvedPlaceholder { name, .. }) => {
+                assert_eq!(name, "made_up")
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(variables.render("${auth_player_name").is_err());
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args("  -Xmx2G   -XX:+UseG1GC ").unwrap(),
+            vec!["-Xmx2G", "-XX:+UseG1GC"]
+        );
+        assert_eq!(
+            split_args(r#"-Dpath="C:\Program Files\Java" '-Dname=a b' -Da=b\ c"#).unwrap(),
+            vec![r"-Dpath=C:\Program Files\Java", "-Dname=a b", "-Da=b c"]
+        );
+        assert_eq!(
+            split_args(r#"-Dquote=\"x\" """#).unwrap(),
+            vec![r#"-Dquote="x""#, ""]
+        );
+        assert!(split_args("-Dpath=\"C:\\Program Files").is_err());
+        assert!(split_args("").unwrap().is_empty());
+    }
+}
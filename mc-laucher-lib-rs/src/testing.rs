@@ -0,0 +1,49 @@
+//! Fixture factories shared by the unit tests
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::Path;
+
+/// Writes a zip with the given entries, names ending in `/` become directories
+pub fn write_jar(path: &Path, entries: &[(&str, &str)]) {
+    create_dir_all(path.parent().unwrap()).unwrap();
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in entries {
+        if name.ends_with('/') {
+            zip.add_directory(*name, Default::default()).unwrap();
+        } else {
+            zip.start_file(*name, Default::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+    }
+    zip.finish().unwrap();
+}
+
+/// Writes the version json to `versions/<id>/<id>.json` of the minecraft directory
+pub fn write_version(mc_dir: &Path, json: serde_json::Value) {
+    let id = json["id"].as_str().unwrap().to_string();
+    let dir = mc_dir.join("versions").join(&id);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("{}.json", id)), json.to_string()).unwrap();
+}
+
+/// Json of a version with only the given libraries, inheriting from `parent` when set
+pub fn version_json(
+    id: &str,
+    main_class: &str,
+    libraries: &[&str],
+    parent: Option<&str>,
+) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "arguments": { "game": [] },
+        "id": id,
+        "libraries": libraries.iter().map(|name| serde_json::json!({ "name": name })).collect::<Vec<_>>(),
+        "mainClass": main_class,
+        "releaseTime": "2022-01-01T00:00:00+00:00",
+        "time": "2022-01-01T00:00:00+00:00",
+        "type": "release"
+    });
+    if let Some(parent) = parent {
+        json["inheritsFrom"] = parent.into();
+    }
+    json
+}
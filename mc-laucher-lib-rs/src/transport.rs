@@ -0,0 +1,503 @@
+use crate::expections::{LauncherLibError, LibResult};
+use async_trait::async_trait;
+use crypto::{digest::Digest, sha1::Sha1};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::fs::{create_dir_all, read, read_to_string, write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Name of the index file in a fixture directory
+pub const FIXTURE_INDEX: &str = "fixtures.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Method {
+    #[serde(rename = "GET")]
+    Get,
+    #[serde(rename = "POST")]
+    Post,
+}
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Get => write!(f, "GET"),
+            Method::Post => write!(f, "POST"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RequestBody {
+    Json(serde_json::Value),
+    Form(Vec<(String, String)>),
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<RequestBody>,
+    /// timeout for the whole request, streamed bodies are not covered
+    pub timeout: Option<Duration>,
+}
+impl HttpRequest {
+    pub fn new(method: Method, url: &str) -> Self {
+        Self {
+            method,
+            url: url.to_string(),
+            headers: vec![],
+            body: None,
+            timeout: None,
+        }
+    }
+
+    pub fn get(url: &str) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        Self::new(Method::Post, url)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn json(mut self, body: serde_json::Value) -> Self {
+        self.body = Some(RequestBody::Json(body));
+        self
+    }
+
+    pub fn form(mut self, params: &[(&str, &str)]) -> Self {
+        self.body = Some(RequestBody::Form(
+            params
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        ));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+enum ResponseBody {
+    Reqwest(reqwest::Response),
+    Buffered(Option<Vec<u8>>),
+}
+
+pub struct HttpResponse {
+    pub url: String,
+    pub status: u16,
+    /// header names are lowercase
+    pub headers: HashMap<String, String>,
+    body: ResponseBody,
+}
+impl Debug for HttpResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("url", &self.url)
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+impl HttpResponse {
+    pub fn new(url: &str, status: u16, headers: HashMap<String, String>, body: Vec<u8>) -> Self {
+        Self {
+            url: url.to_string(),
+            status,
+            headers,
+            body: ResponseBody::Buffered(Some(body)),
+        }
+    }
+
+    fn from_reqwest(response: reqwest::Response) -> Self {
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(key, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (key.as_str().to_lowercase(), value.to_string()))
+            })
+            .collect();
+        Self {
+            url: response.url().to_string(),
+            status: response.status().as_u16(),
+            headers,
+            body: ResponseBody::Reqwest(response),
+        }
+    }
+
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    /// Length of the body from the `Content-Length` header
+    pub fn content_length(&self) -> Option<u64> {
+        match &self.body {
+            ResponseBody::Buffered(Some(body)) => Some(body.len() as u64),
+            _ => self
+                .get_header("content-length")
+                .and_then(|value| value.trim().parse::<u64>().ok()),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Next piece of the body, `None` once it has been read completely
+    pub async fn chunk(&mut self) -> LibResult<Option<Vec<u8>>> {
+        match &mut self.body {
+            ResponseBody::Reqwest(response) => match response.chunk().await {
+                Ok(value) => Ok(value.map(|chunk| chunk.to_vec())),
+                Err(err) => Err(LauncherLibError::HTTP {
+                    msg: format!("Failed to read response | {}", self.url),
+                    source: err,
+                }),
+            },
+            ResponseBody::Buffered(body) => Ok(body.take()),
+        }
+    }
+
+    pub async fn bytes(mut self) -> LibResult<Vec<u8>> {
+        let mut body = vec![];
+        loop {
+            match self.chunk().await {
+                Ok(Some(mut chunk)) => body.append(&mut chunk),
+                Ok(None) => return Ok(body),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub async fn text(self) -> LibResult<String> {
+        match self.bytes().await {
+            Ok(value) => Ok(String::from_utf8_lossy(&value).to_string()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn json<T: DeserializeOwned>(self) -> LibResult<T> {
+        match self.bytes().await {
+            Ok(value) => match serde_json::from_slice::<T>(&value) {
+                Ok(json) => Ok(json),
+                Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Everything the library sends over the network goes through a transport
+#[async_trait]
+pub trait Transport: Send + Sync + Debug {
+    async fn send(&self, request: HttpRequest) -> LibResult<HttpResponse>;
+}
+
+/// Transport that makes real requests using reqwest
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> LibResult<HttpResponse> {
+        let mut builder = match request.method {
+            Method::Get => self.client.get(request.url.as_str()),
+            Method::Post => self.client.post(request.url.as_str()),
+        };
+        for (key, value) in &request.headers {
+            builder = builder.header(key.as_str(), value.as_str());
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder = match request.body {
+            Some(RequestBody::Json(value)) => builder.json(&value),
+            Some(RequestBody::Form(value)) => builder.form(&value),
+            Some(RequestBody::Raw(value)) => builder.body(value),
+            None => builder,
+        };
+
+        match builder.send().await {
+            Ok(response) => Ok(HttpResponse::from_reqwest(response)),
+            Err(err) => Err(LauncherLibError::HTTP {
+                msg: format!("Failed to make http request | {}", request.url),
+                source: err,
+            }),
+        }
+    }
+}
+
+/// A single request and its response as stored in a fixture directory
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordedExchange {
+    pub method: Method,
+    pub url: String,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// file holding the response body, relative to the fixture directory
+    pub body: String,
+}
+
+/// Status, headers and body of a recorded response
+type RecordedResponse = (u16, HashMap<String, String>, Vec<u8>);
+
+/// Transport that answers requests from recorded fixtures instead of the network
+#[derive(Debug, Default)]
+pub struct ReplayTransport {
+    responses: HashMap<(Method, String), RecordedResponse>,
+    requests: Mutex<Vec<String>>,
+}
+impl ReplayTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the fixtures listed in `fixtures.json` of the given directory
+    pub fn from_dir(dir: PathBuf) -> LibResult<Self> {
+        let index: Vec<RecordedExchange> = match read_to_string(dir.join(FIXTURE_INDEX)) {
+            Ok(raw) => match serde_json::from_str(&raw) {
+                Ok(value) => value,
+                Err(err) => return Err(LauncherLibError::ParseJsonSerde(err)),
+            },
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: format!("Failed to read fixture index in {:?}", dir),
+                })
+            }
+        };
+
+        let mut transport = ReplayTransport::new();
+        for exchange in index {
+            let body = match read(dir.join(&exchange.body)) {
+                Ok(value) => value,
+                Err(err) => {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: format!("Failed to read fixture {}", exchange.body),
+                    })
+                }
+            };
+            transport.responses.insert(
+                (exchange.method, exchange.url),
+                (exchange.status, exchange.headers, body),
+            );
+        }
+        Ok(transport)
+    }
+
+    pub fn route(mut self, method: Method, url: &str, status: u16, body: &[u8]) -> Self {
+        self.responses.insert(
+            (method, url.to_string()),
+            (status, HashMap::new(), body.to_vec()),
+        );
+        self
+    }
+
+    /// `METHOD url` of every request that was made, in order
+    pub fn get_requests(&self) -> Vec<String> {
+        match self.requests.lock() {
+            Ok(value) => value.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn send(&self, request: HttpRequest) -> LibResult<HttpResponse> {
+        let key = format!("{} {}", request.method, request.url);
+        match self.requests.lock() {
+            Ok(mut value) => value.push(key.clone()),
+            Err(poisoned) => poisoned.into_inner().push(key.clone()),
+        }
+
+        match self.responses.get(&(request.method, request.url.clone())) {
+            Some((status, headers, body)) => Ok(HttpResponse::new(
+                &request.url,
+                *status,
+                headers.clone(),
+                body.clone(),
+            )),
+            None => Err(LauncherLibError::NotFound(format!(
+                "No recorded response | {}",
+                key
+            ))),
+        }
+    }
+}
+
+/// Transport that passes requests on and saves every exchange as a fixture
+/// that `ReplayTransport::from_dir` can serve. Recorded bodies are not redacted.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+    exchanges: Mutex<Vec<RecordedExchange>>,
+}
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, dir: PathBuf) -> Self {
+        Self {
+            inner,
+            dir,
+            exchanges: Mutex::new(vec![]),
+        }
+    }
+
+    fn get_body_name(method: Method, url: &str) -> String {
+        let mut hasher = Sha1::new();
+        hasher.input_str(&format!("{} {}", method, url));
+        let last = url
+            .split(['/', '?', '#'])
+            .rfind(|part| !part.is_empty())
+            .unwrap_or("body")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .take(48)
+            .collect::<String>();
+        format!("{}-{}", &hasher.result_str()[..12], last)
+    }
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn send(&self, request: HttpRequest) -> LibResult<HttpResponse> {
+        let method = request.method;
+        let url = request.url.clone();
+
+        let response = match self.inner.send(request).await {
+            Ok(value) => value,
+            Err(err) => return Err(err),
+        };
+        let status = response.status;
+        let headers = response.headers.clone();
+        let body = match response.bytes().await {
+            Ok(value) => value,
+            Err(err) => return Err(err),
+        };
+
+        let name = RecordingTransport::get_body_name(method, &url);
+        let exchange = RecordedExchange {
+            method,
+            url: url.clone(),
+            status,
+            headers: headers.clone(),
+            body: name.clone(),
+        };
+
+        let index = {
+            let mut exchanges = match self.exchanges.lock() {
+                Ok(value) => value,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            exchanges.retain(|item| !(item.method == method && item.url == url));
+            exchanges.push(exchange);
+            serde_json::to_string_pretty(&*exchanges)
+        };
+
+        if let Err(err) = create_dir_all(&self.dir) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to create fixture directory".into(),
+            });
+        }
+        if let Err(err) = write(self.dir.join(&name), &body) {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to write fixture".into(),
+            });
+        }
+        match index {
+            Ok(value) => {
+                if let Err(err) = write(self.dir.join(FIXTURE_INDEX), value) {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: "Failed to write fixture index".into(),
+                    });
+                }
+            }
+            Err(err) => return Err(LauncherLibError::ParseJsonSerde(err)),
+        }
+
+        Ok(HttpResponse::new(&url, status, headers, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("mc-fixtures-{}", uuid::Uuid::new_v4()));
+
+        let upstream = Arc::new(
+            ReplayTransport::new()
+                .route(Method::Get, "https://example.com/a.json", 200, b"{\"a\":1}")
+                .route(Method::Post, "https://example.com/login", 401, b"denied"),
+        );
+        let recorder = RecordingTransport::new(upstream, dir.clone());
+
+        let value: serde_json::Value = recorder
+            .send(HttpRequest::get("https://example.com/a.json"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(value["a"], 1);
+        let denied = recorder
+            .send(HttpRequest::post("https://example.com/login"))
+            .await
+            .unwrap();
+        assert_eq!(denied.status, 401);
+
+        let replay = ReplayTransport::from_dir(dir.clone()).unwrap();
+        let text = replay
+            .send(HttpRequest::get("https://example.com/a.json"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(text, "{\"a\":1}");
+        assert!(replay
+            .send(HttpRequest::get("https://example.com/missing"))
+            .await
+            .is_err());
+        assert_eq!(
+            replay.get_requests(),
+            vec!["GET https://example.com/a.json", "GET https://example.com/missing"]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,20 +1,30 @@
+use crate::cancel::CancellationToken;
+use crate::context::get_context;
 use crate::expections::{LauncherLibError, LibResult};
+use crate::journal::find_journal;
 use crate::json::{
     game_settings::GameOptions,
     install::{Callback, DownloadState, Event, Rule, VersionManifest},
 };
+use crate::natives::substitute_arm64_natives;
+use crate::retry::{check_status, with_retry};
+use crate::rules::{is_allowed, is_rule_match, RuleEnvironment};
+use crate::transport::{HttpRequest, HttpResponse};
 use crypto::{digest::Digest, sha1::Sha1};
 use log::error;
+use serde::de::DeserializeOwned;
 use std::env::{consts, var};
-use std::io::Cursor;
-use std::path::PathBuf;
-use tokio::fs::{create_dir_all, read, read_to_string, remove_file, File};
-use tokio::io::copy;
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, read_to_string, remove_file, rename, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 pub async fn read_manifest(path: PathBuf) -> LibResult<VersionManifest> {
     match read_to_string(path).await {
         Ok(raw) => match serde_json::from_str::<VersionManifest>(&raw) {
-            Ok(value) => Ok(value),
+            Ok(mut value) => {
+                substitute_arm64_natives(&mut value.libraries, consts::OS, consts::ARCH);
+                Ok(value)
+            }
             Err(err) => Err(LauncherLibError::ParseJsonSerde(err)),
         },
         Err(err) => Err(LauncherLibError::OS {
@@ -24,21 +34,64 @@ pub async fn read_manifest(path: PathBuf) -> LibResult<VersionManifest> {
     }
 }
 
-/// creates the http client with the set user_agent
-pub async fn get_http_client() -> LibResult<reqwest::Client> {
-    let client = reqwest::ClientBuilder::new();
-    match client
-        .user_agent(format!("rustymodclient/{}", env!("CARGO_PKG_VERSION")))
-        .build()
-    {
-        Ok(value) => Ok(value),
-        Err(err) => Err(LauncherLibError::HTTP {
-            msg: "Failed to create http client".into(),
-            source: err,
-        }),
+/// Fetches and parses a json document, retrying and falling back to mirrors on failure
+pub async fn get_json<T: DeserializeOwned>(url: &str) -> LibResult<T> {
+    with_retry(url, |candidate| async move {
+        match get_response(&candidate).await {
+            Ok(response) => response.json::<T>().await,
+            Err(err) => Err(err),
+        }
+    })
+    .await
+}
+
+/// Fetches a text document, retrying and falling back to mirrors on failure
+pub async fn get_text(url: &str) -> LibResult<String> {
+    with_retry(url, |candidate| async move {
+        match get_response(&candidate).await {
+            Ok(response) => response.text().await,
+            Err(err) => Err(err),
+        }
+    })
+    .await
+}
+
+/// makes a single get request, failing on a non success status
+async fn get_response(url: &str) -> LibResult<HttpResponse> {
+    let mut request = HttpRequest::get(url);
+    if let Some(timeout) = get_context().http.read_timeout {
+        request = request.timeout(timeout);
+    }
+    send_request(request).await
+}
+
+/// Sends a request through the transport of the current context, failing on a non success status
+pub async fn send_request(request: HttpRequest) -> LibResult<HttpResponse> {
+    let transport = match get_context().get_transport() {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    let url = request.url.clone();
+    match transport.send(request).await {
+        Ok(response) => check_status(&url, response),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sends a request without retrying and parses the json response
+pub async fn send_json<T: DeserializeOwned>(request: HttpRequest) -> LibResult<T> {
+    match send_request(request).await {
+        Ok(response) => response.json::<T>().await,
+        Err(err) => Err(err),
     }
 }
 
+/// returns the http client shared by the library, see `LauncherContext::http`
+pub async fn get_http_client() -> LibResult<reqwest::Client> {
+    get_context().get_client()
+}
+
 /// Returns the default path to the .minecraft directory
 pub fn get_minecraft_directory() -> LibResult<PathBuf> {
     match consts::OS {
@@ -73,7 +126,12 @@ pub async fn get_local_installed_versions(mc_dir: PathBuf) -> LibResult<Vec<Vers
                             match read_to_string(path).await {
                                 Ok(raw_json) => {
                                     match serde_json::from_str::<VersionManifest>(&raw_json) {
-                                        Ok(json) => {
+                                        Ok(mut json) => {
+                                            substitute_arm64_natives(
+                                                &mut json.libraries,
+                                                consts::OS,
+                                                consts::ARCH,
+                                            );
                                             versions.push(json);
                                         }
                                         Err(err) => {
@@ -181,25 +239,282 @@ pub fn get_os_version() -> String {
 /// generates the sha1 hash for a file
 pub async fn get_sha1(path: PathBuf) -> LibResult<String> {
     let mut hasher = Sha1::new();
-    match read(path).await {
-        Ok(raw) => {
-            hasher.input(&raw);
-            Ok(hasher.result_str())
+    match hash_file(&path, &mut hasher).await {
+        Ok(_) => Ok(hasher.result_str()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Path of the partial file a download is streamed into before it is moved into place
+pub fn get_part_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    output.with_file_name(name)
+}
+
+/// feeds the contents of a file into the hasher, returns the number of bytes read
+async fn hash_file(path: &PathBuf, hasher: &mut Sha1) -> LibResult<u64> {
+    let mut file = match File::open(path).await {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "SHA1 | Failed to open file".into(),
+            })
         }
-        Err(err) => Err(LauncherLibError::OS {
+    };
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut length: u64 = 0;
+    loop {
+        match file.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read) => {
+                hasher.input(&buffer[..read]);
+                length += read as u64;
+            }
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "SHA1 | Failed to read file".into(),
+                })
+            }
+        }
+    }
+
+    Ok(length)
+}
+
+/// Streams `url` into `part`, resuming from the end of an existing partial file
+/// with a http range request. Returns the sha1 of the complete partial file.
+/// Progress is reported as [`Event::Bytes`] under `source`, the url the caller asked for.
+async fn stream_to_part_file(
+    url: &str,
+    part: &PathBuf,
+    source: &str,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
+) -> LibResult<String> {
+    let context = get_context();
+    let transport = match context.get_transport() {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+    let limiter = context.get_limiter();
+
+    let mut hasher = Sha1::new();
+    let mut offset: u64 = 0;
+    if part.is_file() {
+        offset = match hash_file(part, &mut hasher).await {
+            Ok(value) => value,
+            Err(err) => return Err(err),
+        };
+    }
+
+    cancel.check()?;
+
+    let mut request = HttpRequest::get(url);
+    if offset > 0 {
+        request = request.header("Range", &format!("bytes={}-", offset));
+    }
+
+    let mut response = match transport.send(request).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    // the partial file already holds the whole body
+    if offset > 0 && response.status == 416 {
+        return Ok(hasher.result_str());
+    }
+
+    response = match check_status(url, response) {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    // server ignored the range header so start over from the beginning
+    let append = offset > 0 && response.status == 206;
+    if !append {
+        hasher.reset();
+        offset = 0;
+    }
+    let total = response.content_length().map(|length| length + offset);
+    callback.report(Event::bytes(source.to_string(), offset, total));
+
+    let mut file = match OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+    {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to create file".into(),
+            })
+        }
+    };
+
+    loop {
+        let read = async {
+            match context.http.read_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, response.chunk()).await {
+                    Ok(value) => value,
+                    Err(_) => Err(LauncherLibError::Timeout(format!(
+                        "No data received for {:?} | {}",
+                        timeout, url
+                    ))),
+                },
+                None => response.chunk().await,
+            }
+        };
+        let chunk = tokio::select! {
+            value = read => value,
+            _ = cancel.cancelled() => return Err(LauncherLibError::Cancelled),
+        };
+        match chunk {
+            Ok(Some(chunk)) => {
+                hasher.input(&chunk);
+                if let Err(err) = file.write_all(&chunk).await {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: "Failed to write contents to file".into(),
+                    });
+                }
+                offset += chunk.len() as u64;
+                callback.report(Event::bytes(source.to_string(), offset, total));
+                limiter.consume(chunk.len() as u64).await;
+            }
+            Ok(None) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    if let Err(err) = file.flush().await {
+        return Err(LauncherLibError::OS {
             source: err,
-            msg: "SHA1 | Failed to read file".into(),
-        }),
+            msg: "Failed to write contents to file".into(),
+        });
+    }
+
+    Ok(hasher.result_str())
+}
+
+/// Streams `url` into `part` and checks the result against `sha1`.
+/// On a mismatch the partial file is removed so the next attempt starts over.
+async fn fetch_part_file(
+    url: String,
+    part: &PathBuf,
+    sha1: Option<String>,
+    source: &str,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
+) -> LibResult<()> {
+    let digest = match stream_to_part_file(&url, part, source, callback, cancel).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+
+    if let Some(sha) = sha1 {
+        if digest != sha {
+            let _ = remove_file(part).await;
+            return Err(LauncherLibError::HashMismatch {
+                url,
+                expected: sha,
+                actual: digest,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves a finished partial download into place, decompressing it first when needed.
+async fn finalize_part_file(part: &PathBuf, output: &PathBuf, compressed: bool) -> LibResult<()> {
+    if compressed {
+        let mut name = output.file_name().unwrap_or_default().to_os_string();
+        name.push(".unpack");
+        let unpacked = output.with_file_name(name);
+
+        let source = match std::fs::File::open(part) {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to open partial download".into(),
+                })
+            }
+        };
+        let mut target = match std::fs::File::create(&unpacked) {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to create file".into(),
+                })
+            }
+        };
+
+        if let Err(error) =
+            lzma_rs::lzma_decompress(&mut std::io::BufReader::new(source), &mut target)
+        {
+            let _ = remove_file(&unpacked).await;
+            return Err(LauncherLibError::General(error.to_string()));
+        }
+
+        if let Err(err) = rename(&unpacked, output).await {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to move file into place".into(),
+            });
+        }
+        if let Err(err) = remove_file(part).await {
+            return Err(LauncherLibError::OS {
+                source: err,
+                msg: "Failed to remove partial download".into(),
+            });
+        }
+        return Ok(());
+    }
+
+    if let Err(err) = rename(part, output).await {
+        return Err(LauncherLibError::OS {
+            source: err,
+            msg: "Failed to move file into place".into(),
+        });
+    }
+    Ok(())
+}
+
+/// Counts a file that is already in place towards the byte progress
+fn report_existing_file(url: &str, output: &PathBuf, callback: Callback<'_>) {
+    if let Ok(metadata) = std::fs::metadata(output) {
+        callback.report(Event::bytes(
+            url.to_string(),
+            metadata.len(),
+            Some(metadata.len()),
+        ));
     }
 }
 
+/// Downloads a file by streaming it into a `.part` file next to `output`.
+/// Interrupted downloads are resumed and the file is only moved into place
+/// once its sha1 (of the transferred bytes) matches. Failed transfers are
+/// retried and fall back to the configured mirrors.
 pub async fn download_file(
     url: String,
     output: PathBuf,
-    callback: Callback,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
     sha1: Option<String>,
     compressed: bool,
 ) -> LibResult<DownloadState> {
+    cancel.check()?;
+
     // check if the file directory exits
     if !output.exists() {
         let mut path = output.clone();
@@ -214,11 +529,57 @@ pub async fn download_file(
         }
     }
 
+    let journal = find_journal(&output);
+
     // if exits/has sha1 check if vaild if not remove invaild file.
     if output.exists() && output.is_file() {
-        if sha1.is_none() {
-            callback(Event::download(DownloadState::ExistsUnchecked, url.clone()));
-            return Ok(DownloadState::ExistsUnchecked);
+        // files the install journal saw complete don't need to be hashed again
+        if let Some(journal) = &journal {
+            if journal.is_file_verified(&output, sha1.as_deref()) {
+                journal.use_file(&output);
+                report_existing_file(&url, &output, callback);
+                if sha1.is_some() {
+                    callback.report(Event::download(DownloadState::Exists, url.clone()));
+                    return Ok(DownloadState::Exists);
+                }
+                callback.report(Event::download(DownloadState::ExistsUnchecked, url.clone()));
+                return Ok(DownloadState::ExistsUnchecked);
+            }
+        }
+
+        let sha = match &sha1 {
+            Some(value) => value,
+            // without a journal there is no telling if the file is complete, so trust it as before
+            None if journal.is_none() => {
+                report_existing_file(&url, &output, callback);
+                callback.report(Event::download(DownloadState::ExistsUnchecked, url.clone()));
+                return Ok(DownloadState::ExistsUnchecked);
+            }
+            // files are only moved into place once complete, so one without a sha1 is
+            // trusted unless a newer download of it was left unfinished
+            None if !get_part_path(&output).exists() => {
+                if let Some(journal) = &journal {
+                    journal.record_file(&output, None);
+                }
+                report_existing_file(&url, &output, callback);
+                callback.report(Event::download(DownloadState::ExistsUnchecked, url.clone()));
+                return Ok(DownloadState::ExistsUnchecked);
+            }
+            None => "",
+        };
+
+        // the sha1 of compressed files is of the compressed data, so they can't be checked
+        if !compressed && !sha.is_empty() {
+            if let Ok(value) = get_sha1(output.clone()).await {
+                if value == sha {
+                    if let Some(journal) = &journal {
+                        journal.record_file(&output, sha1.clone());
+                    }
+                    report_existing_file(&url, &output, callback);
+                    callback.report(Event::download(DownloadState::Exists, url.clone()));
+                    return Ok(DownloadState::Exists);
+                }
+            }
         }
 
         if let Err(error) = remove_file(output.clone()).await {
@@ -230,125 +591,64 @@ pub async fn download_file(
     }
 
     if !url.starts_with("http") {
-        callback(Event::Error("Url is invaild".into()));
+        callback.report(Event::Error("Url is invaild".into()));
         return Err(LauncherLibError::General(
             "DOWNLOAD FILE | Invaild url".into(),
         ));
     }
 
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err),
-    };
+    let part = get_part_path(&output);
 
-    match client.get(&url).send().await {
-        Ok(response) => match response.bytes().await {
-            Ok(value) => {
-                let mut file = match File::create(output.clone()).await {
-                    Ok(value) => value,
-                    Err(error) => {
-                        return Err(LauncherLibError::OS {
-                            source: error,
-                            msg: "Failed to create file".into(),
-                        })
-                    }
-                };
-                if compressed {
-                    let mut buf = Cursor::new(value);
+    if let Err(err) = with_retry(&url, |candidate| {
+        fetch_part_file(candidate, &part, sha1.clone(), &url, callback, cancel)
+    })
+    .await
+    {
+        // a cancelled download is not resumed, so don't leave the partial file behind
+        if let LauncherLibError::Cancelled = err {
+            let _ = remove_file(&part).await;
+        }
+        return Err(err);
+    }
 
-                    let mut sync_file: std::fs::File = file.into_std().await;
+    finalize_part_file(&part, &output, compressed).await?;
 
-                    if let Err(error) = lzma_rs::lzma_decompress(&mut buf, &mut sync_file) {
-                        callback(Event::Error("Failed to decompress file".into()));
-                        return Err(LauncherLibError::General(error.to_string()));
-                    }
-                } else {
-                    let mut content = Cursor::new(value);
-                    if let Err(err) = copy(&mut content, &mut file).await {
-                        return Err(LauncherLibError::OS {
-                            source: err,
-                            msg: "Failed to copy contents to file".into(),
-                        });
-                    }
-                }
+    if let Some(journal) = &journal {
+        journal.record_file(&output, sha1.clone());
+    }
 
-                if let Some(sha) = sha1 {
-                    match get_sha1(output.clone()).await {
-                        Ok(value) => {
-                            if sha == value {
-                                callback(Event::download(
-                                    DownloadState::DownloadChecked,
-                                    url.clone(),
-                                ));
-                                return Ok(DownloadState::DownloadChecked);
-                            }
-                            callback(Event::Error(format!("Sha1 Failed | {}", url.clone())));
-                        }
-                        Err(error) => {
-                            callback(Event::Error(error.to_string()));
-                            return Err(error);
-                        }
-                    }
-                }
-                callback(Event::download(DownloadState::Download, url.clone()));
-                Ok(DownloadState::Download)
-            }
-            Err(err) => Err(LauncherLibError::HTTP {
-                msg: format!("Failed to download file | {}", url.clone()).into(),
-                source: err,
-            }),
-        },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make http request".into(),
-        }),
+    if sha1.is_some() {
+        callback.report(Event::download(DownloadState::DownloadChecked, url.clone()));
+        return Ok(DownloadState::DownloadChecked);
     }
+
+    callback.report(Event::download(DownloadState::Download, url.clone()));
+    Ok(DownloadState::Download)
 }
 
 pub async fn download_file_to_string(
     url: String,
     output: &mut String,
-    callback: Callback,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
 ) -> LibResult<DownloadState> {
+    cancel.check()?;
+
     if !url.starts_with("http") {
-        callback(Event::Error("Url is invaild".into()));
+        callback.report(Event::Error("Url is invaild".into()));
         return Err(LauncherLibError::General(
             "DOWNLOAD FILE | Invaild url".into(),
         ));
     }
 
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err),
-    };
-
-    match client.get(&url).send().await {
-        Ok(response) => match response.bytes().await {
-            Ok(value) => {
-                let mut downloaded_bytes = vec![];
-                let mut content = Cursor::new(value);
-                if let Err(err) = copy(&mut content, &mut downloaded_bytes).await {
-                    return Err(LauncherLibError::OS {
-                        source: err,
-                        msg: "Failed to copy contents to file".into(),
-                    });
-                }
-                *output = std::str::from_utf8(downloaded_bytes.as_slice())
-                    .unwrap()
-                    .to_string();
+    match get_text(&url).await {
+        Ok(value) => {
+            *output = value;
 
-                callback(Event::download(DownloadState::Download, url.clone()));
-                Ok(DownloadState::Download)
-            }
-            Err(err) => Err(LauncherLibError::HTTP {
-                msg: format!("Failed to download file | {}", url.clone()).into(),
-                source: err,
-            }),
-        },
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to make http request".into(),
-        }),
+            callback.report(Event::download(DownloadState::Download, url.clone()));
+            Ok(DownloadState::Download)
+        }
+        Err(err) => Err(err),
     }
 }
 
@@ -397,71 +697,135 @@ pub async fn download_file_to_string(
     }
 }*/
 
-/// Parse a single rule from versions.json in .minecraft
+/// Whether an `arch` value from a rule names the rust target `arch`
+pub fn is_arch(value: &str, arch: &str) -> bool {
+    match value {
+        "x86" | "i386" | "i686" => arch == "x86",
+        "x86_64" | "amd64" | "x64" => arch == "x86_64",
+        "arm64" | "aarch64" => arch == "aarch64",
+        "arm" | "arm32" => arch == "arm",
+        _ => value == arch,
+    }
+}
+
+/// Whether a single rule from versions.json in .minecraft allows, on its own, on this machine
 pub fn parse_single_rule(rule: &Rule, options: &GameOptions) -> bool {
-    let result = match rule.action.as_str() {
-        "disallow" => true,
-        _ => false,
-    };
+    is_rule_match(rule, &RuleEnvironment::from_options(options)) == (rule.action == "allow")
+}
 
-    if let Some(os) = &rule.os {
-        for (key, value) in os.iter() {
-            match key.as_str() {
-                "name" => {
-                    if value.as_str() == "windows" && consts::OS != "windows" {
-                        return result;
-                    } else if value.as_str() == "osx" && consts::OS != "macos" {
-                        return result;
-                    } else if value.as_str() == "linux" && consts::OS != "linux" {
-                        return result;
-                    }
-                }
-                "arch" => {
-                    if value == "x86" && consts::ARCH != "x86" {
-                        return result;
-                    }
-                }
-                "version" => {
-                    let r = regex::Regex::new(value.as_str()).expect("Failed to create regex");
-                    if !r.is_match(get_os_version().as_str()) {
-                        return result;
-                    }
-                }
-                _ => {}
-            }
-        }
-    }
+/// Whether the rules allow on this machine with the features the options ask for
+pub fn parse_rule_list(data: &Vec<Rule>, options: &GameOptions) -> bool {
+    is_allowed(data, &RuleEnvironment::from_options(options))
+}
 
-    if let Some(features) = &rule.features {
-        for (key, _) in features.iter() {
-            if key == "has_custom_resolution" && !options.custom_resolution.is_some() {
-                return result;
-            }
-            if key == "is_demo_user" && !options.demo {
-                return result;
+/// The version json at `version_json` followed by every version it inherits from,
+/// nearest first. Fails when the chain leads back to a version already in it.
+pub async fn read_manifest_chain(
+    version_json: PathBuf,
+    mc_dir: &Path,
+) -> LibResult<Vec<VersionManifest>> {
+    let mut current = match read_manifest(version_json).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+    let mut seen = vec![current.id.clone()];
+    let mut chain = vec![];
+    loop {
+        let parent = match current.inherits_from.clone() {
+            Some(value) => value,
+            None => {
+                chain.push(current);
+                return Ok(chain);
             }
+        };
+        chain.push(current);
+
+        if seen.contains(&parent) {
+            return Err(LauncherLibError::General(format!(
+                "inheritsFrom cycle: {} -> {}",
+                seen.join(" -> "),
+                parent
+            )));
         }
+        seen.push(parent.clone());
+
+        let version_path = mc_dir
+            .join("versions")
+            .join(parent.clone())
+            .join(format!("{}.json", parent));
+        current = match read_manifest(version_path).await {
+            Ok(value) => value,
+            Err(err) => return Err(err),
+        };
     }
+}
 
-    !result
+/// `group:artifact` of a library, with the classifier when it has one,
+/// so different versions of the same library share a key
+fn get_library_key(name: &str) -> String {
+    let parts: Vec<&str> = name.split(':').collect();
+    match parts.as_slice() {
+        [group, artifact, _, classifier, ..] => format!(
+            "{}:{}:{}",
+            group,
+            artifact,
+            classifier.split('@').next().unwrap_or_default()
+        ),
+        [group, artifact, ..] => format!("{}:{}", group, artifact),
+        _ => name.to_string(),
+    }
 }
 
-// Parse rule list
-pub fn parse_rule_list(data: &Vec<Rule>, options: &GameOptions) -> bool {
-    for i in data.iter() {
-        if !parse_single_rule(i, options) {
-            return false;
+/// Lays `child` over `parent` the way the launcher does: the child's fields win, its
+/// arguments come after the parent's and its libraries replace the parent's with the
+/// same `group:artifact[:classifier]`.
+pub fn merge_manifest(child: VersionManifest, parent: VersionManifest) -> VersionManifest {
+    let keys: std::collections::HashSet<String> = child
+        .libraries
+        .iter()
+        .map(|library| get_library_key(&library.name))
+        .collect();
+
+    let mut libraries = child.libraries;
+    for library in parent.libraries {
+        if !keys.contains(&get_library_key(&library.name)) {
+            libraries.push(library);
         }
     }
-    true
+
+    let mut arguments = parent.arguments;
+    arguments.game.extend(child.arguments.game);
+    arguments.jvm.extend(child.arguments.jvm);
+
+    VersionManifest {
+        _comment_: child._comment_.or(parent._comment_),
+        inherits_from: None,
+        time: child.time,
+        release_time: child.release_time,
+        id: child.id,
+        release_type: child.release_type.or(parent.release_type),
+        main_class: child.main_class,
+        arguments,
+        minecraft_arguments: child.minecraft_arguments.or(parent.minecraft_arguments),
+        libraries,
+        jar: child.jar.or(parent.jar),
+        asset_index: child.asset_index.or(parent.asset_index),
+        assets: child.assets.or(parent.assets),
+        java_version: child.java_version.or(parent.java_version),
+        downloads: child.downloads.or(parent.downloads),
+        compliance_level: child.compliance_level.or(parent.compliance_level),
+        logging: child.logging.or(parent.logging),
+        minimum_launcher_version: child
+            .minimum_launcher_version
+            .or(parent.minimum_launcher_version),
+    }
 }
 
-/// Implement the inheritsFrom function
+/// Implement the inheritsFrom function, following the whole chain of parents
 /// See <https://github.com/tomsik68/mclauncher-api/wiki/Version-Inheritance-&-Forge>
-/// This function my be unneed
 pub async fn inherit_json(
     original_data: &VersionManifest,
-    path: &PathBuf,
+    path: &Path,
 ) -> LibResult<VersionManifest> {
     let inherit_version = if let Some(value) = original_data.inherits_from.clone() {
         value
@@ -471,173 +835,189 @@ pub async fn inherit_json(
         ));
     };
 
-    let mut new_data = VersionManifest {
-        _comment_: None,
-        inherits_from: None,
-        time: original_data.time.clone(),
-        release_time: original_data.release_time.clone(),
-        id: original_data.id.clone(),
-        release_type: original_data.release_type.clone(),
-        main_class: original_data.main_class.clone(),
-        arguments: original_data.arguments.clone(),
-        libraries: original_data.libraries.clone(),
-        jar: original_data.jar.clone(),
-        asset_index: None,
-        assets: None,
-        java_version: None,
-        downloads: None,
-        compliance_level: None,
-        logging: None,
-        minimum_launcher_version: None,
-    };
-
     let version_path = path
         .join("versions")
         .join(inherit_version.clone())
         .join(format!("{}.json", inherit_version));
-    let inherit_data: VersionManifest = match read_manifest(version_path).await {
+    let mut chain = match read_manifest_chain(version_path, path).await {
         Ok(value) => value,
         Err(err) => return Err(err),
     };
-
-    new_data.asset_index = inherit_data.asset_index;
-    new_data.assets = inherit_data.assets;
-    new_data.java_version = inherit_data.java_version;
-    new_data.downloads = inherit_data.downloads;
-    new_data.compliance_level = inherit_data.compliance_level;
-    new_data.logging = inherit_data.logging;
-    new_data.minimum_launcher_version = inherit_data.minimum_launcher_version;
-
-    for lib in inherit_data.libraries.iter() {
-        new_data.libraries.push(lib.to_owned());
+    if chain.iter().any(|version| version.id == original_data.id) {
+        return Err(LauncherLibError::General(format!(
+            "inheritsFrom cycle: {} inherits from itself",
+            original_data.id
+        )));
     }
 
-    for arg in inherit_data.arguments.game {
-        new_data.arguments.game.push(arg.to_owned());
-    }
-
-    for arg in inherit_data.arguments.jvm {
-        new_data.arguments.jvm.push(arg.to_owned());
+    let mut merged = match chain.pop() {
+        Some(value) => value,
+        None => return Err(LauncherLibError::NotFound(inherit_version)),
+    };
+    while let Some(child) = chain.pop() {
+        merged = merge_manifest(child, merged);
     }
-
-    Ok(new_data)
+    Ok(merge_manifest(original_data.clone(), merged))
 }
 
 pub async fn read_manifest_inherit(
     version_json: PathBuf,
-    mc_dir: &PathBuf,
+    mc_dir: &Path,
 ) -> LibResult<VersionManifest> {
-    match read_manifest(version_json).await {
-        Ok(value) => {
-            if value.inherits_from.is_some() {
-                match inherit_json(&value, &mc_dir).await {
-                    Ok(inherited) => Ok(inherited),
-                    Err(err) => return Err(err),
-                }
-            } else {
-                Ok(value)
-            }
-        }
+    let mut chain = match read_manifest_chain(version_json, mc_dir).await {
+        Ok(value) => value,
         Err(err) => return Err(err),
+    };
+    let mut merged = match chain.pop() {
+        Some(value) => value,
+        None => return Err(LauncherLibError::General("Empty version chain".into())),
+    };
+    while let Some(child) = chain.pop() {
+        merged = merge_manifest(child, merged);
     }
+    Ok(merged)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{version_json, write_version};
+
+    #[test]
+    fn test_is_arch() {
+        assert!(is_arch("x86", "x86"));
+        assert!(!is_arch("x86", "aarch64"));
+        assert!(is_arch("arm64", "aarch64"));
+        assert!(is_arch("x86_64", "x86_64"));
+        assert!(!is_arch("arm64", "x86_64"));
+    }
 
     #[tokio::test]
     async fn test_download_file() {
-        let out =
-            PathBuf::from("C:\\projects\\mc-installer-v2\\src-tauri\\mc-laucher-lib-rs\\tests");
-        let file_compressed: String =
-            "https://launcher.mojang.com/v1/objects/962508e35b9c56e3c89ea4620672cecbeba47109/java"
+        use crate::context::{fixture_context, with_context};
+
+        let out = std::env::temp_dir().join(format!("mc-download-{}", uuid::Uuid::new_v4()));
+        let url_compressed: String =
+            "https://piston-data.mojang.com/v1/objects/7b605fc22afe5e5f6442d5152246d3523b147aab/java"
                 .into();
-        let url_uncomcompressed: String =
-            "https://launcher.mojang.com/v1/objects/1654ec74efdc41582e5f954c10681cbde5a22996/java"
+        let url_uncompressed: String =
+            "https://piston-data.mojang.com/v1/objects/32a119dec14dd1cc8633a5d1b069c680b6dd6755/java"
                 .into();
-        let sha1_compressed: String = "962508e35b9c56e3c89ea4620672cecbeba47109".into();
-        let sha1_uncompressed: String = "1654ec74efdc41582e5f954c10681cbde5a22996".into();
-
-        // compressed
-        match download_file(
-            file_compressed.clone(),
-            out.join("java_c.exe"),
-            |event| {
-                println!("Compressed: {:#?}", event);
-            },
-            None,
-            true,
-        )
-        .await
-        {
-            Ok(value) => {
-                println!("{:#?}", value);
-            }
-            Err(err) => {
-                eprintln!("{:#?}", err);
-            }
-        }
+        let sha1_compressed: String = "7b605fc22afe5e5f6442d5152246d3523b147aab".into();
+        let sha1_uncompressed: String = "32a119dec14dd1cc8633a5d1b069c680b6dd6755".into();
 
-        // compressed with sha1
-        match download_file(
-            file_compressed,
-            out.join("java_cs.exe"),
-            |event| {
-                println!("Compressed: {:#?}", event);
-            },
-            Some(sha1_compressed),
-            true,
-        )
-        .await
-        {
-            Ok(value) => {
-                println!("{:#?}", value);
+        let cases = [
+            ("java_c", url_compressed.clone(), None, true),
+            (
+                "java_cs",
+                url_compressed.clone(),
+                Some(sha1_compressed),
+                true,
+            ),
+            ("java_u", url_uncompressed.clone(), None, false),
+            (
+                "java_us",
+                url_uncompressed.clone(),
+                Some(sha1_uncompressed),
+                false,
+            ),
+        ];
+        with_context(fixture_context("runtime"), async {
+            for (name, url, sha1, compressed) in cases {
+                download_file(
+                    url,
+                    out.join(name),
+                    &|_: Event| {},
+                    &CancellationToken::new(),
+                    sha1,
+                    compressed,
+                )
+                .await
+                .unwrap();
+                let contents = std::fs::read_to_string(out.join(name)).unwrap();
+                assert!(contents.contains("openjdk version \"17.0.3\""), "{}", name);
             }
-            Err(err) => {
-                eprintln!("{:#?}", err);
-            }
-        }
 
-        // no compress
-        match download_file(
-            url_uncomcompressed.clone(),
-            out.join("java_u.exe"),
-            |event| {
-                println!("Compressed {:#?}", event);
-            },
-            None,
-            false,
-        )
-        .await
-        {
-            Ok(value) => {
-                println!("{:#?}", value);
-            }
-            Err(err) => {
-                eprintln!("{:#?}", err);
-            }
-        }
+            let result = download_file(
+                url_uncompressed,
+                out.join("java_bad"),
+                &|_: Event| {},
+                &CancellationToken::new(),
+                Some("0000000000000000000000000000000000000000".into()),
+                false,
+            )
+            .await;
+            assert!(matches!(result, Err(LauncherLibError::HashMismatch { .. })));
+            assert!(!out.join("java_bad").exists());
+        })
+        .await;
 
-        // no compress with sha1
-        match download_file(
-            url_uncomcompressed,
-            out.join("java_us.exe"),
-            |event| {
-                println!("SHA1 {:#?}", event);
-            },
-            Some(sha1_uncompressed),
-            false,
-        )
-        .await
-        {
-            Ok(value) => {
-                println!("{:#?}", value);
-            }
-            Err(err) => {
-                eprintln!("{:#?}", err);
-            }
-        }
+        let _ = std::fs::remove_dir_all(out);
+    }
+
+    #[tokio::test]
+    async fn test_download_file_without_sha1_with_journal() {
+        use crate::context::{fixture_context, with_context};
+        use crate::journal::open_journal;
+
+        let mc_dir = std::env::temp_dir().join(format!("mc-download-{}", uuid::Uuid::new_v4()));
+        let url: String =
+            "https://piston-data.mojang.com/v1/objects/32a119dec14dd1cc8633a5d1b069c680b6dd6755/java"
+                .into();
+        let output = mc_dir.join("runtime").join("java");
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, "installed before the journal").unwrap();
+        let journal = open_journal(&mc_dir);
+
+        with_context(fixture_context("runtime"), async {
+            let state = download_file(
+                url.clone(),
+                output.clone(),
+                &|_: Event| {},
+                &CancellationToken::new(),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+            assert!(matches!(state, DownloadState::ExistsUnchecked));
+            assert!(journal.is_file_verified(&output, None));
+
+            // an unfinished download of a newer copy makes the file suspect
+            std::fs::write(get_part_path(&output), "").unwrap();
+            std::fs::write(&output, "left by an interrupted install").unwrap();
+            let state = download_file(
+                url.clone(),
+                output.clone(),
+                &|_: Event| {},
+                &CancellationToken::new(),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+            assert!(matches!(state, DownloadState::Download));
+        })
+        .await;
+        let contents = std::fs::read_to_string(&output).unwrap();
+        assert!(contents.contains("openjdk version \"17.0.3\""));
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[test]
+    fn test_get_part_path() {
+        let out = PathBuf::from("runtime").join("bin").join("java");
+        assert_eq!(
+            get_part_path(&out),
+            PathBuf::from("runtime").join("bin").join("java.part")
+        );
+
+        let out = PathBuf::from("libraries").join("lwjgl-3.2.2.jar");
+        assert_eq!(
+            get_part_path(&out),
+            PathBuf::from("libraries").join("lwjgl-3.2.2.jar.part")
+        );
     }
 
     #[test]
@@ -653,15 +1033,26 @@ mod tests {
     }
     #[tokio::test]
     async fn test_get_local_installed_versions() {
-        let path = get_minecraft_directory().unwrap();
-        match get_local_installed_versions(path).await {
-            Ok(value) => {
-                println!("{:#?}", value[0]);
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-            }
-        }
+        let mc_dir = std::env::temp_dir().join(format!("mc-installed-{}", uuid::Uuid::new_v4()));
+        assert!(get_local_installed_versions(mc_dir.clone()).await.is_err());
+
+        write_version(
+            &mc_dir,
+            serde_json::json!({
+                "id": "1.18.1",
+                "libraries": [],
+                "mainClass": "net.minecraft.client.main.Main",
+                "releaseTime": "2021-12-10T08:23:00+00:00",
+                "time": "2021-12-10T08:23:00+00:00"
+            }),
+        );
+        std::fs::create_dir_all(mc_dir.join("versions").join("no-json")).unwrap();
+
+        let versions = get_local_installed_versions(mc_dir.clone()).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].id, "1.18.1");
+
+        let _ = std::fs::remove_dir_all(mc_dir);
     }
     #[test]
     fn test_get_minecraft_directory() {
@@ -674,4 +1065,87 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_read_manifest_inherit_recursive() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-inherit-{}", uuid::Uuid::new_v4()));
+        let version = |id: &str, parent: &str, main_class: &str, libraries: &[&str], game: &str| {
+            let mut json = version_json(id, main_class, libraries, Some(parent));
+            json["arguments"]["game"] = serde_json::json!([game]);
+            json
+        };
+        write_version(
+            &mc_dir,
+            serde_json::from_str(
+                &std::fs::read_to_string("tests/fixtures/install/fixture-1.0.json").unwrap(),
+            )
+            .unwrap(),
+        );
+        write_version(
+            &mc_dir,
+            version(
+                "fixture-forge",
+                "fixture-1.0",
+                "cpw.mods.modlauncher.Launcher",
+                &["org.example:demo:2.0", "org.lwjgl:lwjgl:3.3:natives-linux"],
+                "--launchTarget",
+            ),
+        );
+        write_version(
+            &mc_dir,
+            version(
+                "fixture-optifine",
+                "fixture-forge",
+                "optifine.Main",
+                &["optifine:OptiFine:1.0", "org.lwjgl:lwjgl:3.3"],
+                "--tweakClass",
+            ),
+        );
+
+        let json = mc_dir
+            .join("versions")
+            .join("fixture-optifine")
+            .join("fixture-optifine.json");
+        let manifest = read_manifest_inherit(json, &mc_dir).await.unwrap();
+        assert_eq!(manifest.id, "fixture-optifine");
+        assert_eq!(manifest.main_class, "optifine.Main");
+        assert!(manifest.inherits_from.is_none());
+        assert_eq!(manifest.assets.as_deref(), Some("fixture"));
+        // the child's demo 2.0 replaces the root's 1.0, the natives have their own key
+        let libraries: Vec<&str> = manifest
+            .libraries
+            .iter()
+            .map(|library| library.name.as_str())
+            .collect();
+        assert_eq!(
+            libraries,
+            vec![
+                "optifine:OptiFine:1.0",
+                "org.lwjgl:lwjgl:3.3",
+                "org.example:demo:2.0",
+                "org.lwjgl:lwjgl:3.3:natives-linux"
+            ]
+        );
+        let game: Vec<String> = manifest
+            .arguments
+            .game
+            .iter()
+            .map(|argument| format!("{:?}", argument))
+            .collect();
+        assert_eq!(game.len(), 4);
+        assert!(game[0].contains("--username"));
+        assert!(game[2].contains("--launchTarget"));
+        assert!(game[3].contains("--tweakClass"));
+
+        // a chain that loops back is refused
+        write_version(&mc_dir, version("loop-a", "loop-b", "Main", &[], "a"));
+        write_version(&mc_dir, version("loop-b", "loop-a", "Main", &[], "b"));
+        let json = mc_dir.join("versions").join("loop-a").join("loop-a.json");
+        match read_manifest_inherit(json, &mc_dir).await {
+            Err(LauncherLibError::General(msg)) => assert!(msg.contains("cycle"), "{}", msg),
+            other => panic!("{:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
 }
@@ -1,5 +1,6 @@
-use crate::utils::get_http_client;
-use crate::expections::{ LauncherLibError, LibResult };
+use crate::context::get_context;
+use crate::utils::get_json;
+use crate::expections::LibResult;
 use crate::json::{
     launcher_version::{
         VersionsManifestLatest, 
@@ -8,77 +9,44 @@ use crate::json::{
     }
 };
 
-const MINECRAFT_MANIFEST: &str = "https://launchermeta.mojang.com/mc/game/version_manifest.json";
-
 /// Returns the latest version of Minecraft
 pub async fn get_latest_vanilla_version() -> LibResult<VersionsManifestLatest> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get(MINECRAFT_MANIFEST).send().await {
-        Ok(value) => {
-            match value.json::<VersionsManifest>().await {
-                Ok(request) => {
-                    Ok(request.latest)
-                }
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))   
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-            msg: "Failed to fetch Minecraft latest version".into()
-        })
+    match get_json::<VersionsManifest>(&get_context().endpoints.minecraft_manifest).await {
+        Ok(request) => Ok(request.latest),
+        Err(err) => Err(err)
     }
 }
 
 /// Returns all versions that Mojang offers to download
 pub async fn get_vanilla_versions() -> LibResult<Vec<VersionsManifestVersion>> {
-    let client = match get_http_client().await {
-        Ok(value) => value,
-        Err(err) => return Err(err)
-    };
-
-    match client.get(MINECRAFT_MANIFEST).send().await {
-        Ok(value) => {
-            match value.json::<VersionsManifest>().await {
-                Ok(request) => {
-                    Ok(request.versions)
-                }
-                Err(err) => Err(LauncherLibError::PraseJsonReqwest(err))       
-            }
-        }
-        Err(err) => Err(LauncherLibError::HTTP {
-            source: err,
-             msg: "Failed to fetch Minecraft latest version".into()
-        })
+    match get_json::<VersionsManifest>(&get_context().endpoints.minecraft_manifest).await {
+        Ok(request) => Ok(request.versions),
+        Err(err) => Err(err)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::{ fixture_context, with_context };
+
     #[tokio::test]
     async fn test_get_latest_vanilla_version() {
-            match get_latest_vanilla_version().await {
-                Ok(value) => {
-                    println!("{:#?}",value);
-                }
-                Err(err) => {
-                    eprintln!("{}",err);
-                }
-            }
+        with_context(fixture_context("install"), async {
+            let latest = get_latest_vanilla_version().await.unwrap();
+            assert_eq!(latest.release,"fixture-1.0");
+            assert_eq!(latest.snapshot,"fixture-1.0");
+        }).await;
     }
+
     #[tokio::test]
     async fn test_get_vanilla_versions() {
-            match get_vanilla_versions().await {
-                Ok(value) => {
-                    println!("{:#?}",value);
-                }
-                Err(err) => {
-                    eprintln!("{}",err);
-                }
-            }
+        with_context(fixture_context("install"), async {
+            let versions = get_vanilla_versions().await.unwrap();
+            assert_eq!(versions.len(),1);
+            assert_eq!(versions[0].id,"fixture-1.0");
+            assert_eq!(versions[0].version_type,"release");
+            assert!(versions[0].url.ends_with("/fixture-1.0.json"));
+        }).await;
     }
 }
\ No newline at end of file
@@ -0,0 +1,478 @@
+use crate::cancel::CancellationToken;
+use crate::context::get_context;
+use crate::expections::{LauncherLibError, LibResult};
+use crate::install::{get_library_files, IndexAssetsMap};
+use crate::journal::open_journal;
+use crate::json::{
+    game_settings::GameOptions,
+    install::{Callback, DownloadableFile, Event},
+};
+use crate::natives::get_natives;
+use crate::runtime::get_runtime_files;
+use crate::utils::{
+    download_file, get_sha1, parse_rule_list, read_manifest_chain, read_manifest_inherit,
+};
+use std::path::{Path, PathBuf};
+use tokio::fs::{metadata, read_to_string, remove_file};
+
+/// What part of an installation a file belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Library,
+    Native,
+    Client,
+    LogConfig,
+    AssetIndex,
+    Asset,
+    Runtime,
+}
+impl FileKind {
+    /// The install journal step that puts the file in place
+    fn get_step(&self) -> &'static str {
+        match self {
+            FileKind::Library | FileKind::Native => "libraries",
+            FileKind::Client | FileKind::LogConfig => "client",
+            FileKind::AssetIndex | FileKind::Asset => "assets",
+            FileKind::Runtime => "runtime",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileProblem {
+    Missing,
+    SizeMismatch { expected: u64, actual: u64 },
+    HashMismatch { expected: String, actual: String },
+}
+
+/// A file that does not match the manifest
+#[derive(Debug, Clone)]
+pub struct VerifyIssue {
+    pub kind: FileKind,
+    pub path: PathBuf,
+    pub url: String,
+    pub problem: FileProblem,
+    /// whether it was downloaded again and now matches
+    pub repaired: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// number of files that were checked
+    pub checked: usize,
+    pub issues: Vec<VerifyIssue>,
+}
+impl VerifyReport {
+    /// Whether every file matches, counting repaired ones
+    pub fn is_ok(&self) -> bool {
+        self.issues.iter().all(|issue| issue.repaired)
+    }
+}
+
+struct ExpectedFile {
+    kind: FileKind,
+    path: PathBuf,
+    url: String,
+    sha1: String,
+    size: u64,
+}
+impl ExpectedFile {
+    fn new(kind: FileKind, path: PathBuf, file: &DownloadableFile) -> Self {
+        Self {
+            kind,
+            path,
+            url: file.url.clone(),
+            sha1: file.sha1.clone(),
+            size: file.size as u64,
+        }
+    }
+}
+
+async fn check_file(file: &ExpectedFile) -> LibResult<Option<FileProblem>> {
+    let actual = match metadata(&file.path).await {
+        Ok(value) if value.is_file() => value.len(),
+        _ => return Ok(Some(FileProblem::Missing)),
+    };
+    if actual != file.size {
+        return Ok(Some(FileProblem::SizeMismatch {
+            expected: file.size,
+            actual,
+        }));
+    }
+    let sha1 = match get_sha1(file.path.clone()).await {
+        Ok(value) => value,
+        Err(err) => return Err(err),
+    };
+    if sha1 != file.sha1 {
+        return Ok(Some(FileProblem::HashMismatch {
+            expected: file.sha1.clone(),
+            actual: sha1,
+        }));
+    }
+    Ok(None)
+}
+
+/// Checks the files and downloads the ones that don't match again when `repair` is set
+async fn verify_files(
+    files: Vec<ExpectedFile>,
+    mc_dir: &Path,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
+    repair: bool,
+    report: &mut VerifyReport,
+) -> LibResult<()> {
+    let journal = open_journal(mc_dir);
+    let max = files.len();
+    for (count, file) in files.into_iter().enumerate() {
+        cancel.check()?;
+        let problem = check_file(&file).await?;
+        report.checked += 1;
+        callback.report(Event::progress(count + 1, max));
+
+        let problem = match problem {
+            Some(value) => value,
+            None => {
+                journal.record_file(&file.path, Some(file.sha1.clone()));
+                continue;
+            }
+        };
+        callback.report(Event::Status(format!(
+            "{} is {:?}",
+            file.path.display(),
+            problem
+        )));
+        journal.forget_file(&file.path);
+
+        let mut repaired = false;
+        if repair {
+            if problem != FileProblem::Missing {
+                if let Err(err) = remove_file(&file.path).await {
+                    return Err(LauncherLibError::OS {
+                        source: err,
+                        msg: "Failed to remove file".into(),
+                    });
+                }
+            }
+            download_file(
+                file.url.clone(),
+                file.path.clone(),
+                callback,
+                cancel,
+                Some(file.sha1.clone()),
+                false,
+            )
+            .await?;
+            repaired = true;
+        }
+
+        report.issues.push(VerifyIssue {
+            kind: file.kind,
+            path: file.path,
+            url: file.url,
+            problem,
+            repaired,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the libraries, natives, client jar, log config, assets and java runtime of an
+/// installed version against the sha1 and size in its manifest. With `repair` the missing
+/// and corrupt files are downloaded again, otherwise the install steps they belong to are
+/// marked as unfinished so the next install puts them back.
+pub async fn verify_version(
+    version_id: &str,
+    mc_dir: PathBuf,
+    callback: Callback<'_>,
+    cancel: &CancellationToken,
+    repair: bool,
+) -> LibResult<VerifyReport> {
+    let version_json = mc_dir
+        .join("versions")
+        .join(version_id)
+        .join(format!("{}.json", version_id));
+    if !version_json.is_file() {
+        return Err(LauncherLibError::NotFound(version_id.to_string()));
+    }
+
+    // every version of the chain records its own install steps for the files it shares
+    let chain = read_manifest_chain(version_json.clone(), &mc_dir).await?;
+    let manifest = read_manifest_inherit(version_json, &mc_dir).await?;
+    // the jar the game is launched with, as the launch command picks it
+    let jar = manifest.jar.clone().unwrap_or_else(|| manifest.id.clone());
+
+    let mut report = VerifyReport::default();
+    let mut files = vec![];
+
+    callback.report(Event::Status("Verifying libraries".into()));
+    for library in &manifest.libraries {
+        if let Some(rules) = &library.rules {
+            if !parse_rule_list(rules, &GameOptions::default()) {
+                continue;
+            }
+        }
+        // libraries from a maven repo have no sha1 to check against
+        let downloads = match &library.downloads {
+            Some(value) => value,
+            None => continue,
+        };
+        let (parts, jar_filename, jar_filename_native) = match get_library_files(library) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let mut path = mc_dir.join("libraries");
+        for part in parts {
+            path = path.join(part);
+        }
+
+        files.push(ExpectedFile::new(
+            FileKind::Library,
+            path.join(jar_filename),
+            &downloads.artifact,
+        ));
+        if let Some(classifiers) = &downloads.classifiers {
+            if let Some(native) = classifiers.get(&get_natives(library)) {
+                files.push(ExpectedFile::new(
+                    FileKind::Native,
+                    path.join(jar_filename_native),
+                    native,
+                ));
+            }
+        }
+    }
+
+    if let Some(downloads) = &manifest.downloads {
+        if let Some(client) = downloads.get("client") {
+            files.push(ExpectedFile::new(
+                FileKind::Client,
+                mc_dir
+                    .join("versions")
+                    .join(&jar)
+                    .join(format!("{}.jar", jar)),
+                client,
+            ));
+        }
+    }
+
+    if let Some(logging) = &manifest.logging {
+        if let Some(client) = logging.get("client") {
+            if let Some(id) = &client.file.id {
+                files.push(ExpectedFile::new(
+                    FileKind::LogConfig,
+                    mc_dir.join("assets").join("log_configs").join(id),
+                    &client.file,
+                ));
+            }
+        }
+    }
+
+    let index_path = manifest.assets.as_ref().map(|assets| {
+        mc_dir
+            .join("assets")
+            .join("indexes")
+            .join(format!("{}.json", assets))
+    });
+    if let (Some(asset_index), Some(index_path)) = (&manifest.asset_index, &index_path) {
+        files.push(ExpectedFile::new(
+            FileKind::AssetIndex,
+            index_path.clone(),
+            asset_index,
+        ));
+    }
+
+    verify_files(files, &mc_dir, callback, cancel, repair, &mut report).await?;
+
+    // the objects can only be listed once the index is known to be right
+    let index_ok = !report
+        .issues
+        .iter()
+        .any(|issue| issue.kind == FileKind::AssetIndex && !issue.repaired);
+    if let (true, Some(index_path)) = (index_ok, &index_path) {
+        callback.report(Event::Status("Verifying assets".into()));
+        let index: IndexAssetsMap = match read_to_string(index_path).await {
+            Ok(raw) => match serde_json::from_str(&raw) {
+                Ok(value) => value,
+                Err(err) => return Err(LauncherLibError::ParseJsonSerde(err)),
+            },
+            Err(err) => {
+                return Err(LauncherLibError::OS {
+                    source: err,
+                    msg: "Failed to read file".into(),
+                })
+            }
+        };
+        let resources = get_context().endpoints.resources.clone();
+        let mut files = vec![];
+        for object in index.objects.into_values() {
+            let prefix = match object.hash.get(0..2) {
+                Some(value) => value.to_string(),
+                None => continue,
+            };
+            files.push(ExpectedFile {
+                kind: FileKind::Asset,
+                path: mc_dir
+                    .join("assets")
+                    .join("objects")
+                    .join(&prefix)
+                    .join(&object.hash),
+                url: format!("{}{}/{}", resources, prefix, object.hash),
+                sha1: object.hash,
+                size: object.size,
+            });
+        }
+        verify_files(files, &mc_dir, callback, cancel, repair, &mut report).await?;
+    }
+
+    if let Some(java) = &manifest.java_version {
+        callback.report(Event::Status("Verifying java runtime".into()));
+        let files = match get_runtime_files(java.component.clone(), mc_dir.clone()).await {
+            Ok(value) => value
+                .into_iter()
+                .map(|(path, file)| ExpectedFile::new(FileKind::Runtime, path, &file))
+                .collect(),
+            Err(err) => return Err(err),
+        };
+        verify_files(files, &mc_dir, callback, cancel, repair, &mut report).await?;
+    }
+
+    let journal = open_journal(&mc_dir);
+    for issue in report.issues.iter().filter(|issue| !issue.repaired) {
+        match issue.kind {
+            FileKind::Runtime => {
+                if let Some(java) = &manifest.java_version {
+                    journal.reset_step(&format!("runtime:{}", java.component.to_string()))?;
+                }
+            }
+            kind => {
+                for version in &chain {
+                    journal.reset_step(&format!("{}:{}", version.id, kind.get_step()))?;
+                }
+            }
+        }
+    }
+    journal.save()?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{fixture_context, with_context};
+    use crate::install::install_minecraft_version;
+    use crate::testing::{version_json, write_version};
+
+    #[tokio::test]
+    async fn test_verify_and_repair_corrupt_files() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-verify-{}", uuid::Uuid::new_v4()));
+        let reporter = |_: Event| {};
+        let cancel = CancellationToken::new();
+        with_context(fixture_context("install"), async {
+            install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &cancel)
+                .await
+                .unwrap();
+
+            let report = verify_version("fixture-1.0", mc_dir.clone(), &reporter, &cancel, false)
+                .await
+                .unwrap();
+            assert_eq!(report.checked, 4);
+            assert!(report.is_ok());
+
+            let library = mc_dir
+                .join("libraries")
+                .join("org")
+                .join("example")
+                .join("demo")
+                .join("1.0")
+                .join("demo-1.0.jar");
+            let asset = mc_dir
+                .join("assets")
+                .join("objects")
+                .join("3a")
+                .join("3a1d589e96137edd0616bcf57c0ebfe1ef2c76b2");
+            let good = std::fs::read(&library).unwrap();
+            let mut bad = good.clone();
+            bad[0] ^= 0xff;
+            std::fs::write(&library, &bad).unwrap();
+            std::fs::remove_file(&asset).unwrap();
+
+            let report = verify_version("fixture-1.0", mc_dir.clone(), &reporter, &cancel, false)
+                .await
+                .unwrap();
+            assert!(!report.is_ok());
+            assert_eq!(report.issues.len(), 2);
+            assert!(report
+                .issues
+                .iter()
+                .any(|issue| issue.kind == FileKind::Library
+                    && matches!(issue.problem, FileProblem::HashMismatch { .. })));
+            assert!(report.issues.iter().any(
+                |issue| issue.kind == FileKind::Asset && issue.problem == FileProblem::Missing
+            ));
+            // the next install checks those steps again
+            let journal = open_journal(&mc_dir);
+            assert!(!journal.is_step_complete("fixture-1.0:libraries"));
+            assert!(!journal.is_step_complete("fixture-1.0:assets"));
+            assert!(journal.is_step_complete("fixture-1.0:client"));
+
+            let report = verify_version("fixture-1.0", mc_dir.clone(), &reporter, &cancel, true)
+                .await
+                .unwrap();
+            assert_eq!(report.issues.len(), 2);
+            assert!(report.is_ok());
+            assert_eq!(std::fs::read(&library).unwrap(), good);
+            assert!(asset.is_file());
+        })
+        .await;
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+
+    #[tokio::test]
+    async fn test_verify_resets_the_steps_of_an_inheriting_version() {
+        let mc_dir = std::env::temp_dir().join(format!("mc-verify-{}", uuid::Uuid::new_v4()));
+        let reporter = |_: Event| {};
+        let cancel = CancellationToken::new();
+        with_context(fixture_context("install"), async {
+            install_minecraft_version("fixture-1.0".into(), mc_dir.clone(), &reporter, &cancel)
+                .await
+                .unwrap();
+            write_version(
+                &mc_dir,
+                version_json(
+                    "fixture-loader",
+                    "org.example.Loader",
+                    &[],
+                    Some("fixture-1.0"),
+                ),
+            );
+            install_minecraft_version("fixture-loader".into(), mc_dir.clone(), &reporter, &cancel)
+                .await
+                .unwrap();
+            let journal = open_journal(&mc_dir);
+            assert!(journal.is_step_complete("fixture-loader:libraries"));
+
+            let library = mc_dir.join("libraries/org/example/demo/1.0/demo-1.0.jar");
+            std::fs::remove_file(&library).unwrap();
+            let report =
+                verify_version("fixture-loader", mc_dir.clone(), &reporter, &cancel, false)
+                    .await
+                    .unwrap();
+            assert_eq!(report.issues.len(), 1);
+            assert_eq!(report.issues[0].kind, FileKind::Library);
+
+            // the install of either version puts the library back
+            assert!(!journal.is_step_complete("fixture-loader:libraries"));
+            assert!(!journal.is_step_complete("fixture-1.0:libraries"));
+            assert!(journal.is_step_complete("fixture-loader:client"));
+
+            install_minecraft_version("fixture-loader".into(), mc_dir.clone(), &reporter, &cancel)
+                .await
+                .unwrap();
+            assert!(library.is_file());
+        })
+        .await;
+
+        let _ = std::fs::remove_dir_all(mc_dir);
+    }
+}
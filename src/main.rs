@@ -1,11 +1,23 @@
 use std::fs;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::{fs::File, io::Cursor};
 
 use mc_laucher_lib_rs::{
-    client::ClientBuilder,
-    json::client::{InstallManifest, Loader},
+    cancel::CancellationToken,
+    client::{ClientBuilder, InstallOptions},
+    expections::LauncherLibError,
+    json::{
+        authentication_microsoft::Account,
+        client::{InstallManifest, Loader},
+        install::Event,
+    },
+    login::{get_auth_code, login_microsoft, ms_login_url},
+    process::GameEvent,
+    progress::ProgressTracker,
+    support::SupportBundle,
+    utils::get_minecraft_directory,
 };
 use quartz_nbt::io::{read_nbt, write_nbt, Flavor};
 use quartz_nbt::serde::{deserialize, serialize};
@@ -15,18 +27,48 @@ use serde::{Deserialize, Serialize};
 
 const TMP_MOD_DOWNLOAD_DIR: &str = "Downloads\\";
 const GAME_VERSION: &str = "1.18.2";
+const FORGE_LOADER_VERSION: &str = "40.1.73";
 const FORGE_VERSION: &str = "1.18.2-forge-40.1.73";
 const MC_SERVER_DAT_PATH: &str = ".minecraft\\servers.dat";
 const MC_LAUNCHER_PROFILE_PATH: &str = ".minecraft\\launcher_profiles.json";
+const INSTALLER_LOG_PATH: &str = ".minecraft\\seabreyh-installer.log";
 const LAUNCH_PROFILE_NAME: &str = "Seabreyh Mods";
 
 const SERVER_NAME: &str = "Seabreyh MC Server";
 const SERVER_IP: &str = "seabreyh.ml";
 
+/// Passing this launches the game into the server once the install is done
+const PLAY_ARG: &str = "--play";
+/// Azure app id used to sign in with Microsoft, without it the game is started offline
+const CLIENT_ID_ENV: &str = "CLIENT_ID";
+const MS_REDIRECT_URI: &str = "https://login.microsoftonline.com/common/oauth2/nativeclient";
+/// Passing this writes a zip with everything a support ticket needs instead of installing
+const SUPPORT_ARG: &str = "--support";
+const SUPPORT_BUNDLE_NAME: &str = "seabreyh-support.zip";
+
+static INSTALLER_LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Starts a fresh installer log, which support bundles pick up
+fn open_installer_log(roaming_dir: &Path) {
+    let path = format!("{}\\{}", roaming_dir.display(), INSTALLER_LOG_PATH);
+    if let Ok(file) = File::create(path) {
+        let _ = INSTALLER_LOG.set(Mutex::new(file));
+    }
+}
+
+fn log(message: &str) {
+    if let Some(file) = INSTALLER_LOG.get() {
+        let _ = writeln!(file.lock().unwrap(), "{}", message);
+    }
+}
+
 async fn run_install(user_path: PathBuf, roaming_path: PathBuf) {
+    open_installer_log(&roaming_path);
+    log(&format!("Installing {}", FORGE_VERSION));
     add_server_to_client(roaming_path.clone());
     install_forge_client_and_mods(user_path, FORGE_VERSION).await;
     set_launcher_profile(roaming_path);
+    log("Install complete");
     println!("Install complete! You can close this window...");
 }
 
@@ -35,31 +77,173 @@ pub async fn main() {
     let user_dir = dirs::home_dir().unwrap();
     let roaming_dir = dirs::config_dir().unwrap();
 
+    if std::env::args().any(|arg| arg == SUPPORT_ARG) {
+        write_support_bundle(user_dir, roaming_dir).await;
+        return;
+    }
+
     run_install(user_dir, roaming_dir).await;
 
-    loop {}
+    if std::env::args().any(|arg| arg == PLAY_ARG) {
+        launch_into_server().await;
+    }
+
+    std::future::pending::<()>().await;
+}
+
+async fn login(client_id: String) -> Account {
+    println!(
+        "Sign in and paste the address you are sent to:\n{}",
+        ms_login_url(client_id.clone(), MS_REDIRECT_URI.into())
+    );
+    let mut redirect = String::new();
+    std::io::stdin().read_line(&mut redirect).unwrap();
+    let auth_code = get_auth_code(redirect.trim().into()).expect("No auth code in the address");
+
+    match login_microsoft(client_id, MS_REDIRECT_URI.into(), auth_code).await {
+        Ok(account) => account,
+        Err(e) => panic!("Error signing in: {}", e),
+    }
+}
+
+/// Starts the pack and joins the Seabreyh server straight away
+async fn launch_into_server() {
+    let mut builder = ClientBuilder::new(None).unwrap();
+    match std::env::var(CLIENT_ID_ENV) {
+        Ok(client_id) => {
+            let account = login(client_id.clone()).await;
+            builder.set_client_id(client_id).as_msa_user(account);
+        }
+        Err(_) => {
+            println!("{} is not set, starting offline", CLIENT_ID_ENV);
+            builder.as_dev_user();
+        }
+    }
+
+    let mut client = builder
+        .set_minecraft(
+            GAME_VERSION.into(),
+            Some(Loader::Forge),
+            Some(FORGE_LOADER_VERSION.into()),
+        )
+        .unwrap()
+        .join_server(SERVER_IP.into(), None)
+        .build()
+        .unwrap();
+
+    if let Err(e) = client.start().await {
+        panic!("Error launching minecraft: {}", e);
+    }
+    println!("Joining {}...", SERVER_NAME);
+
+    // the game's output is piped to us, so keep reading it until it closes
+    while let Some(event) = client.next_event().await {
+        match event {
+            GameEvent::Stdout(line) | GameEvent::Stderr(line) => println!("{}", line),
+            GameEvent::Log(record) => println!(
+                "[{}/{}] [{}]: {}",
+                record.thread, record.level, record.logger, record.message
+            ),
+            GameEvent::Exited(code) => {
+                let message = match code {
+                    Some(0) => "Minecraft closed".to_string(),
+                    Some(code) => format!("Minecraft exited with code {}", code),
+                    None => "Minecraft was stopped".to_string(),
+                };
+                log(&message);
+                println!("{}", message);
+            }
+        }
+    }
+}
+
+/// Collects the system info, mods, logs and crash report into a zip in the home directory
+async fn write_support_bundle(user_path: PathBuf, roaming_path: PathBuf) {
+    let mc_dir = get_minecraft_directory().unwrap();
+    let output = user_path.join(SUPPORT_BUNDLE_NAME);
+    match SupportBundle::new(mc_dir)
+        .installer_log(PathBuf::from(format!(
+            "{}\\{}",
+            roaming_path.display(),
+            INSTALLER_LOG_PATH
+        )))
+        .launcher_profiles(PathBuf::from(format!(
+            "{}\\{}",
+            roaming_path.display(),
+            MC_LAUNCHER_PROFILE_PATH
+        )))
+        .write(&output)
+        .await
+    {
+        Ok(path) => println!("Wrote {}, attach it to your support ticket", path.display()),
+        Err(e) => panic!("Error writing the support bundle: {}", e),
+    }
+}
+
+/// Redraws the single progress line when what it shows has changed
+fn render_progress(progress: &Mutex<(ProgressTracker, String)>, event: Option<Event>) {
+    const STATUS_WIDTH: usize = 60;
+
+    let mut progress = progress.lock().unwrap();
+    let (tracker, last_line) = &mut *progress;
+    match &event {
+        Some(Event::Status(message)) | Some(Event::Error(message)) => log(message),
+        Some(Event::Phase { name, .. }) => log(&format!("Phase {}", name)),
+        _ => {}
+    }
+    match event {
+        Some(event) => tracker.update(&event),
+        None => tracker.finish(),
+    }
+
+    let status: String = tracker.get_status().chars().take(STATUS_WIDTH).collect();
+    let line = format!("{} {:<width$}", tracker, status, width = STATUS_WIDTH);
+    if line != *last_line {
+        print!("\r{}", line);
+        std::io::stdout().flush().unwrap();
+        *last_line = line;
+    }
 }
 
 async fn install_forge_client_and_mods(user_path: PathBuf, desired_forge_version: &str) {
+    let progress = Mutex::new((ProgressTracker::for_loader(&Loader::Forge), String::new()));
+
+    let cancel = CancellationToken::new();
+    let on_ctrl_c = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_ctrl_c.cancel();
+        }
+    });
+
     if let Err(e) = ClientBuilder::install(
-        InstallManifest::new(GAME_VERSION.into(), Loader::Forge).modloader_version("40.1.73"),
-        None,
-        |event| {
-            println!("{}", event);
+        InstallManifest::new(GAME_VERSION.into(), Loader::Forge)
+            .modloader_version(FORGE_LOADER_VERSION),
+        InstallOptions {
+            desired_forge_version: Some(desired_forge_version.to_string()),
+            temp_path: Some(PathBuf::from(format!(
+                "{}\\{}",
+                user_path.display(),
+                TMP_MOD_DOWNLOAD_DIR
+            ))),
+            ..Default::default()
         },
-        Some(desired_forge_version),
-        Some(PathBuf::from(format!(
-            "{}\\{}",
-            user_path.display(),
-            TMP_MOD_DOWNLOAD_DIR
-        ))),
-        None,
-        None,
+        &|event: Event| render_progress(&progress, Some(event)),
+        &cancel,
     )
     .await
     {
+        println!();
+        if let LauncherLibError::Cancelled = e {
+            log("Install cancelled");
+            println!("Install cancelled");
+            std::process::exit(1);
+        }
+        log(&format!("Install failed: {}", e));
         panic!("Error during forge installation task: {}", e);
     }
+    render_progress(&progress, None);
+    println!();
 }
 
 fn add_server_to_client(roaming_dir: PathBuf) {
@@ -72,7 +256,7 @@ fn add_server_to_client(roaming_dir: PathBuf) {
         };
         let metadata = fs::metadata(filename).expect("unable to read metadata");
         let mut buffer = vec![0; metadata.len() as usize];
-        f_open.read(&mut buffer).expect("buffer overflow");
+        f_open.read_exact(&mut buffer).expect("buffer overflow");
 
         buffer
     }
@@ -116,11 +300,7 @@ fn add_server_to_client(roaming_dir: PathBuf) {
         accept_textures: None,
     };
     if let Some(mut tmp) = servers_dat.servers {
-        if tmp
-            .iter()
-            .find(|s| s.name == SERVER_NAME.to_string())
-            .is_none()
-        {
+        if tmp.iter().find(|s| s.name == SERVER_NAME).is_none() {
             tmp.push(server_entry);
         }
         servers_dat.servers = Some(tmp);